@@ -0,0 +1,379 @@
+// The `Configuration` of `parse_wiki_text` lists the extension tags, magic
+// words, protocols and so on of a particular wiki. `SiteConfiguration` reads
+// these lists from the JSON that the MediaWiki API returns for
+// `api.php?action=query&meta=siteinfo&siprop=general|namespaces|namespacealiases|extensiontags|magicwords|protocols`
+// and falls back to a snapshot of the English Wiktionary's configuration for
+// any that are missing.
+
+use serde::Deserialize;
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::{Configuration, ConfigurationSource, Namespace, NamespaceTable};
+
+// Created using https://github.com/portstrom/fetch_mediawiki_configuration
+pub const WIKTIONARY_CONFIGURATION_SOURCE: ConfigurationSource<'static> =
+    ConfigurationSource {
+        category_namespaces: &["cat", "category"],
+        extension_tags: &[
+            "categorytree",
+            "ce",
+            "charinsert",
+            "chem",
+            "dynamicpagelist",
+            "gallery",
+            "graph",
+            "hiero",
+            "imagemap",
+            "indicator",
+            "inputbox",
+            "mapframe",
+            "maplink",
+            "math",
+            "nowiki",
+            "poem",
+            "pre",
+            "ref",
+            "references",
+            "score",
+            "section",
+            "source",
+            "syntaxhighlight",
+            "talkpage",
+            "templatedata",
+            "templatestyles",
+            "thread",
+            "timeline",
+        ],
+        file_namespaces: &["file", "image"],
+        link_trail: "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz",
+        magic_words: &[
+            "DISAMBIG",
+            "EXPECTUNUSEDCATEGORY",
+            "FORCETOC",
+            "HIDDENCAT",
+            "INDEX",
+            "NEWSECTIONLINK",
+            "NOCC",
+            "NOCOLLABORATIONHUBTOC",
+            "NOCONTENTCONVERT",
+            "NOEDITSECTION",
+            "NOGALLERY",
+            "NOGLOBAL",
+            "NOINDEX",
+            "NONEWSECTIONLINK",
+            "NOTC",
+            "NOTITLECONVERT",
+            "NOTOC",
+            "STATICREDIRECT",
+            "TOC",
+        ],
+        protocols: &[
+            "//",
+            "bitcoin:",
+            "ftp://",
+            "ftps://",
+            "geo:",
+            "git://",
+            "gopher://",
+            "http://",
+            "https://",
+            "irc://",
+            "ircs://",
+            "magnet:",
+            "mailto:",
+            "mms://",
+            "news:",
+            "nntp://",
+            "redis://",
+            "sftp://",
+            "sip:",
+            "sips:",
+            "sms:",
+            "ssh://",
+            "svn://",
+            "tel:",
+            "telnet://",
+            "urn:",
+            "worldwind://",
+            "xmpp:",
+        ],
+        redirect_magic_words: &["REDIRECT"],
+    };
+
+pub fn wiktionary_configuration() -> Configuration {
+    Configuration::new(&WIKTIONARY_CONFIGURATION_SOURCE)
+}
+
+#[derive(Debug)]
+pub enum ConfigurationError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+    LinkTrail(String),
+}
+
+impl From<io::Error> for ConfigurationError {
+    fn from(e: io::Error) -> Self {
+        ConfigurationError::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigurationError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigurationError::JsonError(e)
+    }
+}
+
+impl Display for ConfigurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigurationError::IoError(e) => {
+                write!(f, "error reading siteinfo: {}", e)
+            }
+            ConfigurationError::JsonError(e) => {
+                write!(f, "error parsing siteinfo JSON: {}", e)
+            }
+            ConfigurationError::LinkTrail(link_trail) => {
+                write!(f, "unsupported link trail regex: {}", link_trail)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigurationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigurationError::IoError(e) => Some(e),
+            ConfigurationError::JsonError(e) => Some(e),
+            ConfigurationError::LinkTrail(_) => None,
+        }
+    }
+}
+
+/// An owned version of `ConfigurationSource`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SiteConfiguration {
+    pub category_namespaces: Vec<String>,
+    pub extension_tags: Vec<String>,
+    pub file_namespaces: Vec<String>,
+    pub link_trail: String,
+    pub magic_words: Vec<String>,
+    pub protocols: Vec<String>,
+    pub redirect_magic_words: Vec<String>,
+}
+
+impl Default for SiteConfiguration {
+    fn default() -> Self {
+        fn to_vec(strs: &[&str]) -> Vec<String> {
+            strs.iter().map(|s| s.to_string()).collect()
+        }
+
+        let source = &WIKTIONARY_CONFIGURATION_SOURCE;
+        Self {
+            category_namespaces: to_vec(source.category_namespaces),
+            extension_tags: to_vec(source.extension_tags),
+            file_namespaces: to_vec(source.file_namespaces),
+            link_trail: source.link_trail.to_string(),
+            magic_words: to_vec(source.magic_words),
+            protocols: to_vec(source.protocols),
+            redirect_magic_words: to_vec(source.redirect_magic_words),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SiteinfoJson {
+    query: SiteinfoQuery,
+}
+
+#[derive(Deserialize)]
+struct SiteinfoQuery {
+    general: Option<General>,
+    namespaces: Option<serde::de::IgnoredAny>,
+    extensiontags: Option<Vec<String>>,
+    magicwords: Option<Vec<MagicWord>>,
+    protocols: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct General {
+    linktrail: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MagicWord {
+    name: String,
+    aliases: Vec<String>,
+}
+
+impl SiteConfiguration {
+    /// Reads siteinfo JSON. Lists that are missing from it are taken from
+    /// the snapshot of the English Wiktionary's configuration.
+    pub fn from_siteinfo_json<R: Read>(
+        mut reader: R,
+    ) -> Result<Self, ConfigurationError> {
+        let mut json = String::new();
+        reader.read_to_string(&mut json)?;
+        let siteinfo: SiteinfoJson = serde_json::from_str(&json)?;
+        let mut configuration = Self::default();
+        let SiteinfoQuery {
+            general,
+            namespaces,
+            extensiontags,
+            magicwords,
+            protocols,
+        } = siteinfo.query;
+
+        if let Some(link_trail) = general.and_then(|g| g.linktrail) {
+            configuration.link_trail = parse_link_trail(&link_trail)
+                .ok_or(ConfigurationError::LinkTrail(link_trail))?;
+        }
+        if namespaces.is_some() {
+            let table = NamespaceTable::from_siteinfo_str(&json)?;
+            configuration.category_namespaces =
+                table.names(Namespace::Category).map(String::from).collect();
+            configuration.file_namespaces =
+                table.names(Namespace::File).map(String::from).collect();
+        }
+        if let Some(tags) = extensiontags {
+            configuration.extension_tags = tags
+                .iter()
+                .map(|tag| {
+                    tag.trim_start_matches('<').trim_end_matches('>').into()
+                })
+                .collect();
+        }
+        if let Some(magic_words) = magicwords {
+            let mut switches = BTreeSet::new();
+            let mut redirects = BTreeSet::new();
+            for MagicWord { name, aliases } in magic_words {
+                for alias in aliases {
+                    if name == "redirect" {
+                        redirects.insert(alias.trim_start_matches('#').into());
+                    } else if alias.len() > 4
+                        && alias.starts_with("__")
+                        && alias.ends_with("__")
+                    {
+                        switches.insert(alias[2..alias.len() - 2].into());
+                    }
+                }
+            }
+            configuration.magic_words = switches.into_iter().collect();
+            configuration.redirect_magic_words =
+                redirects.into_iter().collect();
+        }
+        if let Some(protocols) = protocols {
+            configuration.protocols = protocols;
+        }
+        Ok(configuration)
+    }
+
+    pub fn configuration(&self) -> Configuration {
+        fn to_strs(strings: &[String]) -> Vec<&str> {
+            strings.iter().map(String::as_str).collect()
+        }
+
+        Configuration::new(&ConfigurationSource {
+            category_namespaces: &to_strs(&self.category_namespaces),
+            extension_tags: &to_strs(&self.extension_tags),
+            file_namespaces: &to_strs(&self.file_namespaces),
+            link_trail: &self.link_trail,
+            magic_words: &to_strs(&self.magic_words),
+            protocols: &to_strs(&self.protocols),
+            redirect_magic_words: &to_strs(&self.redirect_magic_words),
+        })
+    }
+}
+
+/// Returns the `Configuration` described by the siteinfo JSON file at
+/// `siteinfo_path` if there is one, or else the snapshot of the English
+/// Wiktionary's configuration.
+pub fn load_configuration<P: AsRef<Path>>(
+    siteinfo_path: Option<P>,
+) -> Result<Configuration, ConfigurationError> {
+    match siteinfo_path {
+        Some(path) => {
+            let file = BufReader::new(File::open(path)?);
+            Ok(SiteConfiguration::from_siteinfo_json(file)?.configuration())
+        }
+        None => Ok(wiktionary_configuration()),
+    }
+}
+
+// Converts the character class of a link trail regex such as
+// `/^([a-z]+)(.*)$/sD` into the list of characters that it matches.
+fn parse_link_trail(regex: &str) -> Option<String> {
+    let start = regex.find("^([")? + "^([".len();
+    let end = start + regex[start..].find("]+)")?;
+    let mut chars = regex[start..end].chars().peekable();
+    let mut link_trail = String::new();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' { chars.next()? } else { c };
+        if chars.peek() == Some(&'-') {
+            chars.next();
+            match chars.next() {
+                Some(last) => link_trail.extend(c..=last),
+                None => {
+                    link_trail.push(c);
+                    link_trail.push('-');
+                }
+            }
+        } else {
+            link_trail.push(c);
+        }
+    }
+    Some(link_trail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_link_trail, SiteConfiguration};
+
+    #[test]
+    fn link_trail() {
+        assert_eq!(
+            parse_link_trail("/^([a-z]+)(.*)$/sD").as_deref(),
+            Some("abcdefghijklmnopqrstuvwxyz")
+        );
+        assert_eq!(
+            parse_link_trail("/^([a-cäö\\-]+)(.*)$/sDu").as_deref(),
+            Some("abcäö-")
+        );
+        assert_eq!(parse_link_trail("/^()(.*)$/sD"), None);
+    }
+
+    #[test]
+    fn siteinfo_json() {
+        let json = r##"{"query": {
+            "general": {"linktrail": "/^([a-c]+)(.*)$/sD"},
+            "namespaces": {
+                "6": {"id": 6, "case": "first-letter", "*": "File",
+                      "canonical": "File"},
+                "14": {"id": 14, "case": "first-letter", "*": "Category",
+                       "canonical": "Category"}
+            },
+            "namespacealiases": [{"id": 14, "*": "CAT"}],
+            "extensiontags": ["<pre>", "<ref>"],
+            "magicwords": [
+                {"name": "notoc", "aliases": ["__NOTOC__"]},
+                {"name": "redirect", "aliases": ["#REDIRECT"]},
+                {"name": "pagename", "aliases": ["PAGENAME"]}
+            ]
+        }}"##;
+        let configuration =
+            SiteConfiguration::from_siteinfo_json(json.as_bytes()).unwrap();
+        let defaults = SiteConfiguration::default();
+        assert_eq!(configuration.link_trail, "abc");
+        assert_eq!(configuration.category_namespaces, ["cat", "category"]);
+        assert_eq!(configuration.file_namespaces, ["file", "image"]);
+        assert_eq!(configuration.extension_tags, ["pre", "ref"]);
+        assert_eq!(configuration.magic_words, ["NOTOC"]);
+        assert_eq!(configuration.redirect_magic_words, ["REDIRECT"]);
+        assert_eq!(configuration.protocols, defaults.protocols);
+    }
+}
@@ -0,0 +1,192 @@
+// Opening of dump files, which may be compressed with bzip2, gzip, xz or
+// zstd. The compression format is detected from the first bytes of the file
+// rather than from the extension, so that `-` can stand for standard input
+// and files with misleading names still work.
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader, Cursor, Read},
+    path::Path,
+};
+use xz2::bufread::XzDecoder;
+
+use crate::ParallelBzDecoder;
+
+pub const DEFAULT_DUMP_FILE_NAMES: &[&str] = &[
+    "pages-articles.xml",
+    "pages-meta-current.xml",
+    "pages-articles.xml.bz2",
+    "pages-meta-current.xml.bz2",
+    "pages-articles.xml.gz",
+    "pages-meta-current.xml.gz",
+    "pages-articles.xml.xz",
+    "pages-meta-current.xml.xz",
+    "pages-articles.xml.zst",
+    "pages-meta-current.xml.zst",
+];
+
+#[derive(Debug)]
+pub enum DumpFileError {
+    IoError(io::Error),
+    DefaultsNotFound,
+}
+
+impl From<io::Error> for DumpFileError {
+    fn from(e: io::Error) -> DumpFileError {
+        DumpFileError::IoError(e)
+    }
+}
+
+impl Display for DumpFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpFileError::IoError(e) => {
+                write!(f, "failed to open dump file: {}", e)
+            }
+            DumpFileError::DefaultsNotFound => write!(
+                f,
+                concat!(
+                    "no dump filepath given, and did not find any of the ",
+                    "following filenames in the current directory: {}"
+                ),
+                DEFAULT_DUMP_FILE_NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DumpFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::IoError(e) = self {
+            Some(e)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Bzip2,
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+const MAGIC_LEN: usize = 6;
+
+impl Compression {
+    /// Identifies the compression format from the first bytes of a file.
+    pub fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0x1F, 0x8B]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if magic.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens the dump at `path`, or standard input if `path` is `-`, or the first
+/// of `DEFAULT_DUMP_FILE_NAMES` in the current directory if `path` is `None`,
+/// and decompresses it with `decompress`.
+pub fn open_dump<P: AsRef<Path>>(
+    path: Option<P>,
+    jobs: usize,
+) -> Result<Box<dyn Read + Send>, DumpFileError> {
+    let file = match path {
+        Some(path) if path.as_ref() == Path::new("-") => {
+            return Ok(decompress(io::stdin(), jobs)?);
+        }
+        Some(path) => File::open(path)?,
+        None => DEFAULT_DUMP_FILE_NAMES
+            .iter()
+            .find_map(|path| File::open(path).ok())
+            .ok_or(DumpFileError::DefaultsNotFound)?,
+    };
+    Ok(decompress(file, jobs)?)
+}
+
+/// Wraps `reader` in the decoder for the compression format that its first
+/// bytes indicate. bzip2 input is decompressed on `jobs` threads if `jobs`
+/// is greater than 1. Concatenated streams are decompressed in full in every
+/// format, as multistream dumps require.
+pub fn decompress<R: Read + Send + 'static>(
+    mut reader: R,
+    jobs: usize,
+) -> io::Result<Box<dyn Read + Send>> {
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    (&mut reader)
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut magic)?;
+    let compression = Compression::detect(&magic);
+    let reader = Cursor::new(magic).chain(reader);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Bzip2 if jobs > 1 => {
+            Box::new(ParallelBzDecoder::new(reader, jobs))
+        }
+        Compression::Bzip2 => {
+            Box::new(MultiBzDecoder::new(BufReader::new(reader)))
+        }
+        Compression::Gzip => {
+            Box::new(MultiGzDecoder::new(BufReader::new(reader)))
+        }
+        Compression::Xz => {
+            Box::new(XzDecoder::new_multi_decoder(BufReader::new(reader)))
+        }
+        Compression::Zstd => Box::new(zstd::Decoder::new(reader)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, Compression};
+    use std::io::{Cursor, Read, Write};
+
+    #[test]
+    fn detect_compression() {
+        for (magic, compression) in &[
+            (&b"BZh91AY&SY"[..], Compression::Bzip2),
+            (&[0x1F, 0x8B, 0x08, 0x00][..], Compression::Gzip),
+            (&[0xFD, b'7', b'z', b'X', b'Z', 0x00][..], Compression::Xz),
+            (&[0x28, 0xB5, 0x2F, 0xFD][..], Compression::Zstd),
+            (&b"<mediawiki"[..], Compression::None),
+            (&b""[..], Compression::None),
+        ] {
+            assert_eq!(Compression::detect(magic), *compression);
+        }
+    }
+
+    #[test]
+    fn decompress_concatenated_streams() {
+        let xml = "<mediawiki>\n</mediawiki>\n";
+        let (first, second) = xml.split_at(12);
+        let mut compressed = Vec::new();
+        for part in &[first, second] {
+            let mut encoder = flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(part.as_bytes()).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        for input in [compressed, xml.as_bytes().to_vec()] {
+            let mut decompressed = String::new();
+            decompress(Cursor::new(input), 1)
+                .unwrap()
+                .read_to_string(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, xml);
+        }
+    }
+}
@@ -1,12 +1,29 @@
 pub use parse_mediawiki_dump::Error;
 pub use parse_wiki_text::{
-    self, Configuration, ConfigurationSource, Node, Parameter, Positioned,
-    Warning,
+    self, Configuration, ConfigurationSource, Node, Output, Parameter,
+    Positioned, Warning,
 };
 use std::io::{BufReader, Read};
 
-mod namespaces;
-pub use namespaces::Namespace;
+pub mod namespaces;
+pub use namespaces::{load_namespace_table, Namespace, NamespaceTable};
+
+pub mod configuration;
+pub use configuration::{
+    load_configuration, wiktionary_configuration, SiteConfiguration,
+};
+
+pub mod input;
+pub use input::open_dump;
+
+pub mod multistream;
+pub use multistream::{MultistreamIndex, MultistreamReader};
+
+pub mod parallel_bzip2;
+pub use parallel_bzip2::ParallelBzDecoder;
+
+pub mod pipeline;
+pub use pipeline::{parse_pages, print_parser_warnings};
 
 pub type DumpParser<R> = parse_mediawiki_dump::Parser<BufReader<R>, Namespace>;
 
@@ -16,94 +33,3 @@ pub fn parse<R: Read>(dump_file: R) -> DumpParser<R> {
     let reader = BufReader::new(dump_file);
     parse_mediawiki_dump::parse_with_namespace(reader)
 }
-
-// Created using https://github.com/portstrom/fetch_mediawiki_configuration
-pub fn wiktionary_configuration() -> Configuration {
-    Configuration::new(&ConfigurationSource {
-        category_namespaces: &["cat", "category"],
-        extension_tags: &[
-            "categorytree",
-            "ce",
-            "charinsert",
-            "chem",
-            "dynamicpagelist",
-            "gallery",
-            "graph",
-            "hiero",
-            "imagemap",
-            "indicator",
-            "inputbox",
-            "mapframe",
-            "maplink",
-            "math",
-            "nowiki",
-            "poem",
-            "pre",
-            "ref",
-            "references",
-            "score",
-            "section",
-            "source",
-            "syntaxhighlight",
-            "talkpage",
-            "templatedata",
-            "templatestyles",
-            "thread",
-            "timeline",
-        ],
-        file_namespaces: &["file", "image"],
-        link_trail: "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz",
-        magic_words: &[
-            "DISAMBIG",
-            "EXPECTUNUSEDCATEGORY",
-            "FORCETOC",
-            "HIDDENCAT",
-            "INDEX",
-            "NEWSECTIONLINK",
-            "NOCC",
-            "NOCOLLABORATIONHUBTOC",
-            "NOCONTENTCONVERT",
-            "NOEDITSECTION",
-            "NOGALLERY",
-            "NOGLOBAL",
-            "NOINDEX",
-            "NONEWSECTIONLINK",
-            "NOTC",
-            "NOTITLECONVERT",
-            "NOTOC",
-            "STATICREDIRECT",
-            "TOC",
-        ],
-        protocols: &[
-            "//",
-            "bitcoin:",
-            "ftp://",
-            "ftps://",
-            "geo:",
-            "git://",
-            "gopher://",
-            "http://",
-            "https://",
-            "irc://",
-            "ircs://",
-            "magnet:",
-            "mailto:",
-            "mms://",
-            "news:",
-            "nntp://",
-            "redis://",
-            "sftp://",
-            "sip:",
-            "sips:",
-            "sms:",
-            "ssh://",
-            "svn://",
-            "tel:",
-            "telnet://",
-            "urn:",
-            "worldwind://",
-            "xmpp:",
-        ],
-        redirect_magic_words: &["REDIRECT"],
-    })
-}
@@ -0,0 +1,325 @@
+// Random access to pages in `pages-articles-multistream.xml.bz2`
+// with the help of `pages-articles-multistream-index.txt.bz2`.
+//
+// The multistream dump is a concatenation of bzip2 streams. The first stream
+// contains the `<mediawiki>` start tag and `<siteinfo>`, each of the following
+// streams contains up to 100 `<page>` elements, and the last stream contains
+// the `</mediawiki>` end tag. Each line of the index has the form
+// `offset:page_id:title`, where `offset` is the byte position of the stream
+// that contains the page.
+
+use bzip2::bufread::BzDecoder;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{input, parse, Page};
+
+#[derive(Debug)]
+pub enum MultistreamError {
+    IoError(io::Error),
+    DumpParsingError(crate::Error),
+    IndexFormat { line_number: usize, line: String },
+}
+
+impl From<io::Error> for MultistreamError {
+    fn from(e: io::Error) -> Self {
+        MultistreamError::IoError(e)
+    }
+}
+
+impl From<crate::Error> for MultistreamError {
+    fn from(e: crate::Error) -> Self {
+        MultistreamError::DumpParsingError(e)
+    }
+}
+
+impl Display for MultistreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultistreamError::IoError(e) => {
+                write!(f, "error reading multistream dump or index: {}", e)
+            }
+            MultistreamError::DumpParsingError(e) => {
+                write!(f, "error parsing multistream dump: {}", e)
+            }
+            MultistreamError::IndexFormat { line_number, line } => write!(
+                f,
+                "expected offset:page_id:title in line {} of index: {}",
+                line_number, line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MultistreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MultistreamError::IoError(e) => Some(e),
+            MultistreamError::DumpParsingError(e) => Some(e),
+            MultistreamError::IndexFormat { .. } => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, MultistreamError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub offset: u64,
+    pub id: u32,
+}
+
+/// The contents of `pages-articles-multistream-index.txt`:
+/// the stream offset and page id of every title.
+#[derive(Debug, Default)]
+pub struct MultistreamIndex {
+    by_title: HashMap<String, IndexEntry>,
+    id_to_title: HashMap<u32, String>,
+}
+
+impl MultistreamIndex {
+    /// Reads the index from a decompressed reader.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut index = Self::default();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let (entry, title) = parse_index_line(&line).ok_or_else(|| {
+                MultistreamError::IndexFormat {
+                    line_number: i + 1,
+                    line: line.clone(),
+                }
+            })?;
+            index.id_to_title.insert(entry.id, title.to_string());
+            index.by_title.insert(title.to_string(), entry);
+        }
+        Ok(index)
+    }
+
+    /// Reads the index from a file, decompressing it if it is compressed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = input::decompress(File::open(path)?, 1)?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    pub fn get(&self, title: &str) -> Option<IndexEntry> {
+        self.by_title.get(title).copied()
+    }
+
+    pub fn title(&self, id: u32) -> Option<&str> {
+        self.id_to_title.get(&id).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_title.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_title.is_empty()
+    }
+}
+
+// Titles may contain colons, so only the first two are separators.
+fn parse_index_line(line: &str) -> Option<(IndexEntry, &str)> {
+    let mut parts = line.splitn(3, ':');
+    let offset = parts.next()?.parse().ok()?;
+    let id = parts.next()?.parse().ok()?;
+    let title = parts.next()?;
+    Some((IndexEntry { offset, id }, title))
+}
+
+/// Looks up single pages in a multistream dump without reading
+/// the streams that precede them.
+pub struct MultistreamReader<R: Read + Seek> {
+    dump: R,
+    index: MultistreamIndex,
+    // The first stream, which is prepended to each stream of pages
+    // so that it can be parsed as a complete dump.
+    header: Vec<u8>,
+}
+
+const DUMP_FOOTER: &[u8] = b"</mediawiki>\n";
+
+impl MultistreamReader<File> {
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(
+        dump_path: P,
+        index_path: Q,
+    ) -> Result<Self> {
+        let dump = File::open(dump_path)?;
+        let index = MultistreamIndex::open(index_path)?;
+        Self::new(dump, index)
+    }
+}
+
+impl<R: Read + Seek> MultistreamReader<R> {
+    pub fn new(mut dump: R, index: MultistreamIndex) -> Result<Self> {
+        dump.seek(SeekFrom::Start(0))?;
+        let mut header = Vec::new();
+        BzDecoder::new(BufReader::new(&mut dump)).read_to_end(&mut header)?;
+        Ok(Self {
+            dump,
+            index,
+            header,
+        })
+    }
+
+    pub fn index(&self) -> &MultistreamIndex {
+        &self.index
+    }
+
+    /// Returns the page with the given title (including namespace prefix),
+    /// or `None` if it is not in the index.
+    pub fn get_by_title(&mut self, title: &str) -> Result<Option<Page>> {
+        match self.index.get(title) {
+            Some(IndexEntry { offset, .. }) => {
+                self.find_in_stream(offset, title)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the page with the given page id,
+    /// or `None` if it is not in the index.
+    pub fn get_by_id(&mut self, id: u32) -> Result<Option<Page>> {
+        let (offset, title) = match self.index.title(id) {
+            Some(title) => {
+                (self.index.by_title[title].offset, title.to_string())
+            }
+            None => return Ok(None),
+        };
+        self.find_in_stream(offset, &title)
+    }
+
+    // The stream at `offset` between the header stream and the end tag,
+    // which together make a complete dump.
+    fn stream_xml(&mut self, offset: u64) -> Result<impl Read + '_> {
+        self.dump.seek(SeekFrom::Start(offset))?;
+        let stream = BzDecoder::new(BufReader::new(&mut self.dump));
+        Ok(Cursor::new(&self.header)
+            .chain(stream)
+            .chain(Cursor::new(DUMP_FOOTER)))
+    }
+
+    fn find_in_stream(
+        &mut self,
+        offset: u64,
+        title: &str,
+    ) -> Result<Option<Page>> {
+        for page in parse(self.stream_xml(offset)?) {
+            let page = page?;
+            if page.title == title {
+                return Ok(Some(page));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_index_line, IndexEntry, MultistreamIndex, MultistreamReader,
+    };
+    use bzip2::{write::BzEncoder, Compression};
+    use std::io::{Cursor, Read, Write};
+
+    const HEADER: &str = "<mediawiki>\n<siteinfo>\n</siteinfo>\n";
+
+    fn page(title: &str, id: u32, text: &str) -> String {
+        format!(
+            concat!(
+                "<page>\n<title>{}</title>\n<ns>0</ns>\n<id>{}</id>\n",
+                "<revision>\n<text xml:space=\"preserve\">{}</text>\n",
+                "</revision>\n</page>\n"
+            ),
+            title, id, text
+        )
+    }
+
+    // Builds a multistream dump with a stream for the header, a stream for
+    // each group of pages and a stream for the end tag, and its index.
+    fn multistream(groups: &[&[(&str, u32)]]) -> (Vec<u8>, String) {
+        let mut dump = Vec::new();
+        let mut index = String::new();
+        let add_stream = |dump: &mut Vec<u8>, xml: &str| {
+            let mut encoder = BzEncoder::new(Vec::new(), Compression::fast());
+            encoder.write_all(xml.as_bytes()).unwrap();
+            dump.extend(encoder.finish().unwrap());
+        };
+        add_stream(&mut dump, HEADER);
+        for pages in groups {
+            let offset = dump.len();
+            let mut xml = String::new();
+            for (title, id) in pages.iter() {
+                xml.push_str(&page(title, *id, &format!("text of {}", title)));
+                index.push_str(&format!("{}:{}:{}\n", offset, id, title));
+            }
+            add_stream(&mut dump, &xml);
+        }
+        add_stream(&mut dump, "</mediawiki>\n");
+        (dump, index)
+    }
+
+    #[test]
+    fn index_line() {
+        assert_eq!(
+            parse_index_line("597:6:Module:languages/canonical names"),
+            Some((
+                IndexEntry { offset: 597, id: 6 },
+                "Module:languages/canonical names"
+            ))
+        );
+        assert_eq!(parse_index_line("597:6"), None);
+        assert_eq!(parse_index_line("x:6:title"), None);
+    }
+
+    #[test]
+    fn lookup() {
+        let (dump, index) = multistream(&[
+            &[("a", 1), ("Template:b", 2)],
+            &[("c: d", 5), ("e", 8)],
+        ]);
+        let index = MultistreamIndex::from_reader(index.as_bytes()).unwrap();
+        assert_eq!(index.len(), 4);
+        let mut reader =
+            MultistreamReader::new(Cursor::new(dump), index).unwrap();
+        assert_eq!(reader.header, HEADER.as_bytes());
+
+        let offset = reader.index().get("e").unwrap().offset;
+        let mut xml = String::new();
+        reader
+            .stream_xml(offset)
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        assert_eq!(
+            xml,
+            [
+                HEADER,
+                &page("c: d", 5, "text of c: d"),
+                &page("e", 8, "text of e")
+            ]
+            .concat()
+                + "</mediawiki>\n"
+        );
+
+        let page = reader.get_by_title("c: d").unwrap().unwrap();
+        assert_eq!(
+            (page.title.as_str(), page.text.as_str()),
+            ("c: d", "text of c: d")
+        );
+        let page = reader.get_by_id(2).unwrap().unwrap();
+        assert_eq!(page.title, "Template:b");
+        let page = reader.get_by_title("a").unwrap().unwrap();
+        assert_eq!(page.text, "text of a");
+        assert!(reader.get_by_title("f").unwrap().is_none());
+        assert!(reader.get_by_id(3).unwrap().is_none());
+    }
+}
@@ -1,248 +1,625 @@
-pub use num_enum::TryFromPrimitiveError;
-use num_enum::{IntoPrimitive, TryFromPrimitive};
+// Namespaces are identified by number. Their names, aliases and case rules
+// vary between wikis and change over time, so they are looked up in a
+// `NamespaceTable`, which is loaded from siteinfo JSON
+// (`api.php?action=query&meta=siteinfo&siprop=namespaces|namespacealiases`),
+// from the `<siteinfo>` element at the start of a dump, or from the built-in
+// table for the English Wiktionary.
+
 use parse_mediawiki_dump::NamespaceId;
-use std::{convert::TryFrom, str::FromStr};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader, Cursor, Read},
+    path::Path,
+};
+
+#[derive(Copy, Clone, Eq, Debug, Hash, PartialEq, Ord, PartialOrd)]
+pub struct Namespace(pub i32);
 
-#[derive(Copy, Clone, Eq, Debug, Hash, PartialEq, TryFromPrimitive, IntoPrimitive)]
-#[repr(i32)]
+// The namespaces of the English Wiktionary, named as variants were when
+// `Namespace` was an enum.
+#[allow(non_upper_case_globals)]
 #[rustfmt::skip]
-pub enum Namespace {
-    Media                =   -2,
-    Special              =   -1,
-    Main                 =    0,
-    Talk                 =    1,
-    User                 =    2,
-    UserTalk             =    3,
-    Wiktionary           =    4,
-    WiktionaryTalk       =    5,
-    File                 =    6,
-    FileTalk             =    7,
-    MediaWiki            =    8,
-    MediaWikiTalk        =    9,
-    Template             =   10,
-    TemplateTalk         =   11,
-    Help                 =   12,
-    HelpTalk             =   13,
-    Category             =   14,
-    CategoryTalk         =   15,
-    Thread               =   90,
-    ThreadTalk           =   91,
-    Summary              =   92,
-    SummaryTalk          =   93,
-    Appendix             =  100,
-    AppendixTalk         =  101,
-    Concordance          =  102,
-    ConcordanceTalk      =  103,
-    Index                =  104,
-    IndexTalk            =  105,
-    Rhymes               =  106,
-    RhymesTalk           =  107,
-    Transwiki            =  108,
-    TranswikiTalk        =  109,
-    Thesaurus            =  110,
-    ThesaurusTalk        =  111,
-    Citations            =  114,
-    CitationsTalk        =  115,
-    SignGloss            =  116,
-    SignGlossTalk        =  117,
-    Reconstruction       =  118,
-    ReconstructionTalk   =  119,
-    Module               =  828,
-    ModuleTalk           =  829,
-    Gadget               = 2300,
-    GadgetTalk           = 2301,
-    GadgetDefinition     = 2302,
-    GadgetDefinitionTalk = 2303,
+impl Namespace {
+    pub const Media:                Namespace = Namespace(  -2);
+    pub const Special:              Namespace = Namespace(  -1);
+    pub const Main:                 Namespace = Namespace(   0);
+    pub const Talk:                 Namespace = Namespace(   1);
+    pub const User:                 Namespace = Namespace(   2);
+    pub const UserTalk:             Namespace = Namespace(   3);
+    pub const Wiktionary:           Namespace = Namespace(   4);
+    pub const WiktionaryTalk:       Namespace = Namespace(   5);
+    pub const File:                 Namespace = Namespace(   6);
+    pub const FileTalk:             Namespace = Namespace(   7);
+    pub const MediaWiki:            Namespace = Namespace(   8);
+    pub const MediaWikiTalk:        Namespace = Namespace(   9);
+    pub const Template:             Namespace = Namespace(  10);
+    pub const TemplateTalk:         Namespace = Namespace(  11);
+    pub const Help:                 Namespace = Namespace(  12);
+    pub const HelpTalk:             Namespace = Namespace(  13);
+    pub const Category:             Namespace = Namespace(  14);
+    pub const CategoryTalk:         Namespace = Namespace(  15);
+    pub const Thread:               Namespace = Namespace(  90);
+    pub const ThreadTalk:           Namespace = Namespace(  91);
+    pub const Summary:              Namespace = Namespace(  92);
+    pub const SummaryTalk:          Namespace = Namespace(  93);
+    pub const Appendix:             Namespace = Namespace( 100);
+    pub const AppendixTalk:         Namespace = Namespace( 101);
+    pub const Concordance:          Namespace = Namespace( 102);
+    pub const ConcordanceTalk:      Namespace = Namespace( 103);
+    pub const Index:                Namespace = Namespace( 104);
+    pub const IndexTalk:            Namespace = Namespace( 105);
+    pub const Rhymes:               Namespace = Namespace( 106);
+    pub const RhymesTalk:           Namespace = Namespace( 107);
+    pub const Transwiki:            Namespace = Namespace( 108);
+    pub const TranswikiTalk:        Namespace = Namespace( 109);
+    pub const Thesaurus:            Namespace = Namespace( 110);
+    pub const ThesaurusTalk:        Namespace = Namespace( 111);
+    pub const Citations:            Namespace = Namespace( 114);
+    pub const CitationsTalk:        Namespace = Namespace( 115);
+    pub const SignGloss:            Namespace = Namespace( 116);
+    pub const SignGlossTalk:        Namespace = Namespace( 117);
+    pub const Reconstruction:       Namespace = Namespace( 118);
+    pub const ReconstructionTalk:   Namespace = Namespace( 119);
+    pub const Module:               Namespace = Namespace( 828);
+    pub const ModuleTalk:           Namespace = Namespace( 829);
+    pub const Gadget:               Namespace = Namespace(2300);
+    pub const GadgetTalk:           Namespace = Namespace(2301);
+    pub const GadgetDefinition:     Namespace = Namespace(2302);
+    pub const GadgetDefinitionTalk: Namespace = Namespace(2303);
 }
 
-impl Namespace {
-    pub const MAX_LEN: usize = 22;
-
-    #[rustfmt::skip]
-    pub fn as_str(self) -> &'static str {
-        match &self {
-            Namespace::Media                => "Media",
-            Namespace::Special              => "Special",
-            Namespace::Main                 => "",
-            Namespace::Talk                 => "Talk",
-            Namespace::User                 => "User",
-            Namespace::UserTalk             => "User talk",
-            Namespace::Wiktionary           => "Wiktionary",
-            Namespace::WiktionaryTalk       => "Wiktionary talk",
-            Namespace::File                 => "File",
-            Namespace::FileTalk             => "File talk",
-            Namespace::MediaWiki            => "MediaWiki",
-            Namespace::MediaWikiTalk        => "MediaWiki talk",
-            Namespace::Template             => "Template",
-            Namespace::TemplateTalk         => "Template talk",
-            Namespace::Help                 => "Help",
-            Namespace::HelpTalk             => "Help talk",
-            Namespace::Category             => "Category",
-            Namespace::CategoryTalk         => "Category talk",
-            Namespace::Thread               => "Thread",
-            Namespace::ThreadTalk           => "Thread talk",
-            Namespace::Summary              => "Summary",
-            Namespace::SummaryTalk          => "Summary talk",
-            Namespace::Appendix             => "Appendix",
-            Namespace::AppendixTalk         => "Appendix talk",
-            Namespace::Concordance          => "Concordance",
-            Namespace::ConcordanceTalk      => "Concordance talk",
-            Namespace::Index                => "Index",
-            Namespace::IndexTalk            => "Index talk",
-            Namespace::Rhymes               => "Rhymes",
-            Namespace::RhymesTalk           => "Rhymes talk",
-            Namespace::Transwiki            => "Transwiki",
-            Namespace::TranswikiTalk        => "Transwiki talk",
-            Namespace::Thesaurus            => "Thesaurus",
-            Namespace::ThesaurusTalk        => "Thesaurus talk",
-            Namespace::Citations            => "Citations",
-            Namespace::CitationsTalk        => "Citations talk",
-            Namespace::SignGloss            => "Sign gloss",
-            Namespace::SignGlossTalk        => "Sign gloss talk",
-            Namespace::Reconstruction       => "Reconstruction",
-            Namespace::ReconstructionTalk   => "Reconstruction talk",
-            Namespace::Module               => "Module",
-            Namespace::ModuleTalk           => "Module talk",
-            Namespace::Gadget               => "Gadget",
-            Namespace::GadgetTalk           => "Gadget talk",
-            Namespace::GadgetDefinition     => "Gadget definition",
-            Namespace::GadgetDefinitionTalk => "Gadget definition talk",
-        }
+impl From<i32> for Namespace {
+    fn from(id: i32) -> Self {
+        Namespace(id)
     }
+}
 
-    pub fn normalize_name<'a>(name: &str, buffer: &'a mut [u8]) -> &'a str {
-        let normalized_name = &mut buffer[..name.len()];
-        normalized_name.copy_from_slice(name.as_bytes());
-        normalized_name[0] = normalized_name[0].to_ascii_uppercase();
-        normalized_name[1..].make_ascii_lowercase();
-        for c in normalized_name.iter_mut() {
-            if *c == b'_' {
-                *c = b' ';
+impl From<Namespace> for i32 {
+    fn from(Namespace(id): Namespace) -> Self {
+        id
+    }
+}
+
+impl From<NamespaceId> for Namespace {
+    fn from(NamespaceId(id): NamespaceId) -> Self {
+        Namespace(id)
+    }
+}
+
+#[derive(Debug)]
+pub enum NamespaceError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+    UnknownNamespace(String),
+}
+
+impl From<io::Error> for NamespaceError {
+    fn from(e: io::Error) -> Self {
+        NamespaceError::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for NamespaceError {
+    fn from(e: serde_json::Error) -> Self {
+        NamespaceError::JsonError(e)
+    }
+}
+
+impl Display for NamespaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NamespaceError::IoError(e) => {
+                write!(f, "error reading siteinfo: {}", e)
+            }
+            NamespaceError::JsonError(e) => {
+                write!(f, "error parsing siteinfo JSON: {}", e)
+            }
+            NamespaceError::UnknownNamespace(name) => {
+                write!(f, "invalid namespace: {}", name)
             }
         }
-        unsafe { std::str::from_utf8_unchecked(&*normalized_name) }
     }
 }
 
-impl FromStr for Namespace {
-    type Err = &'static str;
+impl std::error::Error for NamespaceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NamespaceError::IoError(e) => Some(e),
+            NamespaceError::JsonError(e) => Some(e),
+            NamespaceError::UnknownNamespace(_) => None,
+        }
+    }
+}
 
-    fn from_str(namespace_name: &str) -> Result<Self, Self::Err> {
-        if namespace_name.len() > Self::MAX_LEN || !namespace_name.is_ascii() {
-            return Err("invalid namespace name");
+/// Whether the first letter of titles in a namespace is capitalized.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Case {
+    FirstLetter,
+    CaseSensitive,
+}
+
+impl Case {
+    fn from_attribute(value: &str) -> Self {
+        if value == "case-sensitive" {
+            Case::CaseSensitive
+        } else {
+            Case::FirstLetter
         }
-        let mut namespace_buffer = [0u8; Self::MAX_LEN];
-        let namespace_name =
-            Self::normalize_name(namespace_name, &mut namespace_buffer);
-        #[rustfmt::skip]
-        let namespace = match namespace_name {
-            "Media"                  => Namespace::Media,
-            "Special"                => Namespace::Special,
-            "Main"                   => Namespace::Main,
-            "Talk"                   => Namespace::Talk,
-            "User"                   => Namespace::User,
-            "User talk"              => Namespace::UserTalk,
-            "Wiktionary"             => Namespace::Wiktionary,
-            "Wiktionary talk"        => Namespace::WiktionaryTalk,
-            "File"                   => Namespace::File,
-            "File talk"              => Namespace::FileTalk,
-            "Media wiki"             => Namespace::MediaWiki,
-            "Media wiki talk"        => Namespace::MediaWikiTalk,
-            "Template"               => Namespace::Template,
-            "Template talk"          => Namespace::TemplateTalk,
-            "Help"                   => Namespace::Help,
-            "Help talk"              => Namespace::HelpTalk,
-            "Category"               => Namespace::Category,
-            "Category talk"          => Namespace::CategoryTalk,
-            "Thread"                 => Namespace::Thread,
-            "Thread talk"            => Namespace::ThreadTalk,
-            "Summary"                => Namespace::Summary,
-            "Summary talk"           => Namespace::SummaryTalk,
-            "Appendix"               => Namespace::Appendix,
-            "Appendix talk"          => Namespace::AppendixTalk,
-            "Concordance"            => Namespace::Concordance,
-            "Concordance talk"       => Namespace::ConcordanceTalk,
-            "Index"                  => Namespace::Index,
-            "Index talk"             => Namespace::IndexTalk,
-            "Rhymes"                 => Namespace::Rhymes,
-            "Rhymes talk"            => Namespace::RhymesTalk,
-            "Transwiki"              => Namespace::Transwiki,
-            "Transwiki talk"         => Namespace::TranswikiTalk,
-            "Thesaurus"              => Namespace::Thesaurus,
-            "Thesaurus talk"         => Namespace::ThesaurusTalk,
-            "Citations"              => Namespace::Citations,
-            "Citations talk"         => Namespace::CitationsTalk,
-            "Sign gloss"             => Namespace::SignGloss,
-            "Sign gloss talk"        => Namespace::SignGlossTalk,
-            "Reconstruction"         => Namespace::Reconstruction,
-            "Reconstruction talk"    => Namespace::ReconstructionTalk,
-            "Module"                 => Namespace::Module,
-            "Module talk"            => Namespace::ModuleTalk,
-            "Gadget"                 => Namespace::Gadget,
-            "Gadget talk"            => Namespace::GadgetTalk,
-            "Gadget definition"      => Namespace::GadgetDefinition,
-            "Gadget definition talk" => Namespace::GadgetDefinitionTalk,
-            _                        => return Err("invalid namespace name"),
-        };
-        Ok(namespace)
     }
 }
 
-impl TryFrom<NamespaceId> for Namespace {
-    type Error = <Namespace as TryFrom<i32>>::Error;
-    fn try_from(NamespaceId(id): NamespaceId) -> Result<Self, Self::Error> {
-        Self::try_from(id)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamespaceInfo {
+    pub id: Namespace,
+    /// The local name, which is used in page titles.
+    pub name: String,
+    /// The name that the namespace has on every wiki, if it has one.
+    pub canonical: Option<String>,
+    pub case: Case,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NamespaceTable {
+    namespaces: BTreeMap<Namespace, NamespaceInfo>,
+    // Normalized names, canonical names and aliases.
+    ids: HashMap<String, Namespace>,
+}
+
+// The canonical names of the namespaces that every wiki has.
+#[rustfmt::skip]
+const CANONICAL_NAMES: &[(i32, &str)] = &[
+    (-2, "Media"),
+    (-1, "Special"),
+    ( 1, "Talk"),
+    ( 2, "User"),
+    ( 3, "User talk"),
+    ( 4, "Project"),
+    ( 5, "Project talk"),
+    ( 6, "File"),
+    ( 7, "File talk"),
+    ( 8, "MediaWiki"),
+    ( 9, "MediaWiki talk"),
+    (10, "Template"),
+    (11, "Template talk"),
+    (12, "Help"),
+    (13, "Help talk"),
+    (14, "Category"),
+    (15, "Category talk"),
+];
+
+// Aliases that every wiki has.
+const BUILTIN_ALIASES: &[(i32, &str)] = &[(6, "Image"), (7, "Image talk")];
+
+// The namespaces of the English Wiktionary, from siteinfo.
+#[rustfmt::skip]
+const ENGLISH_WIKTIONARY_NAMESPACES: &[(i32, &str, Case)] = {
+    use Case::*;
+    &[
+        (  -2, "Media",                  FirstLetter),
+        (  -1, "Special",                FirstLetter),
+        (   0, "",                       CaseSensitive),
+        (   1, "Talk",                   CaseSensitive),
+        (   2, "User",                   FirstLetter),
+        (   3, "User talk",              FirstLetter),
+        (   4, "Wiktionary",             CaseSensitive),
+        (   5, "Wiktionary talk",        CaseSensitive),
+        (   6, "File",                   CaseSensitive),
+        (   7, "File talk",              CaseSensitive),
+        (   8, "MediaWiki",              FirstLetter),
+        (   9, "MediaWiki talk",         FirstLetter),
+        (  10, "Template",               CaseSensitive),
+        (  11, "Template talk",          CaseSensitive),
+        (  12, "Help",                   CaseSensitive),
+        (  13, "Help talk",              CaseSensitive),
+        (  14, "Category",               CaseSensitive),
+        (  15, "Category talk",          CaseSensitive),
+        (  90, "Thread",                 CaseSensitive),
+        (  91, "Thread talk",            CaseSensitive),
+        (  92, "Summary",                CaseSensitive),
+        (  93, "Summary talk",           CaseSensitive),
+        ( 100, "Appendix",               CaseSensitive),
+        ( 101, "Appendix talk",          CaseSensitive),
+        ( 102, "Concordance",            CaseSensitive),
+        ( 103, "Concordance talk",       CaseSensitive),
+        ( 104, "Index",                  CaseSensitive),
+        ( 105, "Index talk",             CaseSensitive),
+        ( 106, "Rhymes",                 CaseSensitive),
+        ( 107, "Rhymes talk",            CaseSensitive),
+        ( 108, "Transwiki",              CaseSensitive),
+        ( 109, "Transwiki talk",         CaseSensitive),
+        ( 110, "Thesaurus",              CaseSensitive),
+        ( 111, "Thesaurus talk",         CaseSensitive),
+        ( 114, "Citations",              CaseSensitive),
+        ( 115, "Citations talk",         CaseSensitive),
+        ( 116, "Sign gloss",             CaseSensitive),
+        ( 117, "Sign gloss talk",        CaseSensitive),
+        ( 118, "Reconstruction",         CaseSensitive),
+        ( 119, "Reconstruction talk",    CaseSensitive),
+        ( 828, "Module",                 CaseSensitive),
+        ( 829, "Module talk",            CaseSensitive),
+        (2300, "Gadget",                 CaseSensitive),
+        (2301, "Gadget talk",            CaseSensitive),
+        (2302, "Gadget definition",      CaseSensitive),
+        (2303, "Gadget definition talk", CaseSensitive),
+    ]
+};
+
+#[rustfmt::skip]
+const ENGLISH_WIKTIONARY_ALIASES: &[(i32, &str)] = &[
+    (  4, "WT"),
+    ( 10, "T"),
+    ( 14, "CAT"),
+    (100, "AP"),
+    (110, "WS"),
+    (110, "Wikisaurus"),
+    (111, "Wikisaurus talk"),
+    (118, "RC"),
+    (828, "MOD"),
+];
+
+#[derive(Deserialize)]
+struct SiteinfoJson {
+    query: SiteinfoQuery,
+}
+
+#[derive(Deserialize)]
+struct SiteinfoQuery {
+    namespaces: HashMap<String, NamespaceJson>,
+    #[serde(default)]
+    namespacealiases: Vec<AliasJson>,
+}
+
+// `formatversion=2` renames `*` to `name` or `alias`.
+#[derive(Deserialize)]
+struct NamespaceJson {
+    id: i32,
+    case: Case,
+    #[serde(rename = "*", alias = "name")]
+    name: String,
+    canonical: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AliasJson {
+    id: i32,
+    #[serde(rename = "*", alias = "alias")]
+    alias: String,
+}
+
+// MediaWiki compares namespace names case-insensitively
+// and treats underscores as spaces.
+fn normalize_name(name: &str) -> String {
+    name.trim_matches(|c| c == ' ' || c == '_')
+        .replace('_', " ")
+        .to_lowercase()
+}
+
+impl NamespaceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The namespaces of the English Wiktionary as of the time of writing.
+    pub fn english_wiktionary() -> Self {
+        let mut table = Self::new();
+        for &(id, name, case) in ENGLISH_WIKTIONARY_NAMESPACES {
+            table.insert(Namespace(id), name.to_string(), None, case);
+        }
+        for &(id, alias) in ENGLISH_WIKTIONARY_ALIASES {
+            table.add_alias(alias, Namespace(id));
+        }
+        table
+    }
+
+    /// Adds a namespace, along with its canonical name if it is one of the
+    /// namespaces that every wiki has and `canonical` is `None`.
+    pub fn insert(
+        &mut self,
+        id: Namespace,
+        name: String,
+        canonical: Option<String>,
+        case: Case,
+    ) {
+        let canonical = canonical.or_else(|| {
+            CANONICAL_NAMES
+                .iter()
+                .find(|(canonical_id, _)| Namespace(*canonical_id) == id)
+                .map(|(_, name)| name.to_string())
+        });
+        self.ids.insert(normalize_name(&name), id);
+        if let Some(canonical) = &canonical {
+            self.ids.insert(normalize_name(canonical), id);
+        }
+        for &(alias_id, alias) in BUILTIN_ALIASES {
+            if Namespace(alias_id) == id {
+                self.add_alias(alias, id);
+            }
+        }
+        self.namespaces.insert(
+            id,
+            NamespaceInfo {
+                id,
+                name,
+                canonical,
+                case,
+            },
+        );
+    }
+
+    pub fn add_alias(&mut self, alias: &str, id: Namespace) {
+        self.ids.insert(normalize_name(alias), id);
+    }
+
+    /// Reads the `namespaces` and `namespacealiases` properties of siteinfo
+    /// in the JSON format of the MediaWiki API.
+    pub fn from_siteinfo_json<R: Read>(
+        mut reader: R,
+    ) -> Result<Self, NamespaceError> {
+        let mut json = String::new();
+        reader.read_to_string(&mut json)?;
+        Ok(Self::from_siteinfo_str(&json)?)
+    }
+
+    pub(crate) fn from_siteinfo_str(json: &str) -> serde_json::Result<Self> {
+        let siteinfo: SiteinfoJson = serde_json::from_str(json)?;
+        let mut table = Self::new();
+        for namespace in siteinfo.query.namespaces.into_values() {
+            table.insert(
+                Namespace(namespace.id),
+                namespace.name,
+                namespace.canonical,
+                namespace.case,
+            );
+        }
+        for alias in siteinfo.query.namespacealiases {
+            table.add_alias(&alias.alias, Namespace(alias.id));
+        }
+        Ok(table)
+    }
+
+    /// Reads the `<namespaces>` element of the `<siteinfo>` of a dump.
+    /// Dumps don't list aliases, so only those that every wiki has are added.
+    pub fn from_siteinfo_xml(xml: &str) -> Option<Self> {
+        let start = xml.find("<namespaces>")?;
+        let end = start + xml[start..].find("</namespaces>")?;
+        let mut table = Self::new();
+        let mut rest = &xml[start..end];
+        while let Some(tag_start) = rest.find("<namespace ") {
+            rest = &rest[tag_start..];
+            let tag_end = rest.find('>')?;
+            let tag = &rest[..tag_end];
+            let id = get_attribute(tag, "key")?.parse().ok()?;
+            let case = get_attribute(tag, "case")
+                .map(Case::from_attribute)
+                .unwrap_or(Case::FirstLetter);
+            rest = &rest[tag_end + 1..];
+            let name = if tag.ends_with('/') {
+                String::new()
+            } else {
+                let name_end = rest.find("</namespace>")?;
+                let name = unescape_xml(&rest[..name_end]);
+                rest = &rest[name_end..];
+                name
+            };
+            table.insert(Namespace(id), name, None, case);
+        }
+        Some(table)
+    }
+
+    pub fn get(&self, id: Namespace) -> Option<&NamespaceInfo> {
+        self.namespaces.get(&id)
+    }
+
+    /// The local name of the namespace, which is empty for the main namespace.
+    pub fn name(&self, id: Namespace) -> Option<&str> {
+        self.get(id).map(|info| info.name.as_str())
+    }
+
+    /// The case rule of the namespace. MediaWiki capitalizes the first
+    /// letter of titles in unknown namespaces.
+    pub fn case(&self, id: Namespace) -> Case {
+        self.get(id).map(|info| info.case).unwrap_or(Case::FirstLetter)
+    }
+
+    /// Looks up a namespace by local name, canonical name, alias or number.
+    pub fn lookup(&self, name: &str) -> Option<Namespace> {
+        if let Ok(id) = name.trim().parse() {
+            return Some(Namespace(id));
+        }
+        self.ids.get(&normalize_name(name)).copied()
+    }
+
+    /// Looks up a namespace given on the command line, where the main
+    /// namespace, whose name is empty, can also be called `main`.
+    pub fn parse_namespace(
+        &self,
+        name: &str,
+    ) -> Result<Namespace, NamespaceError> {
+        if normalize_name(name) == "main" {
+            return Ok(Namespace::Main);
+        }
+        self.lookup(name)
+            .ok_or_else(|| NamespaceError::UnknownNamespace(name.to_string()))
+    }
+
+    /// The names, canonical name and aliases of the namespace,
+    /// in lowercase and in alphabetical order.
+    pub fn names(&self, id: Namespace) -> impl Iterator<Item = &str> {
+        let mut names: Vec<_> = self
+            .ids
+            .iter()
+            .filter(|(_, name_id)| **name_id == id)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        names.into_iter()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NamespaceInfo> {
+        self.namespaces.values()
+    }
+}
+
+fn get_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&amp;", "&")
+}
+
+// The `<siteinfo>` element of the English Wiktionary is about 5 KB.
+const MAX_SITEINFO_LEN: usize = 1 << 20;
+const SITEINFO_END: &[u8] = b"</siteinfo>";
+
+type WholeDump<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+/// Reads the namespaces from the `<siteinfo>` at the start of `dump`,
+/// and returns them along with a reader that yields all of `dump`,
+/// including the part that was read.
+pub fn read_siteinfo<R: Read>(
+    mut dump: R,
+) -> io::Result<(Option<NamespaceTable>, WholeDump<R>)> {
+    let mut header = Vec::new();
+    let mut buffer = [0; 1 << 13];
+    let end = loop {
+        let count = match dump.read(&mut buffer) {
+            Ok(count) => count,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let search_start = header.len().saturating_sub(SITEINFO_END.len());
+        header.extend_from_slice(&buffer[..count]);
+        if let Some(pos) = header[search_start..]
+            .windows(SITEINFO_END.len())
+            .position(|window| window == SITEINFO_END)
+        {
+            break Some(search_start + pos);
+        }
+        if count == 0 || header.len() > MAX_SITEINFO_LEN {
+            break None;
+        }
+    };
+    let table = end
+        .and_then(|end| std::str::from_utf8(&header[..end]).ok())
+        .and_then(NamespaceTable::from_siteinfo_xml);
+    Ok((table, Cursor::new(header).chain(dump)))
+}
+
+/// Loads the namespace table from the siteinfo JSON file at `siteinfo_path`
+/// if there is one, or else from the `<siteinfo>` of `dump`, or else uses
+/// the table of the English Wiktionary. Returns the table and a reader that
+/// yields all of `dump`.
+pub fn load_namespace_table<P, R>(
+    siteinfo_path: Option<P>,
+    dump: R,
+) -> Result<(NamespaceTable, Box<dyn Read + Send>), NamespaceError>
+where
+    P: AsRef<Path>,
+    R: Read + Send + 'static,
+{
+    if let Some(path) = siteinfo_path {
+        let file = BufReader::new(File::open(path)?);
+        let table = NamespaceTable::from_siteinfo_json(file)?;
+        Ok((table, Box::new(dump)))
+    } else {
+        let (table, dump) = read_siteinfo(dump)?;
+        let table = table.unwrap_or_else(NamespaceTable::english_wiktionary);
+        Ok((table, Box::new(dump)))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Namespace, TryFromPrimitiveError};
+    use super::{read_siteinfo, Case, Namespace, NamespaceTable};
     use parse_mediawiki_dump::NamespaceId;
-    use std::convert::TryFrom;
-    use std::str::FromStr;
+    use std::io::Read;
 
     #[test]
-    fn namespace_from_str() {
-        assert_eq!(
-            Namespace::from_str("wiktionary talk"),
-            Ok(Namespace::WiktionaryTalk)
-        );
-        assert_eq!(
-            Namespace::from_str("Wiktionary talk"),
-            Ok(Namespace::WiktionaryTalk)
-        );
-        assert_eq!(
-            Namespace::from_str("Wiktionary_talk"),
-            Ok(Namespace::WiktionaryTalk)
-        );
+    fn namespace_lookup() {
+        let table = NamespaceTable::english_wiktionary();
+        for name in &["wiktionary talk", "Wiktionary talk", "Wiktionary_talk"]
+        {
+            assert_eq!(table.lookup(name), Some(Namespace::WiktionaryTalk));
+        }
+        assert_eq!(table.lookup("WT"), Some(Namespace::Wiktionary));
+        assert_eq!(table.lookup("Project"), Some(Namespace::Wiktionary));
+        assert_eq!(table.lookup("image"), Some(Namespace::File));
+        assert_eq!(table.lookup("829"), Some(Namespace::ModuleTalk));
+        assert_eq!(table.lookup("Nonexistent"), None);
+        assert_eq!(table.lookup("Main"), None);
+        assert_eq!(table.parse_namespace("Main").unwrap(), Namespace::Main);
     }
 
     #[test]
-    fn namespace_as_str() {
-        assert_eq!(Namespace::Talk.as_str(), "Talk");
-        assert_eq!(Namespace::WiktionaryTalk.as_str(), "Wiktionary talk");
+    fn namespace_names() {
+        let table = NamespaceTable::english_wiktionary();
+        assert_eq!(table.name(Namespace::Talk), Some("Talk"));
+        assert_eq!(
+            table.name(Namespace::WiktionaryTalk),
+            Some("Wiktionary talk")
+        );
+        assert_eq!(table.name(Namespace(1000)), None);
+        assert_eq!(table.case(Namespace::Main), Case::CaseSensitive);
+        assert_eq!(table.case(Namespace::User), Case::FirstLetter);
     }
 
     #[test]
     fn namespace_numbers() {
-        assert_eq!(Namespace::try_from(828), Ok(Namespace::Module));
-        assert_eq!(Namespace::try_from(829), Ok(Namespace::ModuleTalk));
-        assert_eq!(
-            Namespace::try_from(1000),
-            Err(TryFromPrimitiveError { number: 1000i32 })
-        );
-
-        assert_eq!(i32::from(Namespace::Module), 828);
+        assert_eq!(Namespace::from(828), Namespace::Module);
         assert_eq!(i32::from(Namespace::ModuleTalk), 829);
+        assert_eq!(Namespace::from(NamespaceId(10)), Namespace::Template);
     }
 
     #[test]
-    fn namespace_try_from_namespace_id() {
-        assert_eq!(Namespace::try_from(NamespaceId(0)), Ok(Namespace::Main));
-        assert_eq!(Namespace::try_from(NamespaceId(10)), Ok(Namespace::Template));
+    fn siteinfo_json() {
+        let json = r#"{"query": {
+            "namespaces": {
+                "0": {"id": 0, "case": "case-sensitive", "*": ""},
+                "4": {"id": 4, "case": "case-sensitive", "*": "Wiktionary",
+                      "canonical": "Project"},
+                "3000": {"id": 3000, "case": "first-letter", "name": "New"}
+            },
+            "namespacealiases": [{"id": 4, "*": "WT"}]
+        }}"#;
+        let table = NamespaceTable::from_siteinfo_json(json.as_bytes())
+            .unwrap();
+        assert_eq!(table.lookup("new"), Some(Namespace(3000)));
+        assert_eq!(table.lookup("wt"), Some(Namespace::Wiktionary));
+        assert_eq!(table.lookup("project"), Some(Namespace::Wiktionary));
+        assert_eq!(table.case(Namespace(3000)), Case::FirstLetter);
     }
 
     #[test]
-    fn normalize_namespace_name() {}
+    fn siteinfo_xml() {
+        let dump = r#"<mediawiki>
+  <siteinfo>
+    <namespaces>
+      <namespace key="0" case="case-sensitive" />
+      <namespace key="2" case="first-letter">User</namespace>
+      <namespace key="3000" case="case-sensitive">A &amp; B</namespace>
+    </namespaces>
+  </siteinfo>
+  <page>"#;
+        let (table, mut reader) = read_siteinfo(dump.as_bytes()).unwrap();
+        let table = table.unwrap();
+        assert_eq!(table.name(Namespace::Main), Some(""));
+        assert_eq!(table.lookup("a & b"), Some(Namespace(3000)));
+        assert_eq!(table.case(Namespace::User), Case::FirstLetter);
+        assert_eq!(table.case(Namespace(3000)), Case::CaseSensitive);
+        let mut replayed = String::new();
+        reader.read_to_string(&mut replayed).unwrap();
+        assert_eq!(replayed, dump);
+    }
 }
@@ -0,0 +1,637 @@
+// Decompression of bzip2 files on several threads.
+//
+// A bzip2 stream consists of a header (`BZh1` to `BZh9`), a sequence of
+// blocks of at most 900 kB of input each, and an end-of-stream marker with a
+// checksum of the whole stream. Each block starts with a 48-bit magic number
+// and its own checksum and can be decompressed without the blocks before it,
+// but blocks aren't aligned to bytes. One thread finds the blocks in the
+// compressed input and groups them into batches, worker threads turn each
+// batch into a stream of its own and decompress it, and `ParallelBzDecoder`
+// returns the decompressed batches in their original order. Splitting at
+// blocks rather than at streams means that single-stream files like
+// `pages-articles.xml.bz2` are decompressed in parallel as well as
+// multistream files, whose stream boundaries are simply skipped.
+//
+// The magic numbers can also occur inside compressed data, with a
+// probability of 2^-48 at each bit. A block that was split at such a false
+// boundary fails to decompress, so a failed block is merged with the one
+// after it and tried again, and a batch that fails is merged with the next
+// one in case the block continues there. Only if that fails too is the error
+// returned. A false end-of-stream magic number at the end of a batch isn't
+// recovered from, because the data after it may already be gone.
+
+use bzip2::read::BzDecoder;
+use crossbeam::channel::{self, Receiver, Sender};
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, Read},
+    thread::{self, JoinHandle},
+};
+
+// Minimum amount of compressed data to send to a worker at once.
+const BATCH_SIZE: usize = 1 << 22;
+const READ_SIZE: usize = 1 << 20;
+const BATCHES_PER_JOB: usize = 2;
+
+const MAGIC_LEN: usize = 48;
+const MAGIC_MASK: u64 = (1 << MAGIC_LEN) - 1;
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+const END_OF_STREAM_MAGIC: u64 = 0x1772_4538_5090;
+const CHECKSUM_LEN: usize = 32;
+// The header of the streams made for batches. Level 9 allows blocks of any
+// size.
+const STREAM_HEADER: &[u8] = b"BZh9";
+
+// A batch that fails to decompress is returned with the error, so that it
+// can be merged with the next one.
+type BatchResult = Result<Vec<u8>, (io::Error, Option<Batch>)>;
+type Decompressed = (usize, BatchResult);
+
+// Compressed blocks, as ranges of bits in `data`, which starts at byte
+// `start` of the input.
+struct Batch {
+    index: usize,
+    start: usize,
+    data: Vec<u8>,
+    blocks: Vec<(usize, usize)>,
+}
+
+impl Batch {
+    // Appends the batch that comes after this one, unless there is data
+    // missing between them.
+    fn append(mut self, next: Batch) -> Option<Batch> {
+        if next.start > self.start + self.data.len() {
+            return None;
+        }
+        let overlap = next.start - self.start;
+        self.data.truncate(overlap);
+        self.data.extend_from_slice(&next.data);
+        self.blocks.extend(
+            next.blocks
+                .into_iter()
+                .map(|(start, end)| (start + overlap * 8, end + overlap * 8)),
+        );
+        Some(self)
+    }
+}
+
+pub struct ParallelBzDecoder {
+    receiver: Receiver<Decompressed>,
+    pending: BTreeMap<usize, BatchResult>,
+    next: usize,
+    current: Cursor<Vec<u8>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ParallelBzDecoder {
+    /// Decompresses `reader` with `jobs` worker threads.
+    pub fn new<R: Read + Send + 'static>(reader: R, jobs: usize) -> Self {
+        Self::with_batch_size(reader, jobs, BATCH_SIZE)
+    }
+
+    fn with_batch_size<R: Read + Send + 'static>(
+        reader: R,
+        jobs: usize,
+        batch_size: usize,
+    ) -> Self {
+        let jobs = jobs.max(1);
+        let (batch_sender, batch_receiver) =
+            channel::bounded::<Batch>(jobs * BATCHES_PER_JOB);
+        let (output_sender, output_receiver) =
+            channel::bounded(jobs * BATCHES_PER_JOB);
+        let mut threads: Vec<_> = (0..jobs)
+            .map(|_| {
+                let batch_receiver = batch_receiver.clone();
+                let output_sender = output_sender.clone();
+                thread::spawn(move || {
+                    for batch in batch_receiver {
+                        let index = batch.index;
+                        let result = decompress_batch(batch)
+                            .map_err(|(e, batch)| (e, Some(batch)));
+                        if output_sender.send((index, result)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        threads.push(thread::spawn(move || {
+            Splitter::new(batch_size).split(
+                reader,
+                batch_sender,
+                output_sender,
+            )
+        }));
+        Self {
+            receiver: output_receiver,
+            pending: BTreeMap::new(),
+            next: 0,
+            current: Cursor::new(Vec::new()),
+            threads,
+        }
+    }
+
+    // Returns `false` when all batches have been read.
+    fn next_batch(&mut self) -> io::Result<bool> {
+        let result = match self.receive()? {
+            Some(result) => result,
+            None => return Ok(false),
+        };
+        self.next += 1;
+        let decompressed = match result {
+            Ok(decompressed) => decompressed,
+            Err((error, None)) => return Err(error),
+            Err((error, Some(batch))) => self.merge_failed(error, batch)?,
+        };
+        self.current = Cursor::new(decompressed);
+        Ok(true)
+    }
+
+    // Waits for the result of the next batch. Returns `None` if all threads
+    // have finished without sending it.
+    fn receive(&mut self) -> io::Result<Option<BatchResult>> {
+        loop {
+            if let Some(result) = self.pending.remove(&self.next) {
+                return Ok(Some(result));
+            }
+            match self.receiver.recv() {
+                Ok((i, result)) => {
+                    self.pending.insert(i, result);
+                }
+                Err(_) => {
+                    return if self.join_threads() {
+                        Ok(None)
+                    } else {
+                        Err(io::Error::other(
+                            "bzip2 decompression thread panicked",
+                        ))
+                    };
+                }
+            }
+        }
+    }
+
+    // Decompresses a failed batch together with the failed batches after
+    // it, in case a block was split between them at a false magic number.
+    // Returns the first error if that doesn't work.
+    fn merge_failed(
+        &mut self,
+        error: io::Error,
+        mut batch: Batch,
+    ) -> io::Result<Vec<u8>> {
+        loop {
+            let next = match self.receive()? {
+                Some(Err((_, Some(next)))) => next,
+                Some(result) => {
+                    self.pending.insert(self.next, result);
+                    return Err(error);
+                }
+                None => return Err(error),
+            };
+            self.next += 1;
+            batch = match batch.append(next) {
+                Some(batch) => batch,
+                None => return Err(error),
+            };
+            match decompress_batch(batch) {
+                Ok(decompressed) => return Ok(decompressed),
+                Err((_, merged)) => batch = merged,
+            }
+        }
+    }
+
+    // Waits for the threads to finish and returns whether none of them
+    // panicked.
+    fn join_threads(&mut self) -> bool {
+        let results: Vec<_> =
+            self.threads.drain(..).map(JoinHandle::join).collect();
+        results.iter().all(Result::is_ok)
+    }
+}
+
+impl Read for ParallelBzDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let count = self.current.read(buf)?;
+            if count > 0 || buf.is_empty() || !self.next_batch()? {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+impl Drop for ParallelBzDecoder {
+    // Disconnects the output channel, which makes the workers stop at their
+    // next batch and the splitting thread at the next batch it sends.
+    fn drop(&mut self) {
+        self.receiver = channel::never();
+        self.join_threads();
+    }
+}
+
+// Finds the blocks in compressed data, which is read a piece at a time.
+struct Splitter {
+    batch_size: usize,
+    // Compressed data that hasn't been sent in a batch yet.
+    buffer: Vec<u8>,
+    // The number of bytes removed from the start of `buffer`.
+    drained: usize,
+    // The number of bytes of `buffer` that have been searched for magic
+    // numbers, and the last eight of them.
+    scanned: usize,
+    window: u64,
+    // The bit position of the block that is being read, if any.
+    block_start: Option<usize>,
+    // Blocks that have ended and haven't been sent yet.
+    blocks: Vec<(usize, usize)>,
+    found_stream_end: bool,
+    batch_index: usize,
+}
+
+impl Splitter {
+    fn new(batch_size: usize) -> Self {
+        Self {
+            batch_size,
+            buffer: Vec::new(),
+            drained: 0,
+            scanned: 0,
+            window: 0,
+            block_start: None,
+            blocks: Vec::new(),
+            found_stream_end: false,
+            batch_index: 0,
+        }
+    }
+
+    fn split<R: Read>(
+        mut self,
+        mut reader: R,
+        batch_sender: Sender<Batch>,
+        output_sender: Sender<Decompressed>,
+    ) {
+        let mut read_buffer = vec![0; READ_SIZE];
+        loop {
+            let count = match reader.read(&mut read_buffer) {
+                Ok(count) => count,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let _ =
+                        output_sender.send((self.batch_index, Err((e, None))));
+                    return;
+                }
+            };
+            if count == 0 {
+                break;
+            }
+            self.buffer.extend_from_slice(&read_buffer[..count]);
+            self.scan();
+            if self.pending_len() >= self.batch_size
+                && !self.send_batch(&batch_sender)
+            {
+                return;
+            }
+        }
+        if !self.blocks.is_empty() && !self.send_batch(&batch_sender) {
+            return;
+        }
+        let error = if self.block_start.is_some() {
+            Some((io::ErrorKind::UnexpectedEof, "bzip2 stream is truncated"))
+        } else if !self.found_stream_end && !self.buffer.is_empty() {
+            Some((io::ErrorKind::InvalidData, "no bzip2 stream found"))
+        } else {
+            None
+        };
+        if let Some((kind, message)) = error {
+            let error = io::Error::new(kind, message);
+            let _ = output_sender.send((self.batch_index, Err((error, None))));
+        }
+    }
+
+    // Searches the new part of the buffer for magic numbers at every bit
+    // position, ending the current block at each of them.
+    fn scan(&mut self) {
+        while self.scanned < self.buffer.len() {
+            self.window =
+                (self.window << 8) | u64::from(self.buffer[self.scanned]);
+            self.scanned += 1;
+            for shift in (0..8).rev() {
+                let end = self.scanned * 8 - shift;
+                if end < MAGIC_LEN {
+                    continue;
+                }
+                let position = end - MAGIC_LEN;
+                let is_block = match (self.window >> shift) & MAGIC_MASK {
+                    BLOCK_MAGIC => true,
+                    END_OF_STREAM_MAGIC => false,
+                    _ => continue,
+                };
+                if let Some(start) = self.block_start.take() {
+                    self.blocks.push((start, position));
+                }
+                if is_block {
+                    self.block_start = Some(position);
+                } else {
+                    self.found_stream_end = true;
+                }
+            }
+        }
+    }
+
+    // The number of bytes taken up by the blocks that haven't been sent.
+    fn pending_len(&self) -> usize {
+        match (self.blocks.first(), self.blocks.last()) {
+            (Some((start, _)), Some((_, end))) => (end - start) / 8,
+            _ => 0,
+        }
+    }
+
+    // Sends the blocks that have ended and removes the data that won't be
+    // needed any more. Returns `false` if the workers have stopped.
+    fn send_batch(&mut self, batch_sender: &Sender<Batch>) -> bool {
+        let first_byte = match self.blocks.first() {
+            Some(&(start, _)) => start / 8,
+            None => return true,
+        };
+        let last_byte = self.blocks[self.blocks.len() - 1].1.div_ceil(8);
+        let offset = first_byte * 8;
+        let batch = Batch {
+            index: self.batch_index,
+            start: self.drained + first_byte,
+            data: self.buffer[first_byte..last_byte].to_vec(),
+            blocks: self
+                .blocks
+                .drain(..)
+                .map(|(start, end)| (start - offset, end - offset))
+                .collect(),
+        };
+        if batch_sender.send(batch).is_err() {
+            return false;
+        }
+        self.batch_index += 1;
+        // Magic numbers that haven't been found yet start at most
+        // `MAGIC_LEN + 7` bits before the end of the scanned data.
+        let keep_from = self
+            .block_start
+            .unwrap_or_else(|| (self.scanned * 8).saturating_sub(64))
+            / 8;
+        self.buffer.drain(..keep_from);
+        self.drained += keep_from;
+        self.scanned -= keep_from;
+        if let Some(start) = &mut self.block_start {
+            *start -= keep_from * 8;
+        }
+        true
+    }
+}
+
+// Decompresses the blocks of a batch. If that fails, they are decompressed
+// one at a time, and a block that fails is merged once with the one after it:
+// the rest of the block comes after a false block magic number, and after a
+// false end-of-stream one it runs until the next block. Returns the batch
+// with the error if that doesn't help, since the block may continue in the
+// next batch.
+fn decompress_batch(batch: Batch) -> Result<Vec<u8>, (io::Error, Batch)> {
+    if let Ok(decompressed) = decompress_blocks(&batch.data, &batch.blocks) {
+        return Ok(decompressed);
+    }
+    let mut decompressed = Vec::new();
+    let mut blocks = batch.blocks.clone();
+    let mut merged = false;
+    let mut i = 0;
+    while i < blocks.len() {
+        match decompress_blocks(&batch.data, &blocks[i..=i]) {
+            Ok(block) => {
+                decompressed.extend_from_slice(&block);
+                merged = false;
+                i += 1;
+            }
+            Err(error) if merged || i + 1 == blocks.len() => {
+                return Err((error, batch));
+            }
+            Err(_) => {
+                let (start, end) = blocks[i];
+                let (next_start, next_end) = blocks[i + 1];
+                if next_start == end {
+                    blocks[i] = (start, next_end);
+                    blocks.remove(i + 1);
+                } else {
+                    blocks[i] = (start, next_start);
+                }
+                merged = true;
+            }
+        }
+    }
+    Ok(decompressed)
+}
+
+// Decompresses blocks by putting them in a stream of their own. The checksum
+// of a stream combines those of its blocks.
+fn decompress_blocks(
+    data: &[u8],
+    blocks: &[(usize, usize)],
+) -> io::Result<Vec<u8>> {
+    let mut stream = BitWriter::new(STREAM_HEADER);
+    let mut checksum = 0u32;
+    for &(start, end) in blocks {
+        if end - start < MAGIC_LEN + CHECKSUM_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bzip2 block is too short",
+            ));
+        }
+        let block_checksum = read_bits(data, start + MAGIC_LEN, CHECKSUM_LEN);
+        checksum = checksum.rotate_left(1) ^ block_checksum as u32;
+        stream.copy_bits(data, start, end);
+    }
+    stream.write_bits(END_OF_STREAM_MAGIC >> 24, 24);
+    stream.write_bits(END_OF_STREAM_MAGIC & 0xFF_FFFF, 24);
+    stream.write_bits(u64::from(checksum), CHECKSUM_LEN);
+    let mut decompressed = Vec::new();
+    BzDecoder::new(stream.finish().as_slice())
+        .read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+// Reads `count` bits (at most 32) starting at bit `start`, most significant
+// bit first, as bzip2 stores them.
+fn read_bits(data: &[u8], start: usize, count: usize) -> u64 {
+    let first = start / 8;
+    let last = (start + count).div_ceil(8);
+    let value = data[first..last]
+        .iter()
+        .fold(0u64, |value, &byte| (value << 8) | u64::from(byte));
+    (value >> (last * 8 - start - count)) & ((1 << count) - 1)
+}
+
+// Writes bits to bytes, most significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u64,
+    bit_count: usize,
+}
+
+impl BitWriter {
+    fn new(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+            bits: 0,
+            bit_count: 0,
+        }
+    }
+
+    // Writes the lowest `count` bits (at most 32) of `value`.
+    fn write_bits(&mut self, value: u64, count: usize) {
+        self.bits = (self.bits << count) | (value & ((1 << count) - 1));
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bit_count -= 8;
+            self.bytes.push((self.bits >> self.bit_count) as u8);
+        }
+        self.bits &= (1 << self.bit_count) - 1;
+    }
+
+    fn copy_bits(&mut self, data: &[u8], start: usize, end: usize) {
+        let mut position = start;
+        while position < end {
+            let count = (end - position).min(32);
+            self.write_bits(read_bits(data, position, count), count);
+            position += count;
+        }
+    }
+
+    // Pads the last byte with zeros.
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.bytes.push((self.bits << (8 - self.bit_count)) as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decompress_batch, read_bits, Batch, BitWriter, ParallelBzDecoder,
+        Splitter,
+    };
+    use bzip2::{read::BzEncoder, Compression};
+    use std::io::{Cursor, ErrorKind, Read};
+
+    fn compress(data: &[u8], level: u32) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        BzEncoder::new(data, Compression::new(level))
+            .read_to_end(&mut compressed)
+            .unwrap();
+        compressed
+    }
+
+    fn decompress(
+        compressed: Vec<u8>,
+        batch_size: usize,
+    ) -> std::io::Result<String> {
+        let mut decompressed = String::new();
+        ParallelBzDecoder::with_batch_size(
+            Cursor::new(compressed),
+            4,
+            batch_size,
+        )
+        .read_to_string(&mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    // Text that compresses badly enough to fill several blocks.
+    fn pages(count: usize) -> String {
+        (0..count)
+            .map(|i| format!("<page>{}</page>\n", i * 7919 % 1_000_003))
+            .collect()
+    }
+
+    #[test]
+    fn bits() {
+        let data = [0b1010_1100, 0b0101_0011, 0xFF];
+        assert_eq!(read_bits(&data, 2, 4), 0b1011);
+        assert_eq!(read_bits(&data, 6, 5), 0b00010);
+        let mut writer = BitWriter::new(b"x");
+        writer.copy_bits(&data, 2, 13);
+        writer.write_bits(0b1, 1);
+        assert_eq!(writer.finish(), vec![b'x', 0b1011_0001, 0b0101_0000]);
+    }
+
+    #[test]
+    fn decompresses_blocks_in_order() {
+        // Level 1 makes blocks of 100 kB.
+        let text = pages(100_000);
+        for &batch_size in &[1, 100_000, 1 << 22] {
+            let compressed = compress(text.as_bytes(), 1);
+            assert_eq!(decompress(compressed, batch_size).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn decompresses_streams_in_order() {
+        let streams: Vec<String> =
+            (0..1000).map(|i| format!("<page>{}</page>\n", i)).collect();
+        let multistream: Vec<u8> = streams
+            .iter()
+            .flat_map(|s| compress(s.as_bytes(), 9))
+            .collect();
+        assert_eq!(decompress(multistream, 100).unwrap(), streams.concat());
+        assert_eq!(decompress(Vec::new(), 100).unwrap(), "");
+    }
+
+    // Makes a batch of some of the blocks in `compressed` the way the
+    // splitting thread does.
+    fn batch(compressed: &[u8], blocks: &[(usize, usize)]) -> Batch {
+        let first_byte = blocks[0].0 / 8;
+        let last_byte = blocks[blocks.len() - 1].1.div_ceil(8);
+        let offset = first_byte * 8;
+        Batch {
+            index: 0,
+            start: first_byte,
+            data: compressed[first_byte..last_byte].to_vec(),
+            blocks: blocks
+                .iter()
+                .map(|&(start, end)| (start - offset, end - offset))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn recovers_from_false_magic() {
+        let text = pages(50_000);
+        let compressed = compress(text.as_bytes(), 1);
+        let mut splitter = Splitter::new(usize::MAX);
+        splitter.buffer = compressed.clone();
+        splitter.scan();
+        // Pretend that a block magic number was found in the middle of the
+        // second block.
+        let mut blocks = splitter.blocks;
+        assert!(blocks.len() > 2);
+        let (start, end) = blocks[1];
+        let middle = (start + end) / 2;
+        blocks[1] = (start, middle);
+        blocks.insert(2, (middle, end));
+        let decompressed = decompress_batch(batch(&compressed, &blocks))
+            .unwrap_or_else(|(e, _)| panic!("{}", e));
+        assert_eq!(decompressed, text.as_bytes());
+
+        // And that the batches were split there.
+        let first = decompress_batch(batch(&compressed, &blocks[..2]));
+        let second = decompress_batch(batch(&compressed, &blocks[2..]));
+        let (first, second) = match (first, second) {
+            (Err((_, first)), Err((_, second))) => (first, second),
+            _ => panic!("split batches were decompressed"),
+        };
+        let decompressed = decompress_batch(first.append(second).unwrap())
+            .unwrap_or_else(|(e, _)| panic!("{}", e));
+        assert_eq!(decompressed, text.as_bytes());
+    }
+
+    #[test]
+    fn reports_truncation() {
+        let mut compressed = compress(pages(50_000).as_bytes(), 1);
+        compressed.truncate(compressed.len() / 2);
+        let error = decompress(compressed, 1).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
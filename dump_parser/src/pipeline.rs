@@ -0,0 +1,122 @@
+// A pipeline that parses the wikitext of pages on several threads.
+//
+// One thread reads pages from the dump, `jobs` worker threads parse the
+// wikitext and extract whatever the caller needs from the parse tree, and
+// the calling thread receives the extracted values in the order in which the
+// pages appear in the dump. Parse trees borrow from the page text, so workers
+// must turn them into owned values before sending them back.
+
+use crossbeam::channel;
+use std::collections::BTreeMap;
+
+use crate::{Configuration, Error, Output, Page, Warning};
+
+// How many pages per worker may be waiting to be parsed or merged.
+const QUEUE_SIZE_PER_JOB: usize = 16;
+
+/// Parses each page in `pages` with `configuration` and passes the page and
+/// its parser output to `process`, then passes the page and the value
+/// returned by `process` to `merge` in dump order.
+///
+/// `process` runs on `jobs` threads at once; with a `jobs` of 0 or 1,
+/// everything runs on the calling thread. Stops at the first error returned
+/// by `pages` or `merge`.
+pub fn parse_pages<I, T, E, F, G>(
+    pages: I,
+    configuration: &Configuration,
+    jobs: usize,
+    process: F,
+    mut merge: G,
+) -> Result<(), E>
+where
+    I: Iterator<Item = Result<Page, Error>> + Send,
+    T: Send,
+    E: From<Error>,
+    F: Fn(&Page, Output) -> T + Sync,
+    G: FnMut(Page, T) -> Result<(), E>,
+{
+    if jobs <= 1 {
+        for page in pages {
+            let page = page?;
+            let value = process(&page, configuration.parse(&page.text));
+            merge(page, value)?;
+        }
+        return Ok(());
+    }
+
+    crossbeam::scope(|scope| {
+        let (page_sender, page_receiver) =
+            channel::bounded(jobs * QUEUE_SIZE_PER_JOB);
+        let (output_sender, output_receiver) =
+            channel::bounded(jobs * QUEUE_SIZE_PER_JOB);
+
+        scope.spawn(move |_| {
+            for (i, page) in pages.enumerate() {
+                let is_err = page.is_err();
+                if page_sender.send((i, page)).is_err() || is_err {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..jobs {
+            let page_receiver = page_receiver.clone();
+            let output_sender = output_sender.clone();
+            let process = &process;
+            scope.spawn(move |_| {
+                for (i, page) in page_receiver {
+                    let result = page.map(|page: Page| {
+                        let value =
+                            process(&page, configuration.parse(&page.text));
+                        (page, value)
+                    });
+                    if output_sender.send((i, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(page_receiver);
+        drop(output_sender);
+
+        // Returning early drops `output_receiver`,
+        // which makes the other threads stop.
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (i, result) in output_receiver {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&next) {
+                next += 1;
+                let (page, value) = result?;
+                merge(page, value)?;
+            }
+        }
+        Ok(())
+    })
+    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+pub fn print_parser_warnings(page: &Page, warnings: &[Warning]) {
+    for warning in warnings {
+        let Warning {
+            start,
+            end,
+            message,
+        } = warning;
+        let range = 0..page.text.len();
+        let message = message.message().trim_end_matches('.');
+        if !(range.contains(start) && range.contains(end)) {
+            eprintln!("byte position {} or {} in warning {} is out of range of {:?}, size of [[{}]]",
+                start, end, message, range, &page.title);
+        } else {
+            eprintln!(
+                "{} at bytes {}..{} ({:?}) in [[{}]]",
+                &message,
+                start,
+                end,
+                &page.text[*start..*end],
+                &page.title
+            );
+        }
+    }
+}
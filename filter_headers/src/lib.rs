@@ -1,12 +1,11 @@
 use dump_parser::{
-    wiktionary_configuration as create_configuration, DumpParser,
-    Node::{self, *},
-    Namespace, Page, Positioned, Warning,
+    parse_pages, print_parser_warnings, Configuration, DumpParser, Error,
+    Namespace, Node, Page,
 };
+use parse_wiki_text_ext::headings;
 use serde::{Serialize, Serializer};
 use std::{
     collections::{HashMap, HashSet},
-    convert::TryInto,
     io::Read,
 };
 
@@ -20,7 +19,7 @@ pub struct HeaderFilterer {
 #[derive(Serialize)]
 struct Entry<'a> {
     header: &'a str,
-    titles: Vec<&'a String>,
+    titles: Vec<&'a str>,
 }
 
 impl Serialize for HeaderFilterer {
@@ -28,24 +27,11 @@ impl Serialize for HeaderFilterer {
     where
         S: Serializer,
     {
-        let mut header_to_titles: Vec<_> = self
-            .header_to_titles
-            .iter()
-            .map(|(header, titles)| {
-                let mut titles: Vec<&String> = titles.iter().collect();
-                titles.sort();
-                Entry { header, titles }
-            })
-            .collect();
-        header_to_titles.sort_by(
-            |Entry {
-                 header: header1, ..
-             },
-             Entry {
-                 header: header2, ..
-             }| { header1.cmp(header2) },
-        );
-        header_to_titles.serialize(serializer)
+        self.sorted()
+            .into_iter()
+            .map(|(header, titles)| Entry { header, titles })
+            .collect::<Vec<_>>()
+            .serialize(serializer)
     }
 }
 
@@ -61,152 +47,120 @@ impl HeaderFilterer {
         }
     }
 
-    pub fn parse<R: Read>(
+    pub fn parse<R: Read + Send>(
         &mut self,
         parser: DumpParser<R>,
+        configuration: &Configuration,
         page_limit: usize,
         namespaces: Vec<Namespace>,
+        jobs: usize,
         verbose: bool,
-    ) {
+    ) -> Result<(), Error> {
         let namespaces: HashSet<Namespace> = namespaces.into_iter().collect();
-        let parser = parser
-            .map(|result| {
-                result.unwrap_or_else(|e| {
-                    panic!("Error while parsing dump: {}", e);
-                })
-            })
-            .filter(|page| {
-                namespaces.contains(&page.namespace.try_into().unwrap())
-            })
-            .take(page_limit);
-        let configuration = create_configuration();
-        for page in parser {
-            let parser_output = configuration.parse(&page.text);
-            if verbose {
-                for warning in parser_output.warnings {
-                    let Warning {
-                        start,
-                        end,
-                        message,
-                    } = warning;
-                    let range = 0..page.text.len();
-                    let message = message.message().trim_end_matches('.');
-                    if !(range.contains(&start) && range.contains(&end)) {
-                        eprintln!("byte position {} or {} in warning {} is out of range of {:?}, size of [[{}]]",
-                            start, end, message, range, &page.title);
-                    } else {
-                        eprintln!(
-                            "{} at bytes {}..{} ({:?}) in [[{}]]",
-                            &message,
-                            start,
-                            end,
-                            &page.text[start..end],
-                            &page.title
-                        );
-                    }
+        let parser = parser.take(page_limit).filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+        let Self {
+            top_level_headers,
+            other_headers,
+            header_to_titles,
+        } = self;
+        let filter = HeaderFilter {
+            top_level_headers,
+            other_headers,
+        };
+        parse_pages(
+            parser,
+            configuration,
+            jobs,
+            |page, parser_output| {
+                if verbose {
+                    print_parser_warnings(page, &parser_output.warnings);
                 }
-            }
+                filter.headers(page, &parser_output.nodes)
+            },
+            |page, headers| {
+                for header in headers {
+                    add_title(header_to_titles, header, &page.title);
+                }
+                Ok(())
+            },
+        )
+    }
 
-            self.process_nodes(&page, &parser_output.nodes);
-        }
+    /// Returns the headers that are not in the lists of headers with the
+    /// titles of the pages they are found in, both sorted.
+    pub fn sorted(&self) -> Vec<(&str, Vec<&str>)> {
+        let mut header_to_titles: Vec<_> = self
+            .header_to_titles
+            .iter()
+            .map(|(header, titles)| {
+                let mut titles: Vec<&str> =
+                    titles.iter().map(String::as_str).collect();
+                titles.sort_unstable();
+                (header.as_str(), titles)
+            })
+            .collect();
+        header_to_titles.sort_by_key(|&(header, _)| header);
+        header_to_titles
     }
 
-    fn process_nodes(&mut self, page: &Page, nodes: &[Node]) {
-        for node in nodes {
-            match node {
-                DefinitionList { items, .. } => {
-                    for item in items {
-                        self.process_nodes(&page, &item.nodes);
-                    }
-                }
-                Heading { nodes, level, .. } => {
-                    self.process_header(&page, &nodes, *level);
-                }
-                Preformatted { nodes, .. } | Tag { nodes, .. } => {
-                    self.process_nodes(&page, &nodes);
-                }
-                Image { text, .. } | Link { text, .. } => {
-                    self.process_nodes(&page, &text);
-                }
-                OrderedList { items, .. } | UnorderedList { items, .. } => {
-                    for item in items {
-                        self.process_nodes(&page, &item.nodes);
-                    }
-                }
-                Parameter { name, default, .. } => {
-                    match default {
-                        Some(nodes) => self.process_nodes(&page, &nodes),
-                        None => {}
-                    }
-                    self.process_nodes(&page, &name);
-                }
-                Table {
-                    attributes,
-                    captions,
-                    rows,
-                    ..
-                } => {
-                    self.process_nodes(&page, &attributes);
-                    for caption in captions {
-                        if let Some(attributes) = &caption.attributes {
-                            self.process_nodes(&page, attributes)
-                        }
-                        self.process_nodes(&page, &caption.content);
-                    }
-                    for row in rows {
-                        self.process_nodes(&page, &row.attributes);
-                        for cell in &row.cells {
-                            if let Some(attributes) = &cell.attributes {
-                                self.process_nodes(&page, attributes);
-                            }
-                            self.process_nodes(&page, &cell.content);
-                        }
-                    }
-                }
-                Template {
-                    name, parameters, ..
-                } => {
-                    self.process_nodes(&page, &name);
-                    for parameter in parameters {
-                        if let Some(name) = &parameter.name {
-                            self.process_nodes(&page, name);
-                        }
-                        self.process_nodes(&page, &parameter.value);
-                    }
-                }
-                Bold { .. }
-                | BoldItalic { .. }
-                | Category { .. }
-                | CharacterEntity { .. }
-                | Comment { .. }
-                | EndTag { .. }
-                | ExternalLink { .. }
-                | HorizontalDivider { .. }
-                | Italic { .. }
-                | MagicWord { .. }
-                | ParagraphBreak { .. }
-                | Redirect { .. }
-                | StartTag { .. }
-                | Text { .. } => {}
+    /// Records the headers of a page, given as text and level,
+    /// that are not in the lists of headers.
+    pub fn add_page<'a, I>(&mut self, title: &str, headers: I)
+    where
+        I: IntoIterator<Item = (&'a str, u8)>,
+    {
+        let Self {
+            top_level_headers,
+            other_headers,
+            header_to_titles,
+        } = self;
+        let filter = HeaderFilter {
+            top_level_headers,
+            other_headers,
+        };
+        for (header, level) in headers {
+            if !filter.is_listed(header, level) {
+                add_title(header_to_titles, header.into(), title);
             }
         }
     }
+}
 
-    fn process_header(&mut self, page: &Page, nodes: &[Node], level: u8) {
-        let text = nodes
-            .get_text_from(&page.text)
-            .trim_matches(|c: char| c == ' ' || c == '\t');
-        if !match level {
-            2 => &self.top_level_headers,
-            _ => &self.other_headers,
-        }
-        .contains(text)
-        {
-            let titles = self
-                .header_to_titles
-                .entry(text.into())
-                .or_insert_with(HashSet::new);
-            titles.insert(page.title.to_string());
+fn add_title(
+    header_to_titles: &mut HashMap<String, HashSet<String>>,
+    header: String,
+    title: &str,
+) {
+    header_to_titles
+        .entry(header)
+        .or_insert_with(HashSet::new)
+        .insert(title.to_string());
+}
+
+// The part of `HeaderFilterer` that is shared with the threads
+// that parse wikitext.
+struct HeaderFilter<'a> {
+    top_level_headers: &'a HashSet<String>,
+    other_headers: &'a HashSet<String>,
+}
+
+impl<'a> HeaderFilter<'a> {
+    fn headers(&self, page: &Page, nodes: &[Node]) -> Vec<String> {
+        headings(&page.text, nodes)
+            .into_iter()
+            .filter(|&(text, level)| !self.is_listed(text, level))
+            .map(|(text, _)| text.into())
+            .collect()
+    }
+
+    fn is_listed(&self, header: &str, level: u8) -> bool {
+        match level {
+            2 => self.top_level_headers,
+            _ => self.other_headers,
         }
+        .contains(header)
     }
 }
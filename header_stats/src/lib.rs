@@ -1,18 +1,17 @@
 use dump_parser::{
-    wiktionary_configuration as create_configuration, DumpParser, Namespace,
-    Node::{self, *},
-    Page, Positioned, Warning,
+    parse_pages, print_parser_warnings, Configuration, DumpParser, Error,
+    Namespace, Node, Page,
 };
+use parse_wiki_text_ext::headings;
 use serde::{ser::Serializer, Serialize};
 use std::{
     collections::{HashMap, HashSet},
-    convert::TryInto,
     default::Default,
     io::Read,
     ops::{Index, IndexMut},
 };
 
-type HeaderLevel = u8;
+pub type HeaderLevel = u8;
 
 const MAX_HEADER_LEVEL: usize = 6;
 const MIN_HEADER_LEVEL: usize = 1;
@@ -25,6 +24,15 @@ impl HeaderCounts {
     fn new() -> Self {
         HeaderCounts([0usize; HEADER_LEVEL_ARRAY_SIZE])
     }
+
+    /// Returns each header level with the number of times the header
+    /// occurs at it.
+    pub fn iter(&self) -> impl Iterator<Item = (HeaderLevel, usize)> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| ((i + MIN_HEADER_LEVEL) as HeaderLevel, count))
+    }
 }
 
 impl Index<HeaderLevel> for HeaderCounts {
@@ -52,20 +60,11 @@ impl Serialize for HeaderStats {
             counts: &'a HeaderCounts,
         }
 
-        let mut header_counts: Vec<_> = self
-            .header_counts
-            .iter()
+        self.sorted()
+            .into_iter()
             .map(|(header, counts)| HeaderStat { header, counts })
-            .collect();
-        header_counts.sort_by(
-            |HeaderStat {
-                 header: header1, ..
-             },
-             HeaderStat {
-                 header: header2, ..
-             }| { header1.cmp(header2) },
-        );
-        header_counts.serialize(serializer)
+            .collect::<Vec<_>>()
+            .serialize(serializer)
     }
 }
 
@@ -80,146 +79,66 @@ impl HeaderStats {
         Default::default()
     }
 
-    pub fn parse<R: Read>(
+    pub fn parse<R: Read + Send>(
         &mut self,
         parser: DumpParser<R>,
+        configuration: &Configuration,
         page_limit: usize,
         namespaces: Vec<Namespace>,
+        jobs: usize,
         verbose: bool,
-    ) {
+    ) -> Result<(), Error> {
         let namespaces: HashSet<Namespace> = namespaces.into_iter().collect();
-        let parser = parser
-            .map(|result| {
-                result.unwrap_or_else(|e| {
-                    panic!("Error while parsing dump: {}", e);
-                })
-            })
-            .filter(|page| {
-                namespaces.contains(&page.namespace.try_into().unwrap())
-            })
-            .take(page_limit);
-        let configuration = create_configuration();
-        for page in parser {
-            // eprintln!("title: [[{}]]", &page.title);
-            let parser_output = configuration.parse(&page.text);
-            if verbose {
-                for warning in parser_output.warnings {
-                    let Warning {
-                        start,
-                        end,
-                        message,
-                    } = warning;
-                    let range = 0..page.text.len();
-                    let message = message.message().trim_end_matches('.');
-                    if !(range.contains(&start) && range.contains(&end)) {
-                        eprintln!("byte position {} or {} in warning {} is out of range of {:?}, size of [[{}]]",
-                            start, end, message, range, &page.title);
-                    } else {
-                        eprintln!(
-                            "{} at bytes {}..{} ({:?}) in [[{}]]",
-                            &message,
-                            start,
-                            end,
-                            &page.text[start..end],
-                            &page.title
-                        );
-                    }
+        let parser = parser.take(page_limit).filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+        parse_pages(
+            parser,
+            configuration,
+            jobs,
+            |page, parser_output| {
+                if verbose {
+                    print_parser_warnings(page, &parser_output.warnings);
+                }
+                Self::headers(page, &parser_output.nodes)
+            },
+            |_page, headers| {
+                for (header, level) in headers {
+                    self.add_header(header, level);
                 }
-            }
+                Ok(())
+            },
+        )
+    }
 
-            self.process_nodes(&page, &parser_output.nodes);
-        }
+    /// Returns the text and level of the headers in `nodes`.
+    pub fn headers(
+        page: &Page,
+        nodes: &[Node],
+    ) -> Vec<(String, HeaderLevel)> {
+        headings(&page.text, nodes)
+            .into_iter()
+            .map(|(text, level)| (text.into(), level as HeaderLevel))
+            .collect()
     }
 
-    fn process_nodes(&mut self, page: &Page, nodes: &[Node]) {
-        for node in nodes {
-            match node {
-                DefinitionList { items, .. } => {
-                    for item in items {
-                        self.process_nodes(&page, &item.nodes);
-                    }
-                }
-                Heading { nodes, level, .. } => {
-                    self.process_header(&page, &nodes, *level);
-                }
-                Preformatted { nodes, .. } | Tag { nodes, .. } => {
-                    self.process_nodes(&page, &nodes);
-                }
-                Image { text, .. } | Link { text, .. } => {
-                    self.process_nodes(&page, &text);
-                }
-                OrderedList { items, .. } | UnorderedList { items, .. } => {
-                    for item in items {
-                        self.process_nodes(&page, &item.nodes);
-                    }
-                }
-                Parameter { name, default, .. } => {
-                    match default {
-                        Some(nodes) => self.process_nodes(&page, &nodes),
-                        None => {}
-                    }
-                    self.process_nodes(&page, &name);
-                }
-                Table {
-                    attributes,
-                    captions,
-                    rows,
-                    ..
-                } => {
-                    self.process_nodes(&page, &attributes);
-                    for caption in captions {
-                        if let Some(attributes) = &caption.attributes {
-                            self.process_nodes(&page, attributes)
-                        }
-                        self.process_nodes(&page, &caption.content);
-                    }
-                    for row in rows {
-                        self.process_nodes(&page, &row.attributes);
-                        for cell in &row.cells {
-                            if let Some(attributes) = &cell.attributes {
-                                self.process_nodes(&page, attributes);
-                            }
-                            self.process_nodes(&page, &cell.content);
-                        }
-                    }
-                }
-                Template {
-                    name, parameters, ..
-                } => {
-                    self.process_nodes(&page, &name);
-                    for parameter in parameters {
-                        if let Some(name) = &parameter.name {
-                            self.process_nodes(&page, name);
-                        }
-                        self.process_nodes(&page, &parameter.value);
-                    }
-                }
-                Bold { .. }
-                | BoldItalic { .. }
-                | Category { .. }
-                | CharacterEntity { .. }
-                | Comment { .. }
-                | EndTag { .. }
-                | ExternalLink { .. }
-                | HorizontalDivider { .. }
-                | Italic { .. }
-                | MagicWord { .. }
-                | ParagraphBreak { .. }
-                | Redirect { .. }
-                | StartTag { .. }
-                | Text { .. } => {}
-            }
-        }
+    /// Returns the headers and their counts, sorted by header.
+    pub fn sorted(&self) -> Vec<(&str, &HeaderCounts)> {
+        let mut header_counts: Vec<_> = self
+            .header_counts
+            .iter()
+            .map(|(header, counts)| (header.as_str(), counts))
+            .collect();
+        header_counts.sort_by_key(|&(header, _)| header);
+        header_counts
     }
 
-    fn process_header(&mut self, page: &Page, nodes: &[Node], level: u8) {
-        let key = nodes
-            .get_text_from(&page.text)
-            .trim_matches(|c: char| c == ' ' || c == '\t');
+    pub fn add_header(&mut self, header: String, level: HeaderLevel) {
         let value = self
             .header_counts
-            .entry(key.into())
+            .entry(header)
             .or_insert_with(HeaderCounts::new);
-        value[level as HeaderLevel] += 1;
+        value[level] += 1;
     }
 }
@@ -10,18 +10,21 @@ if test -z "$date"
 end
 
 set -l all_headers all_headers/"$date".json
+set -l filtered_headers filtered_headers/"$date".json
+set -l job_file header_jobs.toml
+echo -n > "$job_file"
+
 if test ! \( -f "$all_headers" -a -s "$all_headers" \)
-	echo 'generating header statistics'
-	wiktionary-data all-headers \
-		--namespaces main \
-		--pretty \
-		| sd '("counts":)\s*\[\s*(\d+),\s*(\d+),\s*(\d+),\s*(\d+),\s*(\d+),\s*(\d+)\s*\]' \
-			'$1 [$2,$3,$4,$5,$6,$7]' \
-			> "$all_headers"
-	ln -sf "$date".json all_headers/latest.json
+	echo 'will generate header statistics'
+	printf '%s\n' \
+		'[[jobs]]' \
+		'kind = "all-headers"' \
+		"output = \"$all_headers\"" \
+		'namespaces = ["main"]' \
+		'pretty = true' \
+		>> "$job_file"
 end
 
-set -l filtered_headers filtered_headers/"$date".json
 if test ! \( -f "$filtered_headers" -a -s "$filtered_headers" \)
 	set -l language_names language_names.txt
 	echo 'getting data on language names'
@@ -39,14 +42,31 @@ if test ! \( -f "$filtered_headers" -a -s "$filtered_headers" \)
 			exit -1;
 		end;
 	lua -e 'for name in pairs(require "language_name_to_code") do print(name) end' > "$language_names"
-	echo 'filtering headers'
-	wiktionary-data filter-headers \
-		--namespaces main,reconstruction \
-		--top-level-headers "$language_names" \
-		--other-headers "correct_headers.txt" \
-		--pretty \
-		> "$filtered_headers"
-	ln -sf "$date".json filtered_headers/latest.json
+	echo 'will filter headers'
+	printf '%s\n' \
+		'[[jobs]]' \
+		'kind = "filter-headers"' \
+		"output = \"$filtered_headers\"" \
+		'namespaces = ["main", "reconstruction"]' \
+		"top_level_headers = [\"$language_names\"]" \
+		'other_headers = ["correct_headers.txt"]' \
+		'pretty = true' \
+		>> "$job_file"
+end
+
+if test -s "$job_file"
+	echo 'running header jobs'
+	wiktionary-data run "$job_file"
+		or exit 1
+	if test -f "$all_headers"
+		sd '("counts":)\s*\[\s*(\d+),\s*(\d+),\s*(\d+),\s*(\d+),\s*(\d+),\s*(\d+)\s*\]' \
+			'$1 [$2,$3,$4,$5,$6,$7]' \
+			"$all_headers"
+		ln -sf "$date".json all_headers/latest.json
+	end
+	if test -f "$filtered_headers"
+		ln -sf "$date".json filtered_headers/latest.json
+	end
 end
 
 set -l summary "update from $date dump"
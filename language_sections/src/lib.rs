@@ -0,0 +1,198 @@
+// Splits pages into their language sections, the sections under level-2
+// headings such as `==English==`, and maps the language names in the
+// headings to codes using the data modules of `Module:languages`.
+
+use dump_parser::{DumpParser, Error, Namespace, Node};
+use parse_wiki_text_ext::Section;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io::Read, ops::Range};
+
+/// Canonical language names mapped to language codes.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct LanguageCodes(HashMap<String, String>);
+
+impl LanguageCodes {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads the language data modules from the Module namespace of a dump.
+    pub fn from_dump<R: Read>(parser: DumpParser<R>) -> Result<Self, Error> {
+        let mut codes = Self::new();
+        for page in parser {
+            let page = page?;
+            if page.namespace != Namespace::Module {
+                continue;
+            }
+            if let Some((_, title)) = page.title.split_once(':') {
+                if Self::is_data_module(title) {
+                    codes.add_data_module(&page.text);
+                }
+            }
+        }
+        Ok(codes)
+    }
+
+    /// Whether a title without namespace prefix is one of the data modules
+    /// of `Module:languages`, such as `languages/data/2` or, in older
+    /// dumps, `languages/data3/a`.
+    pub fn is_data_module(title: &str) -> bool {
+        title.starts_with("languages/data")
+            && !title.ends_with("/documentation")
+    }
+
+    /// Adds the languages in the text of a data module, which are given as
+    /// `m["en"] = {` followed by the canonical name in double quotes.
+    pub fn add_data_module(&mut self, text: &str) {
+        let mut rest = text;
+        while let Some(i) = rest.find("m[\"") {
+            rest = &rest[i + "m[\"".len()..];
+            if let Some((code, name)) = parse_language(rest) {
+                self.0.insert(name.into(), code.into());
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// Parses the code and canonical name from the part of a language in a data
+// module after `m["`.
+fn parse_language(text: &str) -> Option<(&str, &str)> {
+    let (code, rest) = text.split_once('"')?;
+    if code.is_empty() || code.contains(char::is_whitespace) {
+        return None;
+    }
+    let rest = rest
+        .strip_prefix(']')?
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start()
+        .strip_prefix('{')?
+        .trim_start()
+        .strip_prefix('"')?;
+    let (name, _) = rest.split_once('"')?;
+    Some((code, name))
+}
+
+/// A level-2 section of a page.
+#[derive(Debug, Serialize)]
+pub struct LanguageSection<'a> {
+    /// The text of the heading.
+    pub language: &'a str,
+    /// The code of the language, if the heading is a canonical name.
+    pub code: Option<&'a str>,
+    /// The byte range of the section, including the heading.
+    pub range: Range<usize>,
+    pub text: &'a str,
+}
+
+impl<'a> LanguageSection<'a> {
+    pub fn new(
+        wikitext: &'a str,
+        language: &'a str,
+        range: Range<usize>,
+        codes: &'a LanguageCodes,
+    ) -> Self {
+        LanguageSection {
+            language,
+            code: codes.get(language),
+            text: &wikitext[range.clone()],
+            range,
+        }
+    }
+
+    /// Whether `language` is the name or code of the language.
+    pub fn is_language(&self, language: &str) -> bool {
+        self.language == language || self.code == Some(language)
+    }
+}
+
+/// Returns the level-2 sections among the top-level nodes of a page.
+pub fn language_sections<'a>(
+    wikitext: &'a str,
+    nodes: &'a [Node<'a>],
+    codes: &'a LanguageCodes,
+) -> Vec<LanguageSection<'a>> {
+    Section::new(wikitext, nodes)
+        .children
+        .into_iter()
+        .filter(|section| section.level == 2)
+        .filter_map(|section| {
+            let language = section.heading?;
+            Some(LanguageSection::new(wikitext, language, section.range, codes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{language_sections, LanguageCodes};
+    use dump_parser::Configuration;
+
+    #[test]
+    fn data_module() {
+        let mut codes = LanguageCodes::new();
+        codes.add_data_module(concat!(
+            "local m = {}\n\n",
+            "m[\"en\"] = {\n\t\"English\",\n\t1860,\n}\n\n",
+            "m[\"la\"]={\"Latin\", 397}\n\n",
+            "m[\"fr\"] = require(\"Module:languages/data\").fr\n\n",
+            "return m\n"
+        ));
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes.get("English"), Some("en"));
+        assert_eq!(codes.get("Latin"), Some("la"));
+        assert_eq!(codes.get("French"), None);
+        assert!(LanguageCodes::is_data_module("languages/data/3/a"));
+        assert!(LanguageCodes::is_data_module("languages/data2"));
+        assert!(!LanguageCodes::is_data_module(
+            "languages/data2/documentation"
+        ));
+        assert!(!LanguageCodes::is_data_module("languages"));
+    }
+
+    #[test]
+    fn split() {
+        let configuration = Configuration::default();
+        let mut codes = LanguageCodes::new();
+        codes.add_data_module("m[\"en\"] = {\n\t\"English\",\n}\n");
+        let wikitext = concat!(
+            "{{also|A}}\n",
+            "==English==\n",
+            "===Noun===\n",
+            "{{en-noun}}\n\n",
+            "==Translingual==\n",
+            "===Symbol===\n",
+            "{{mul-symbol}}\n",
+        );
+        let output = configuration.parse(wikitext);
+        let sections = language_sections(wikitext, &output.nodes, &codes);
+        let english = wikitext.find("==English").unwrap();
+        let translingual = wikitext.find("==Translingual").unwrap();
+        assert_eq!(
+            sections
+                .iter()
+                .map(|s| (s.language, s.code, s.text))
+                .collect::<Vec<_>>(),
+            vec![
+                ("English", Some("en"), &wikitext[english..translingual]),
+                ("Translingual", None, &wikitext[translingual..]),
+            ]
+        );
+        assert!(sections[0].is_language("en"));
+        assert!(sections[1].is_language("Translingual"));
+    }
+}
@@ -0,0 +1,455 @@
+// Streaming parser for the SQL dumps of MediaWiki tables, such as
+// `page.sql` and `redirect.sql`. mysqldump puts each `INSERT` statement on
+// its own line, so the dump is read a line at a time, lines that do not
+// insert into the table being parsed are skipped, and each parenthesized
+// tuple of values in an `INSERT` statement is converted into a `Row`.
+
+use std::{
+    fmt::Display,
+    io::{self, BufRead},
+    marker::PhantomData,
+    str::FromStr,
+};
+
+pub mod tables;
+pub use tables::{CategoryLink, Page, Redirect, TemplateLink};
+
+#[derive(Debug)]
+pub enum SqlDumpError {
+    IoError(io::Error),
+    SyntaxError {
+        line: usize,
+        column: usize,
+        expected: &'static str,
+    },
+    Utf8Error {
+        line: usize,
+        column: usize,
+    },
+}
+
+impl From<io::Error> for SqlDumpError {
+    fn from(e: io::Error) -> Self {
+        SqlDumpError::IoError(e)
+    }
+}
+
+impl Display for SqlDumpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SqlDumpError::IoError(e) => {
+                write!(f, "failed to read SQL dump: {}", e)
+            }
+            SqlDumpError::SyntaxError {
+                line,
+                column,
+                expected,
+            } => write!(
+                f,
+                "expected {} at line {}, column {} of SQL dump",
+                expected, line, column
+            ),
+            SqlDumpError::Utf8Error { line, column } => write!(
+                f,
+                "invalid UTF-8 in string at line {}, column {} of SQL dump",
+                line, column
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SqlDumpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let SqlDumpError::IoError(e) = self {
+            Some(e)
+        } else {
+            None
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SqlDumpError>;
+
+/// A row of a table, built from the values of one tuple in an `INSERT`
+/// statement, in the order that the columns were declared in.
+pub trait Row: Sized {
+    /// The name of the table, as it appears in ``INSERT INTO `table` ``.
+    const TABLE: &'static str;
+
+    fn from_fields(fields: &mut Fields<'_>) -> Result<Self>;
+}
+
+/// A type that can be parsed from a single value in an `INSERT` statement.
+pub trait FromSqlValue: Sized {
+    fn from_sql_value(fields: &mut Fields<'_>) -> Result<Self>;
+}
+
+/// A cursor over the values of a tuple in an `INSERT` statement.
+pub struct Fields<'a> {
+    line: &'a [u8],
+    pos: usize,
+    line_number: usize,
+    first: bool,
+}
+
+impl<'a> Fields<'a> {
+    /// Parses the next value in the tuple.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next<T: FromSqlValue>(&mut self) -> Result<T> {
+        if self.first {
+            self.first = false;
+        } else {
+            self.expect(b',', "comma")?;
+        }
+        T::from_sql_value(self)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.line.get(self.pos).copied()
+    }
+
+    fn error(&self, expected: &'static str) -> SqlDumpError {
+        SqlDumpError::SyntaxError {
+            line: self.line_number,
+            column: self.pos + 1,
+            expected,
+        }
+    }
+
+    fn expect(&mut self, byte: u8, expected: &'static str) -> Result<()> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    fn null(&mut self) -> bool {
+        if self.line[self.pos..].starts_with(b"NULL") {
+            self.pos += "NULL".len();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Parses a number consisting of the bytes that `is_number_byte` accepts.
+    fn number<T: FromStr>(
+        &mut self,
+        is_number_byte: fn(u8) -> bool,
+        expected: &'static str,
+    ) -> Result<T> {
+        let start = self.pos;
+        let len = self.line[start..]
+            .iter()
+            .take_while(|&&b| is_number_byte(b))
+            .count();
+        let number = std::str::from_utf8(&self.line[start..start + len])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .ok_or_else(|| self.error(expected))?;
+        self.pos += len;
+        Ok(number)
+    }
+
+    // Parses a quoted string, replacing the backslash escapes that mysqldump
+    // generates (`\0 \b \n \r \t \Z \' \" \\`) with the characters they
+    // stand for. As in MySQL, a backslash before any other character is
+    // dropped. The Lua parser only replaces `\'` and `\\` and leaves other
+    // backslashes in place.
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        self.expect(b'\'', "string")?;
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'\'') => {
+                    self.pos += 1;
+                    return Ok(bytes);
+                }
+                Some(b'\\') => {
+                    let escaped = self
+                        .line
+                        .get(self.pos + 1)
+                        .ok_or_else(|| self.error("end of string"))?;
+                    bytes.push(match escaped {
+                        b'0' => b'\0',
+                        b'b' => b'\x08',
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'Z' => b'\x1A',
+                        &other => other,
+                    });
+                    self.pos += 2;
+                }
+                Some(b) => {
+                    bytes.push(b);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("end of string")),
+            }
+        }
+    }
+}
+
+macro_rules! impl_from_sql_value_for_integer {
+    ($($type:ty)+) => {
+        $(
+            impl FromSqlValue for $type {
+                fn from_sql_value(fields: &mut Fields<'_>) -> Result<Self> {
+                    fields.number(
+                        |b| b == b'-' || b.is_ascii_digit(),
+                        "integer",
+                    )
+                }
+            }
+        )+
+    };
+}
+
+impl_from_sql_value_for_integer!(i8 i16 i32 i64 u8 u16 u32 u64);
+
+impl FromSqlValue for f64 {
+    fn from_sql_value(fields: &mut Fields<'_>) -> Result<Self> {
+        fields.number(
+            |b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'),
+            "floating-point number",
+        )
+    }
+}
+
+impl FromSqlValue for bool {
+    fn from_sql_value(fields: &mut Fields<'_>) -> Result<Self> {
+        let value = match fields.peek() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(fields.error("boolean")),
+        };
+        fields.pos += 1;
+        Ok(value)
+    }
+}
+
+impl FromSqlValue for Vec<u8> {
+    fn from_sql_value(fields: &mut Fields<'_>) -> Result<Self> {
+        fields.bytes()
+    }
+}
+
+impl FromSqlValue for String {
+    fn from_sql_value(fields: &mut Fields<'_>) -> Result<Self> {
+        let start = fields.pos;
+        String::from_utf8(fields.bytes()?).map_err(|_| {
+            SqlDumpError::Utf8Error {
+                line: fields.line_number,
+                column: start + 1,
+            }
+        })
+    }
+}
+
+impl<T: FromSqlValue> FromSqlValue for Option<T> {
+    fn from_sql_value(fields: &mut Fields<'_>) -> Result<Self> {
+        if fields.null() {
+            Ok(None)
+        } else {
+            T::from_sql_value(fields).map(Some)
+        }
+    }
+}
+
+/// An iterator over the rows of table `T` in a SQL dump.
+pub struct Rows<R, T> {
+    reader: R,
+    prefix: Vec<u8>,
+    line: Vec<u8>,
+    line_number: usize,
+    // Position in `line` of the next tuple, if `line` is an `INSERT`
+    // statement for the table.
+    pos: Option<usize>,
+    row: PhantomData<T>,
+}
+
+/// Iterates over the rows of table `T` in the SQL dump read from `reader`.
+/// To read a compressed dump, wrap the reader in a decoder, for instance
+/// with `dump_parser::input::decompress`.
+pub fn iter_rows<T: Row, R: BufRead>(reader: R) -> Rows<R, T> {
+    Rows {
+        reader,
+        prefix: format!("INSERT INTO `{}` VALUES ", T::TABLE).into_bytes(),
+        line: Vec::new(),
+        line_number: 0,
+        pos: None,
+        row: PhantomData,
+    }
+}
+
+impl<R: BufRead, T: Row> Rows<R, T> {
+    fn next_row(&mut self) -> Result<Option<T>> {
+        loop {
+            if let Some(pos) = self.pos {
+                match self.line.get(pos) {
+                    Some(b'(') => {
+                        let mut fields = Fields {
+                            line: &self.line,
+                            pos: pos + 1,
+                            line_number: self.line_number,
+                            first: true,
+                        };
+                        let row = T::from_fields(&mut fields)?;
+                        fields.expect(b')', "closing parenthesis")?;
+                        if fields.peek() == Some(b',') {
+                            fields.pos += 1;
+                        }
+                        self.pos = Some(fields.pos);
+                        return Ok(Some(row));
+                    }
+                    Some(b';') => self.pos = None,
+                    _ => {
+                        return Err(SqlDumpError::SyntaxError {
+                            line: self.line_number,
+                            column: pos + 1,
+                            expected: "opening parenthesis or semicolon",
+                        })
+                    }
+                }
+            }
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            if self.line.starts_with(&self.prefix) {
+                self.pos = Some(self.prefix.len());
+            }
+        }
+    }
+}
+
+impl<R: BufRead, T: Row> Iterator for Rows<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_row();
+        if next.is_err() {
+            // Don't return the same error again.
+            self.pos = None;
+        }
+        next.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{iter_rows, Page, Redirect, SqlDumpError};
+
+    const REDIRECT_SQL: &str = concat!(
+        "-- MySQL dump\n",
+        "CREATE TABLE `redirect` (\n",
+        "  `rd_from` int(8) unsigned NOT NULL DEFAULT 0,\n",
+        ") ENGINE=InnoDB DEFAULT CHARSET=binary;\n",
+        "INSERT INTO `redirect` VALUES (1,10,'en-noun',NULL,NULL),",
+        r#"(2,0,'don\'t','',NULL),(3,0,'a\\b\n\"c\"',NULL,'Etymology');"#,
+        "\n",
+        r"INSERT INTO `redirect` VALUES (4,0,'x',NULL,NULL);",
+        "\n",
+        "UNLOCK TABLES;\n",
+    );
+
+    #[test]
+    fn parse_redirects() {
+        let redirects = iter_rows::<Redirect, _>(REDIRECT_SQL.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            redirects,
+            vec![
+                Redirect {
+                    from: 1,
+                    namespace: 10,
+                    title: "en-noun".into(),
+                    interwiki: None,
+                    fragment: None,
+                },
+                Redirect {
+                    from: 2,
+                    namespace: 0,
+                    title: "don't".into(),
+                    interwiki: Some("".into()),
+                    fragment: None,
+                },
+                Redirect {
+                    from: 3,
+                    namespace: 0,
+                    title: "a\\b\n\"c\"".into(),
+                    interwiki: None,
+                    fragment: Some("Etymology".into()),
+                },
+                Redirect {
+                    from: 4,
+                    namespace: 0,
+                    title: "x".into(),
+                    interwiki: None,
+                    fragment: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unescape_strings() {
+        let sql = concat!(
+            "INSERT INTO `redirect` VALUES ",
+            r#"(1,0,'\0\b\n\r\t\Z|\'\"\\|\%\x\\n',NULL,NULL);"#,
+            "\n",
+        );
+        let redirect = iter_rows::<Redirect, _>(sql.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(redirect.title, "\0\x08\n\r\t\x1A|'\"\\|%x\\n");
+    }
+
+    #[test]
+    fn skip_other_tables() {
+        assert_eq!(iter_rows::<Page, _>(REDIRECT_SQL.as_bytes()).count(), 0);
+    }
+
+    #[test]
+    fn parse_page() {
+        let sql = concat!(
+            "INSERT INTO `page` VALUES (5,10,'en-noun','',0,0,",
+            "0.123456789,'20200101000000','20200102000000',100,42,",
+            "'wikitext',NULL);\n"
+        );
+        let page = iter_rows::<Page, _>(sql.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.id, 5);
+        assert_eq!(page.namespace, 10);
+        assert_eq!(page.title, "en-noun");
+        assert!(!page.is_redirect);
+        assert_eq!(page.random, 0.123456789);
+        assert_eq!(page.links_updated.as_deref(), Some("20200102000000"));
+        assert_eq!(page.latest, Some(100));
+        assert_eq!(page.content_model.as_deref(), Some("wikitext"));
+        assert_eq!(page.lang, None);
+    }
+
+    #[test]
+    fn report_wrong_number_of_columns() {
+        let sql = "INSERT INTO `redirect` VALUES (1,10,'en-noun',NULL);\n";
+        let mut rows = iter_rows::<Redirect, _>(sql.as_bytes());
+        match rows.next() {
+            Some(Err(SqlDumpError::SyntaxError {
+                line: 1,
+                column: 51,
+                expected: "comma",
+            })) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(rows.next().is_none());
+    }
+}
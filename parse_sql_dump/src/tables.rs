@@ -0,0 +1,127 @@
+// Rows of the tables whose dumps the Wiktionary scripts use. The fields are
+// listed in the order of the columns in the dumps, without the prefix that
+// MediaWiki adds to column names (`page_`, `rd_`, `cl_`, `tl_`).
+
+use crate::{Fields, Result, Row};
+
+/// Page ids are `page_id`, `rd_from`, `cl_from` and `tl_from`.
+pub type PageId = u32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub id: PageId,
+    pub namespace: i32,
+    /// Title without namespace prefix, with underscores instead of spaces.
+    pub title: String,
+    pub restrictions: String,
+    pub is_redirect: bool,
+    pub is_new: bool,
+    pub random: f64,
+    pub touched: String,
+    pub links_updated: Option<String>,
+    pub latest: Option<u32>,
+    pub len: u32,
+    pub content_model: Option<String>,
+    pub lang: Option<String>,
+}
+
+impl Row for Page {
+    const TABLE: &'static str = "page";
+
+    fn from_fields(fields: &mut Fields<'_>) -> Result<Self> {
+        Ok(Page {
+            id: fields.next()?,
+            namespace: fields.next()?,
+            title: fields.next()?,
+            restrictions: fields.next()?,
+            is_redirect: fields.next()?,
+            is_new: fields.next()?,
+            random: fields.next()?,
+            touched: fields.next()?,
+            links_updated: fields.next()?,
+            latest: fields.next()?,
+            len: fields.next()?,
+            content_model: fields.next()?,
+            lang: fields.next()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    /// Id of the redirect page.
+    pub from: PageId,
+    /// Namespace of the target.
+    pub namespace: i32,
+    /// Title of the target, without namespace prefix.
+    pub title: String,
+    pub interwiki: Option<String>,
+    pub fragment: Option<String>,
+}
+
+impl Row for Redirect {
+    const TABLE: &'static str = "redirect";
+
+    fn from_fields(fields: &mut Fields<'_>) -> Result<Self> {
+        Ok(Redirect {
+            from: fields.next()?,
+            namespace: fields.next()?,
+            title: fields.next()?,
+            interwiki: fields.next()?,
+            fragment: fields.next()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryLink {
+    pub from: PageId,
+    /// Category name, without namespace prefix.
+    pub to: String,
+    /// Binary collation key, which need not be valid UTF-8.
+    pub sortkey: Vec<u8>,
+    pub timestamp: String,
+    pub sortkey_prefix: String,
+    pub collation: String,
+    /// `page`, `subcat` or `file`.
+    pub r#type: String,
+}
+
+impl Row for CategoryLink {
+    const TABLE: &'static str = "categorylinks";
+
+    fn from_fields(fields: &mut Fields<'_>) -> Result<Self> {
+        Ok(CategoryLink {
+            from: fields.next()?,
+            to: fields.next()?,
+            sortkey: fields.next()?,
+            timestamp: fields.next()?,
+            sortkey_prefix: fields.next()?,
+            collation: fields.next()?,
+            r#type: fields.next()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateLink {
+    pub from: PageId,
+    /// Namespace of the transcluded page.
+    pub namespace: i32,
+    /// Title of the transcluded page, without namespace prefix.
+    pub title: String,
+    pub from_namespace: i32,
+}
+
+impl Row for TemplateLink {
+    const TABLE: &'static str = "templatelinks";
+
+    fn from_fields(fields: &mut Fields<'_>) -> Result<Self> {
+        Ok(TemplateLink {
+            from: fields.next()?,
+            namespace: fields.next()?,
+            title: fields.next()?,
+            from_namespace: fields.next()?,
+        })
+    }
+}
@@ -0,0 +1,144 @@
+// A depth-first iterator over the nodes that `parse_wiki_text` produces, for
+// use where a `Visitor` would be overkill, such as in a `filter_map`.
+
+use parse_wiki_text::Node;
+use std::{convert::Infallible, iter::Flatten, vec};
+
+use crate::visit::for_each_child_list;
+
+type Children<'a> = Flatten<vec::IntoIter<&'a [Node<'a>]>>;
+
+fn children<'a>(node: &'a Node<'a>) -> Children<'a> {
+    let mut lists = Vec::new();
+    let _ = for_each_child_list(node, |nodes| {
+        lists.push(nodes);
+        Ok::<_, Infallible>(())
+    });
+    lists.into_iter().flatten()
+}
+
+/// Iterator over nodes and all the nodes inside them, in the order in which
+/// they appear in the wikitext, along with their depth: 0 for the nodes the
+/// iterator was created from, 1 for their children, and so on.
+pub struct Descendants<'a> {
+    // The top-level nodes, then the children of each node in `ancestors`.
+    stack: Vec<Children<'a>>,
+    ancestors: Vec<&'a Node<'a>>,
+    // The node last returned, whose children are to be visited next.
+    last: Option<&'a Node<'a>>,
+}
+
+impl<'a> Descendants<'a> {
+    pub fn new(nodes: &'a [Node<'a>]) -> Self {
+        Descendants {
+            stack: vec![vec![nodes].into_iter().flatten()],
+            ancestors: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// The nodes that contain the node last returned by `next`, starting with
+    /// the outermost. Its length is the depth of that node.
+    pub fn ancestors(&self) -> &[&'a Node<'a>] {
+        &self.ancestors
+    }
+
+    /// The node that directly contains the node last returned by `next`.
+    pub fn parent(&self) -> Option<&'a Node<'a>> {
+        self.ancestors.last().copied()
+    }
+
+    /// Don't visit the children of the node last returned by `next`.
+    pub fn skip_children(&mut self) {
+        self.last = None;
+    }
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = (&'a Node<'a>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(node) = self.last.take() {
+            self.stack.push(children(node));
+            self.ancestors.push(node);
+        }
+        loop {
+            if let Some(node) = self.stack.last_mut()?.next() {
+                self.last = Some(node);
+                return Some((node, self.ancestors.len()));
+            }
+            self.stack.pop();
+            self.ancestors.pop();
+        }
+    }
+}
+
+/// Adds `descendants` to `[Node]` and `Vec<Node>`, such as
+/// `ParserOutput::nodes`.
+pub trait NodesExt<'a> {
+    fn descendants(&'a self) -> Descendants<'a>;
+}
+
+impl<'a> NodesExt<'a> for [Node<'a>] {
+    fn descendants(&'a self) -> Descendants<'a> {
+        Descendants::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NodesExt;
+    use parse_wiki_text::{Configuration, Node, Positioned};
+
+    #[test]
+    fn descendants() {
+        let configuration = Configuration::default();
+        let wikitext = concat!(
+            "==={{a}}===\n",
+            "* [[link|{{b|{{c}} }}]]\n",
+            "{{d}}"
+        );
+        let output = configuration.parse(wikitext);
+        let templates: Vec<_> = output
+            .nodes
+            .descendants()
+            .filter_map(|(node, depth)| match node {
+                Node::Template { .. } => {
+                    Some((node.get_text_from(wikitext), depth))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            templates,
+            vec![
+                ("{{a}}", 1),
+                ("{{b|{{c}} }}", 2),
+                ("{{c}}", 3),
+                ("{{d}}", 0),
+            ]
+        );
+
+        let mut descendants = output.nodes.descendants();
+        let mut parents = Vec::new();
+        while let Some((node, depth)) = descendants.next() {
+            assert_eq!(descendants.ancestors().len(), depth);
+            match node {
+                Node::Heading { .. } => descendants.skip_children(),
+                Node::Template { .. } => parents.push((
+                    node.get_text_from(wikitext),
+                    descendants.parent().map(|p| p.get_text_from(wikitext)),
+                )),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            parents,
+            vec![
+                ("{{b|{{c}} }}", Some("[[link|{{b|{{c}} }}]]")),
+                ("{{c}}", Some("{{b|{{c}} }}")),
+                ("{{d}}", None),
+            ]
+        );
+    }
+}
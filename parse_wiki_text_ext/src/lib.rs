@@ -1,5 +1,12 @@
 pub mod template_parameters;
 
+pub mod descendants;
+pub mod section;
+pub mod visit;
+pub use descendants::{Descendants, NodesExt};
+pub use section::Section;
+pub use visit::{headings, walk, Flow, Visitor};
+
 #[cfg(test)]
 mod tests {
     use parse_wiki_text::{Configuration, Node, Positioned};
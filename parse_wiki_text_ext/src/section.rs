@@ -0,0 +1,257 @@
+// The sections of a page as a tree, built from the headings among the
+// top-level nodes, so that the language and part-of-speech sections that
+// contain a node can be looked up by its position.
+
+use parse_wiki_text::{Node, Positioned};
+use std::ops::Range;
+
+/// A heading and everything under it up to the next heading of the same or
+/// a higher level, or, at the root of the tree, the whole page.
+#[derive(Debug)]
+pub struct Section<'a> {
+    /// The text of the heading without surrounding whitespace, or `None`
+    /// for the root.
+    pub heading: Option<&'a str>,
+    /// The level of the heading, from 1 to 6, or 0 for the root.
+    pub level: u8,
+    /// The byte range of the section in the wikitext, including the heading
+    /// and child sections.
+    pub range: Range<usize>,
+    /// The heading node, if any, followed by the nodes before the first
+    /// child section.
+    pub nodes: &'a [Node<'a>],
+    pub children: Vec<Section<'a>>,
+}
+
+impl<'a> Section<'a> {
+    /// Builds the tree of sections from the top-level nodes of a page.
+    /// Headings inside other nodes are treated as content.
+    pub fn new(wikitext: &'a str, nodes: &'a [Node<'a>]) -> Self {
+        let mut stack = vec![SectionBuilder::new(None, 0, 0, 0)];
+        for (i, node) in nodes.iter().enumerate() {
+            if let Node::Heading {
+                nodes: heading,
+                level,
+                ..
+            } = node
+            {
+                while matches!(stack.last(), Some(s) if s.level >= *level) {
+                    let section = stack.pop().expect("checked above").build(
+                        nodes,
+                        i,
+                        node.start(),
+                    );
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(section);
+                    }
+                }
+                if let Some(parent) = stack.last_mut() {
+                    parent.end_of_own_nodes.get_or_insert(i);
+                }
+                let text = heading.get_text_from(wikitext).trim();
+                stack.push(SectionBuilder::new(
+                    Some(text),
+                    *level,
+                    node.start(),
+                    i,
+                ));
+            }
+        }
+        let mut section = stack.pop().expect("root is never popped");
+        while let Some(mut parent) = stack.pop() {
+            let built = section.build(nodes, nodes.len(), wikitext.len());
+            parent.children.push(built);
+            section = parent;
+        }
+        section.build(nodes, nodes.len(), wikitext.len())
+    }
+
+    /// The heading node, or `None` for the root.
+    pub fn heading_node(&self) -> Option<&'a Node<'a>> {
+        self.heading.and(self.nodes.first())
+    }
+
+    /// The nodes after the heading and before the first child section.
+    pub fn content(&self) -> &'a [Node<'a>] {
+        match self.heading_node() {
+            Some(_) => &self.nodes[1..],
+            None => self.nodes,
+        }
+    }
+
+    /// The sections that contain `position`, a byte offset such as the start
+    /// of a node, starting with this one and ending with the innermost.
+    /// Empty if `position` is outside this section.
+    pub fn path_to(&self, position: usize) -> Vec<&Section<'a>> {
+        let mut path = Vec::new();
+        let mut section = self;
+        while section.range.contains(&position) {
+            path.push(section);
+            match section
+                .children
+                .iter()
+                .find(|child| child.range.contains(&position))
+            {
+                Some(child) => section = child,
+                None => break,
+            }
+        }
+        path
+    }
+
+    /// Iterates over this section and its descendants in the order in
+    /// which they appear in the wikitext.
+    pub fn iter(&self) -> impl Iterator<Item = &Section<'a>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let section = stack.pop()?;
+            stack.extend(section.children.iter().rev());
+            Some(section)
+        })
+    }
+
+    /// Calls `f` with the path from this section to each of its descendants
+    /// in the order in which they appear in the wikitext, starting with this
+    /// section itself, and stops at the first error.
+    pub fn try_for_each_path<E, F>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&[&Section<'a>]) -> Result<(), E>,
+    {
+        fn visit<'a, 'b, E>(
+            section: &'b Section<'a>,
+            path: &mut Vec<&'b Section<'a>>,
+            f: &mut dyn FnMut(&[&'b Section<'a>]) -> Result<(), E>,
+        ) -> Result<(), E> {
+            path.push(section);
+            f(path)?;
+            for child in &section.children {
+                visit(child, path, f)?;
+            }
+            path.pop();
+            Ok(())
+        }
+
+        visit(self, &mut Vec::new(), &mut f)
+    }
+}
+
+struct SectionBuilder<'a> {
+    heading: Option<&'a str>,
+    level: u8,
+    start: usize,
+    first_node: usize,
+    // Index of the first node of the first child section.
+    end_of_own_nodes: Option<usize>,
+    children: Vec<Section<'a>>,
+}
+
+impl<'a> SectionBuilder<'a> {
+    fn new(
+        heading: Option<&'a str>,
+        level: u8,
+        start: usize,
+        first_node: usize,
+    ) -> Self {
+        SectionBuilder {
+            heading,
+            level,
+            start,
+            first_node,
+            end_of_own_nodes: None,
+            children: Vec::new(),
+        }
+    }
+
+    // `next_node` and `end` are the index and byte offset of the next
+    // heading of the same or a higher level, or the end of the page.
+    fn build(
+        self,
+        nodes: &'a [Node<'a>],
+        next_node: usize,
+        end: usize,
+    ) -> Section<'a> {
+        let end_of_own_nodes = self.end_of_own_nodes.unwrap_or(next_node);
+        Section {
+            heading: self.heading,
+            level: self.level,
+            range: self.start..end,
+            nodes: &nodes[self.first_node..end_of_own_nodes],
+            children: self.children,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Section;
+    use parse_wiki_text::{Configuration, Node, Positioned};
+
+    #[test]
+    fn sections() {
+        let configuration = Configuration::default();
+        let wikitext = concat!(
+            "{{also|a}}\n",
+            "==English==\n",
+            "===Etymology===\n",
+            "From {{der|en|la|a}}.\n",
+            "===Noun===\n",
+            "{{en-noun}}\n",
+            "====Synonyms====\n",
+            "==Latin==\n",
+            "{{la-noun}}\n",
+        );
+        let output = configuration.parse(wikitext);
+        let root = Section::new(wikitext, &output.nodes);
+        let english = wikitext.find("==English").unwrap();
+        let latin = wikitext.find("==Latin").unwrap();
+        assert_eq!(
+            root.iter()
+                .map(|s| (s.heading, s.level, &wikitext[s.range.clone()]))
+                .collect::<Vec<_>>(),
+            vec![
+                (None, 0, wikitext),
+                (Some("English"), 2, &wikitext[english..latin]),
+                (
+                    Some("Etymology"),
+                    3,
+                    "===Etymology===\nFrom {{der|en|la|a}}.\n"
+                ),
+                (
+                    Some("Noun"),
+                    3,
+                    "===Noun===\n{{en-noun}}\n====Synonyms====\n"
+                ),
+                (Some("Synonyms"), 4, "====Synonyms====\n"),
+                (Some("Latin"), 2, &wikitext[latin..]),
+            ]
+        );
+        assert_eq!(
+            root.nodes.iter().map(|n| n.get_text_from(wikitext)).next(),
+            Some("{{also|a}}")
+        );
+
+        let template = output
+            .nodes
+            .iter()
+            .find(|node| {
+                matches!(node, Node::Template { .. })
+                    && node.get_text_from(wikitext) == "{{en-noun}}"
+            })
+            .unwrap();
+        assert_eq!(
+            root.path_to(template.start())
+                .iter()
+                .map(|s| s.heading)
+                .collect::<Vec<_>>(),
+            vec![None, Some("English"), Some("Noun")]
+        );
+        let noun = &root.children[0].children[1];
+        assert!(noun.heading_node().is_some());
+        assert!(noun
+            .content()
+            .iter()
+            .any(|node| std::ptr::eq(node, template)));
+        assert!(root.heading_node().is_none());
+        assert_eq!(root.content().len(), root.nodes.len());
+    }
+}
@@ -0,0 +1,657 @@
+// A depth-first walk over the nodes that `parse_wiki_text` produces, which
+// calls a `Visitor` on entering and leaving each node, so that code that is
+// interested in a few kinds of node doesn't have to match every kind of node
+// that contains other nodes. It visits the same nodes in the same order as
+// the walks that it replaced: the default of a parameter before its name,
+// and not the nodes inside categories and external links.
+//
+// `Visitor` has hooks for the kinds of node that code in this workspace
+// looks for: templates, headings, links, tags and comments. Other kinds are
+// rarely of interest on their own and can be matched in `enter` and
+// `leave`.
+
+use parse_wiki_text::{
+    Node::{self, *},
+    Positioned,
+};
+use std::convert::Infallible;
+
+// Not imported because `Node::Parameter` would shadow it.
+type TemplateParameter<'a> = parse_wiki_text::Parameter<'a>;
+
+/// What the walk does after `Visitor::enter` returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow<B> {
+    /// Visit the children of the node.
+    Continue,
+    /// Go on to the next sibling of the node without visiting its children.
+    /// `Visitor::leave` is still called for the node.
+    SkipChildren,
+    /// Stop walking, and return `Err` with the value from `walk`.
+    Break(B),
+}
+
+impl<B> From<Result<(), B>> for Flow<B> {
+    fn from(result: Result<(), B>) -> Self {
+        match result {
+            Ok(()) => Flow::Continue,
+            Err(b) => Flow::Break(b),
+        }
+    }
+}
+
+/// Hooks that are called on entering and leaving each node. `ancestors`
+/// contains the nodes that contain the node, starting with the outermost.
+/// `enter` and `leave` call the hook for the kind of the node, if there is
+/// one, so a visitor that overrides them doesn't get the other hooks. All
+/// hooks do nothing by default.
+pub trait Visitor<'a> {
+    /// The value that stops the walk, often an error.
+    type Break;
+
+    fn enter(
+        &mut self,
+        node: &'a Node<'a>,
+        ancestors: &[&'a Node<'a>],
+    ) -> Flow<Self::Break> {
+        match node {
+            Template {
+                name, parameters, ..
+            } => self.enter_template(node, name, parameters, ancestors),
+            Heading { level, nodes, .. } => {
+                self.enter_heading(node, *level, nodes, ancestors)
+            }
+            Link { target, text, .. } => {
+                self.enter_link(node, target, text, ancestors)
+            }
+            Tag { name, nodes, .. } => {
+                self.enter_tag(node, name, nodes, ancestors)
+            }
+            Comment { .. } => self.enter_comment(node, ancestors),
+            _ => Flow::Continue,
+        }
+    }
+
+    /// Called after the children of the node have been visited.
+    fn leave(
+        &mut self,
+        node: &'a Node<'a>,
+        ancestors: &[&'a Node<'a>],
+    ) -> Result<(), Self::Break> {
+        match node {
+            Template {
+                name, parameters, ..
+            } => self.leave_template(node, name, parameters, ancestors),
+            Heading { level, nodes, .. } => {
+                self.leave_heading(node, *level, nodes, ancestors)
+            }
+            Link { target, text, .. } => {
+                self.leave_link(node, target, text, ancestors)
+            }
+            Tag { name, nodes, .. } => {
+                self.leave_tag(node, name, nodes, ancestors)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn enter_template(
+        &mut self,
+        _node: &'a Node<'a>,
+        _name: &'a [Node<'a>],
+        _parameters: &'a [TemplateParameter<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Flow<Self::Break> {
+        Flow::Continue
+    }
+
+    /// Called after the templates in the name and parameters of the
+    /// template, so nested templates are left before the outer one.
+    fn leave_template(
+        &mut self,
+        _node: &'a Node<'a>,
+        _name: &'a [Node<'a>],
+        _parameters: &'a [TemplateParameter<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Result<(), Self::Break> {
+        Ok(())
+    }
+
+    fn enter_heading(
+        &mut self,
+        _node: &'a Node<'a>,
+        _level: u8,
+        _nodes: &'a [Node<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Flow<Self::Break> {
+        Flow::Continue
+    }
+
+    fn leave_heading(
+        &mut self,
+        _node: &'a Node<'a>,
+        _level: u8,
+        _nodes: &'a [Node<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Result<(), Self::Break> {
+        Ok(())
+    }
+
+    fn enter_link(
+        &mut self,
+        _node: &'a Node<'a>,
+        _target: &'a str,
+        _text: &'a [Node<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Flow<Self::Break> {
+        Flow::Continue
+    }
+
+    fn leave_link(
+        &mut self,
+        _node: &'a Node<'a>,
+        _target: &'a str,
+        _text: &'a [Node<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Result<(), Self::Break> {
+        Ok(())
+    }
+
+    fn enter_tag(
+        &mut self,
+        _node: &'a Node<'a>,
+        _name: &'a str,
+        _nodes: &'a [Node<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Flow<Self::Break> {
+        Flow::Continue
+    }
+
+    fn leave_tag(
+        &mut self,
+        _node: &'a Node<'a>,
+        _name: &'a str,
+        _nodes: &'a [Node<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> Result<(), Self::Break> {
+        Ok(())
+    }
+
+    /// Comments contain no nodes, so there is no `leave_comment`.
+    fn enter_comment(
+        &mut self,
+        _node: &'a Node<'a>,
+        _ancestors: &[&'a Node<'a>],
+    ) -> Flow<Self::Break> {
+        Flow::Continue
+    }
+}
+
+/// Visits `nodes` and all the nodes inside them, mostly in the order in which
+/// they appear in the wikitext.
+pub fn walk<'a, V: Visitor<'a> + ?Sized>(
+    nodes: &'a [Node<'a>],
+    visitor: &mut V,
+) -> Result<(), V::Break> {
+    walk_nodes(nodes, visitor, &mut Vec::new())
+}
+
+fn walk_nodes<'a, V: Visitor<'a> + ?Sized>(
+    nodes: &'a [Node<'a>],
+    visitor: &mut V,
+    ancestors: &mut Vec<&'a Node<'a>>,
+) -> Result<(), V::Break> {
+    for node in nodes {
+        match visitor.enter(node, ancestors) {
+            Flow::Continue => {
+                ancestors.push(node);
+                let result = for_each_child_list(node, |children| {
+                    walk_nodes(children, visitor, ancestors)
+                });
+                ancestors.pop();
+                result?;
+            }
+            Flow::SkipChildren => {}
+            Flow::Break(b) => return Err(b),
+        }
+        visitor.leave(node, ancestors)?;
+    }
+    Ok(())
+}
+
+/// Returns the text and level of the headings in `nodes`, including those
+/// inside other nodes such as templates, in the order in which they appear.
+/// The text is trimmed of spaces and tabs only, so that other whitespace
+/// such as a no-break space shows up in header statistics.
+pub fn headings<'a>(
+    wikitext: &'a str,
+    nodes: &'a [Node<'a>],
+) -> Vec<(&'a str, u8)> {
+    struct Headings<'a> {
+        wikitext: &'a str,
+        headings: Vec<(&'a str, u8)>,
+    }
+
+    impl<'a> Visitor<'a> for Headings<'a> {
+        type Break = Infallible;
+
+        fn enter_heading(
+            &mut self,
+            _node: &'a Node<'a>,
+            level: u8,
+            nodes: &'a [Node<'a>],
+            _ancestors: &[&'a Node<'a>],
+        ) -> Flow<Self::Break> {
+            let text = nodes
+                .get_text_from(self.wikitext)
+                .trim_matches(|c: char| c == ' ' || c == '\t');
+            self.headings.push((text, level));
+            Flow::SkipChildren
+        }
+    }
+
+    let mut visitor = Headings {
+        wikitext,
+        headings: Vec::new(),
+    };
+    let _ = walk(nodes, &mut visitor);
+    visitor.headings
+}
+
+/// Calls `f` with each list of nodes directly inside `node` and stops at the
+/// first error. The lists are in the order in which they appear in the
+/// wikitext, except that the default of a parameter comes before its name.
+/// The nodes inside categories and external links are skipped.
+pub fn for_each_child_list<'a, E, F>(
+    node: &'a Node<'a>,
+    mut f: F,
+) -> Result<(), E>
+where
+    F: FnMut(&'a [Node<'a>]) -> Result<(), E>,
+{
+    match node {
+        Heading { nodes, .. }
+        | Preformatted { nodes, .. }
+        | Tag { nodes, .. }
+        | Image { text: nodes, .. }
+        | Link { text: nodes, .. } => f(nodes),
+        DefinitionList { items, .. } => {
+            items.iter().try_for_each(|item| f(&item.nodes))
+        }
+        OrderedList { items, .. } | UnorderedList { items, .. } => {
+            items.iter().try_for_each(|item| f(&item.nodes))
+        }
+        Parameter { name, default, .. } => {
+            if let Some(default) = default {
+                f(default)?;
+            }
+            f(name)
+        }
+        Table {
+            attributes,
+            captions,
+            rows,
+            ..
+        } => {
+            f(attributes)?;
+            for caption in captions {
+                if let Some(attributes) = &caption.attributes {
+                    f(attributes)?;
+                }
+                f(&caption.content)?;
+            }
+            for row in rows {
+                f(&row.attributes)?;
+                for cell in &row.cells {
+                    if let Some(attributes) = &cell.attributes {
+                        f(attributes)?;
+                    }
+                    f(&cell.content)?;
+                }
+            }
+            Ok(())
+        }
+        Template {
+            name, parameters, ..
+        } => {
+            f(name)?;
+            for parameter in parameters {
+                if let Some(name) = &parameter.name {
+                    f(name)?;
+                }
+                f(&parameter.value)?;
+            }
+            Ok(())
+        }
+        Bold { .. }
+        | BoldItalic { .. }
+        | Category { .. }
+        | CharacterEntity { .. }
+        | Comment { .. }
+        | EndTag { .. }
+        | ExternalLink { .. }
+        | HorizontalDivider { .. }
+        | Italic { .. }
+        | MagicWord { .. }
+        | ParagraphBreak { .. }
+        | Redirect { .. }
+        | StartTag { .. }
+        | Text { .. } => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{headings, walk, Flow, TemplateParameter, Visitor};
+    use parse_wiki_text::{Configuration, Node, Positioned};
+
+    // Records the text of templates as they are left, along with the names
+    // of the kinds of their ancestors.
+    struct Templates<'a> {
+        wikitext: &'a str,
+        templates: Vec<(&'a str, Vec<&'static str>)>,
+        skip_headings: bool,
+        stop_at: Option<&'a str>,
+    }
+
+    fn kind(node: &Node) -> &'static str {
+        match node {
+            Node::Heading { .. } => "heading",
+            Node::Link { .. } => "link",
+            Node::Template { .. } => "template",
+            Node::UnorderedList { .. } => "list",
+            _ => "other",
+        }
+    }
+
+    impl<'a> Visitor<'a> for Templates<'a> {
+        type Break = &'a str;
+
+        fn enter(
+            &mut self,
+            node: &'a Node<'a>,
+            _ancestors: &[&'a Node<'a>],
+        ) -> Flow<Self::Break> {
+            match node {
+                Node::Heading { .. } if self.skip_headings => {
+                    Flow::SkipChildren
+                }
+                _ => Flow::Continue,
+            }
+        }
+
+        fn leave(
+            &mut self,
+            node: &'a Node<'a>,
+            ancestors: &[&'a Node<'a>],
+        ) -> Result<(), Self::Break> {
+            if let Node::Template { .. } = node {
+                let text = node.get_text_from(self.wikitext);
+                let ancestors = ancestors.iter().map(|n| kind(n)).collect();
+                self.templates.push((text, ancestors));
+                if self.stop_at == Some(text) {
+                    return Err(text);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    type Visited<'a> =
+        (Vec<(&'a str, Vec<&'static str>)>, Result<(), &'a str>);
+
+    fn visit<'a>(
+        wikitext: &'a str,
+        nodes: &'a [Node<'a>],
+        skip_headings: bool,
+        stop_at: Option<&'a str>,
+    ) -> Visited<'a> {
+        let mut visitor = Templates {
+            wikitext,
+            templates: Vec::new(),
+            skip_headings,
+            stop_at,
+        };
+        let result = walk(nodes, &mut visitor);
+        (visitor.templates, result)
+    }
+
+    #[test]
+    fn walk_nodes() {
+        let configuration = Configuration::default();
+        let wikitext =
+            concat!("==={{a}}===\n", "* [[link|{{b|{{c}} }}]]\n", "{{d}}");
+        let output = configuration.parse(wikitext);
+        let (templates, result) = visit(wikitext, &output.nodes, false, None);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            templates,
+            vec![
+                ("{{a}}", vec!["heading"]),
+                ("{{c}}", vec!["list", "link", "template"]),
+                ("{{b|{{c}} }}", vec!["list", "link"]),
+                ("{{d}}", vec![]),
+            ]
+        );
+
+        let (templates, _) = visit(wikitext, &output.nodes, true, None);
+        assert_eq!(
+            templates.iter().map(|(t, _)| *t).collect::<Vec<_>>(),
+            vec!["{{c}}", "{{b|{{c}} }}", "{{d}}"]
+        );
+
+        let (templates, result) =
+            visit(wikitext, &output.nodes, false, Some("{{c}}"));
+        assert_eq!(result, Err("{{c}}"));
+        assert_eq!(templates.len(), 2);
+    }
+
+    // Records the hooks that are called, with the text of the nodes.
+    struct Hooks<'a> {
+        wikitext: &'a str,
+        calls: Vec<String>,
+    }
+
+    impl<'a> Hooks<'a> {
+        fn record(&mut self, hook: &str, node: &Node) {
+            let text = node.get_text_from(self.wikitext);
+            self.calls.push(format!("{} {}", hook, text));
+        }
+    }
+
+    impl<'a> Visitor<'a> for Hooks<'a> {
+        type Break = ();
+
+        fn enter_template(
+            &mut self,
+            node: &'a Node<'a>,
+            _name: &'a [Node<'a>],
+            _parameters: &'a [TemplateParameter<'a>],
+            _ancestors: &[&'a Node<'a>],
+        ) -> Flow<Self::Break> {
+            self.record("enter_template", node);
+            Flow::Continue
+        }
+
+        fn leave_template(
+            &mut self,
+            node: &'a Node<'a>,
+            _name: &'a [Node<'a>],
+            _parameters: &'a [TemplateParameter<'a>],
+            _ancestors: &[&'a Node<'a>],
+        ) -> Result<(), Self::Break> {
+            self.record("leave_template", node);
+            Ok(())
+        }
+
+        fn enter_heading(
+            &mut self,
+            node: &'a Node<'a>,
+            level: u8,
+            _nodes: &'a [Node<'a>],
+            ancestors: &[&'a Node<'a>],
+        ) -> Flow<Self::Break> {
+            assert_eq!((level, ancestors.len()), (2, 0));
+            self.record("enter_heading", node);
+            Flow::SkipChildren
+        }
+
+        fn enter_comment(
+            &mut self,
+            node: &'a Node<'a>,
+            ancestors: &[&'a Node<'a>],
+        ) -> Flow<Self::Break> {
+            self.record("enter_comment", node);
+            if ancestors.is_empty() {
+                Flow::Continue
+            } else {
+                Flow::Break(())
+            }
+        }
+    }
+
+    fn text(wikitext: &str, start: usize, end: usize) -> Node<'_> {
+        Node::Text {
+            start,
+            end,
+            value: &wikitext[start..end],
+        }
+    }
+
+    fn template<'a>(
+        wikitext: &'a str,
+        start: usize,
+        end: usize,
+        parameters: Vec<TemplateParameter<'a>>,
+    ) -> Node<'a> {
+        Node::Template {
+            start,
+            end,
+            name: vec![text(wikitext, start + 2, start + 3)],
+            parameters,
+        }
+    }
+
+    // The nodes are built by hand because the parser doesn't produce
+    // parameters with defaults inside templates.
+    #[test]
+    fn hooks_and_order() {
+        let wikitext = concat!(
+            "<!--c-->==h{{h}}==",
+            "[[Category:c{{k}}]][http://x {{x}}]",
+            "{{{p{{n}}|{{d}}}}}",
+        );
+        let parameter = Node::Parameter {
+            start: 53,
+            end: 71,
+            name: vec![
+                text(wikitext, 56, 57),
+                template(wikitext, 57, 62, vec![]),
+            ],
+            default: Some(vec![template(wikitext, 63, 68, vec![])]),
+        };
+        let nodes = vec![
+            Node::Comment { start: 0, end: 8 },
+            Node::Heading {
+                start: 8,
+                end: 18,
+                level: 2,
+                nodes: vec![
+                    text(wikitext, 10, 11),
+                    template(wikitext, 11, 16, vec![]),
+                ],
+            },
+            Node::Category {
+                start: 18,
+                end: 37,
+                target: "Category:c",
+                ordinal: vec![template(wikitext, 30, 35, vec![])],
+            },
+            Node::ExternalLink {
+                start: 37,
+                end: 53,
+                nodes: vec![
+                    text(wikitext, 38, 47),
+                    template(wikitext, 47, 52, vec![]),
+                ],
+            },
+            parameter,
+        ];
+        let mut hooks = Hooks {
+            wikitext,
+            calls: Vec::new(),
+        };
+        assert_eq!(walk(&nodes, &mut hooks), Ok(()));
+        assert_eq!(
+            hooks.calls,
+            vec![
+                "enter_comment <!--c-->",
+                "enter_heading ==h{{h}}==",
+                "enter_template {{d}}",
+                "leave_template {{d}}",
+                "enter_template {{n}}",
+                "leave_template {{n}}",
+            ]
+        );
+
+        // A comment inside a template stops the walk.
+        let wikitext = "{{t|<!--c-->}}{{u}}";
+        let nodes = vec![
+            template(
+                wikitext,
+                0,
+                14,
+                vec![TemplateParameter {
+                    start: 4,
+                    end: 12,
+                    name: None,
+                    value: vec![Node::Comment { start: 4, end: 12 }],
+                }],
+            ),
+            template(wikitext, 14, 19, vec![]),
+        ];
+        let mut hooks = Hooks {
+            wikitext,
+            calls: Vec::new(),
+        };
+        assert_eq!(walk(&nodes, &mut hooks), Err(()));
+        assert_eq!(
+            hooks.calls,
+            vec!["enter_template {{t|<!--c-->}}", "enter_comment <!--c-->"]
+        );
+    }
+
+    #[test]
+    fn headings_in_nodes() {
+        let wikitext = "==English\u{a0}==\n{{t|\n=== Noun\t===\n}}";
+        let heading = |start, end, level, text_end| Node::Heading {
+            start,
+            end,
+            level,
+            nodes: vec![text(wikitext, start + level as usize, text_end)],
+        };
+        let nodes = vec![
+            heading(0, 13, 2, 11),
+            text(wikitext, 13, 14),
+            template(
+                wikitext,
+                14,
+                34,
+                vec![TemplateParameter {
+                    start: 18,
+                    end: 32,
+                    name: None,
+                    value: vec![
+                        text(wikitext, 18, 19),
+                        heading(19, 31, 3, 28),
+                        text(wikitext, 31, 32),
+                    ],
+                }],
+            ),
+        ];
+        assert_eq!(
+            headings(wikitext, &nodes),
+            vec![("English\u{a0}", 2), ("Noun", 3)]
+        );
+    }
+}
@@ -1,4 +1,3 @@
-use bzip2::bufread::BzDecoder;
 use getopts::Options;
 use rlua::{
     Context, Function, Lua, Result as LuaResult,
@@ -9,7 +8,8 @@ use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
 use std::str::FromStr;
 use unicase::UniCase;
-use dump_parser::Namespace;
+use dump_parser::{Namespace, NamespaceTable};
+use template_iter::Title;
 
 #[macro_export]
 macro_rules! exit_with_error {
@@ -31,22 +31,25 @@ use process_comments_with_headers::process_comments_and_headers_with_function;
 mod process_headers;
 use process_headers::process_headers_with_function;
 
-struct Page(dump_parser::Page);
-
-impl From<dump_parser::Page> for Page {
-    fn from(page: dump_parser::Page) -> Self {
-        Page(page)
-    }
+struct Page<'a> {
+    page: dump_parser::Page,
+    namespace_table: &'a NamespaceTable,
 }
 
-impl<'lua, 'a> ToLua<'lua> for Page {
+impl<'lua, 'a> ToLua<'lua> for Page<'a> {
     fn to_lua(self, lua: Context<'lua>) -> LuaResult<Value<'lua>> {
-        let page = self.0;
+        let Page {
+            page,
+            namespace_table,
+        } = self;
         let table = lua.create_table()?;
         table.set("title", page.title)?;
         table.set("text", page.text)?;
-        let namespace_str = page.namespace.as_str();
-        table.set("namespace", namespace_str)?;
+        // Namespaces missing from the table are given by number.
+        match namespace_table.name(page.namespace) {
+            Some(name) => table.set("namespace", name)?,
+            None => table.set("namespace", i32::from(page.namespace))?,
+        }
         if let Some(format) = page.format {
             table.set("format", format)?;
         }
@@ -96,6 +99,7 @@ fn process_text_with_function<R: BufRead>(
     dump_file: R,
     process_page: Function,
     namespaces: HashSet<Namespace>,
+    namespace_table: &NamespaceTable,
 ) -> LuaResult<()> {
     let parser = dump_parser::parse(dump_file).map(|result| {
         result.unwrap_or_else(|e| {
@@ -104,7 +108,10 @@ fn process_text_with_function<R: BufRead>(
     });
     for page in parser {
         if namespaces.contains(&page.namespace) {
-            let continue_parsing: bool = process_page.call(Page::from(page))?;
+            let continue_parsing: bool = process_page.call(Page {
+                page,
+                namespace_table,
+            })?;
             if !continue_parsing {
                 break;
             }
@@ -202,13 +209,25 @@ fn main() {
     let mut options = Options::new();
     options.optopt("s", "script", "Lua script", "FILE");
     options.optopt("e", "eval", "Lua code", "TEXT");
-    options.optopt("i", "dump", "XML page dump file", "FILE");
+    options.optopt(
+        "i",
+        "dump",
+        "XML page dump file, optionally compressed, or - for standard input",
+        "FILE",
+    );
     options.optmulti(
         "n",
         "namespaces",
         "list of namespaces (names or numbers) to process",
         "NS",
     );
+    options.optopt(
+        "",
+        "siteinfo",
+        "siteinfo JSON with namespaces and parser configuration \
+         (default: read namespaces from the dump)",
+        "FILE",
+    );
     options.optmulti("t", "templates", "list of templates", "TEMPLATES");
     options.optmulti(
         "T",
@@ -237,24 +256,6 @@ fn main() {
         exit_with_error!("--templates or --template-file only allowed with subcommand templates or templates-and-headers");
     }
 
-    let namespace_args = matches.opt_strs("namespaces");
-    let (mut namespaces, mut failures) = (Vec::new(), Vec::<&str>::new());
-    for namespace_arg in &namespace_args {
-        match namespace_arg.parse::<Namespace>() {
-            Ok(n) => namespaces.push(n),
-            Err(_) => failures.push(namespace_arg),
-        }
-    }
-    if !failures.is_empty() {
-        exit_with_error!(
-            "invalid namespace{}: {}",
-            if failures.len() == 1 { "" } else { "s" },
-            failures.join(", ")
-        );
-    } else if namespaces.is_empty() {
-        namespaces.push(Namespace::Main);
-    }
-
     let (script, name, eval) = if matches.opt_present("eval") {
         let script = matches.opt_str("eval").unwrap();
         let name = "(command line)".to_string();
@@ -277,13 +278,43 @@ fn main() {
         exit_with_error!("Either code or a script file is required.");
     };
 
-    let dump_filename = matches
-        .opt_str("dump")
-        .unwrap_or_else(|| "pages-articles.xml".into());
+    let dump_filename = matches.opt_str("dump");
+    let dump = dump_parser::open_dump(dump_filename.as_ref(), 1)
+        .unwrap_or_else(|e| {
+            exit_with_error!(
+                "could not open dump file '{}': {}",
+                dump_filename.as_deref().unwrap_or("(default)"),
+                e
+            )
+        });
+    let siteinfo_path = matches.opt_str("siteinfo");
+    let (namespace_table, dump) =
+        dump_parser::load_namespace_table(siteinfo_path.as_ref(), dump)
+            .unwrap_or_else(|e| {
+                exit_with_error!("could not load namespaces: {}", e)
+            });
+    let configuration = dump_parser::load_configuration(siteinfo_path)
+        .unwrap_or_else(|e| {
+            exit_with_error!("could not load parser configuration: {}", e)
+        });
 
-    let dump = File::open(&dump_filename).unwrap_or_else(|e| {
-        exit_with_error!("could not open dump file '{}': {}", &dump_filename, e)
-    });
+    let namespace_args = matches.opt_strs("namespaces");
+    let (mut namespaces, mut failures) = (Vec::new(), Vec::<&str>::new());
+    for namespace_arg in &namespace_args {
+        match namespace_table.lookup(namespace_arg) {
+            Some(n) => namespaces.push(n),
+            None => failures.push(namespace_arg),
+        }
+    }
+    if !failures.is_empty() {
+        exit_with_error!(
+            "invalid namespace{}: {}",
+            if failures.len() == 1 { "" } else { "s" },
+            failures.join(", ")
+        );
+    } else if namespaces.is_empty() {
+        namespaces.push(Namespace::Main);
+    }
 
     let namespaces: HashSet<_> = namespaces.into_iter().collect();
 
@@ -309,6 +340,17 @@ fn main() {
                 "Either --templates or --template-file is required"
             );
         };
+        // Normalized as the names of templates in pages are.
+        let templates = templates
+            .into_iter()
+            .map(|template| {
+                Title::new(&template, Namespace::Template, &namespace_table)
+                    .map(Title::into_name)
+                    .unwrap_or_else(|e| {
+                        exit_with_error!("invalid template {}: {}", template, e)
+                    })
+            })
+            .collect();
         Some(templates)
     } else {
         None
@@ -350,74 +392,48 @@ fn main() {
         }?;
 
         let dump = BufReader::new(dump);
-        if dump_filename.ends_with(".bz2") {
-            let dump = BzDecoder::new(dump);
-            let dump = BufReader::new(dump);
-            match subcommand {
-                Subcommand::Text => {
-                    process_text_with_function(dump, process_page, namespaces)
-                }
-                Subcommand::Templates => process_templates_with_function(
+        match subcommand {
+            Subcommand::Text => {
+                process_text_with_function(
                     dump,
                     process_page,
                     namespaces,
-                    templates.unwrap(),
-                ),
-                Subcommand::TemplatesAndHeaders => {
-                    process_templates_and_headers_with_function(
-                        dump,
-                        process_page,
-                        namespaces,
-                        templates.unwrap(),
-                    )
-                }
-                Subcommand::CommentsAndHeaders => {
-                    process_comments_and_headers_with_function(
-                        dump,
-                        process_page,
-                        namespaces,
-                    )
-                }
-                Subcommand::Headers => process_headers_with_function(
-                    dump,
-                    process_page,
-                    namespaces,
-                ),
-                _ => Ok(()),
+                    &namespace_table,
+                )
             }
-        } else {
-            match subcommand {
-                Subcommand::Text => {
-                    process_text_with_function(dump, process_page, namespaces)
-                }
-                Subcommand::Templates => process_templates_with_function(
+            Subcommand::Templates => process_templates_with_function(
+                dump,
+                process_page,
+                namespaces,
+                templates.unwrap(),
+                &configuration,
+                &namespace_table,
+            ),
+            Subcommand::TemplatesAndHeaders => {
+                process_templates_and_headers_with_function(
                     dump,
                     process_page,
                     namespaces,
                     templates.unwrap(),
-                ),
-                Subcommand::TemplatesAndHeaders => {
-                    process_templates_and_headers_with_function(
-                        dump,
-                        process_page,
-                        namespaces,
-                        templates.unwrap(),
-                    )
-                }
-                Subcommand::CommentsAndHeaders => {
-                    process_comments_and_headers_with_function(
-                        dump,
-                        process_page,
-                        namespaces,
-                    )
-                }
-                Subcommand::Headers => process_headers_with_function(
+                    &configuration,
+                    &namespace_table,
+                )
+            }
+            Subcommand::CommentsAndHeaders => {
+                process_comments_and_headers_with_function(
                     dump,
                     process_page,
                     namespaces,
-                ),
-                _ => Ok(()),
+                    &configuration,
+                )
             }
+            Subcommand::Headers => process_headers_with_function(
+                dump,
+                process_page,
+                namespaces,
+                &configuration,
+            ),
+            _ => Ok(()),
         }?;
 
         Ok(())
@@ -1,22 +1,24 @@
 use dump_parser::{
-    parse_wiki_text::Positioned, wiktionary_configuration, Node,
+    parse_wiki_text::Positioned, Configuration, Node, Parameter,
 };
 use rlua::{
     Context, Error as LuaError, Function, Result as LuaResult, ToLua, Value,
 };
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashSet};
-use std::convert::From;
+use std::convert::{From, Infallible};
 use std::io::BufRead;
-use std::ops::{Deref, DerefMut, Index, IndexMut};
 use std::result::Result as StdResult;
 use std::string::ToString;
 use string_wrapper::StringWrapper;
 use template_iter::{
-    normalize_title,
-    parse_wiki_text_ext::template_parameters::{self, ParameterKey},
+    parse_wiki_text_ext::{
+        template_parameters::{self, ParameterKey},
+        walk, Section, Visitor as NodeVisitor,
+    },
+    Title,
 };
-use dump_parser::Namespace;
+use dump_parser::{Namespace, NamespaceTable};
 
 use crate::exit_with_error;
 
@@ -32,17 +34,12 @@ pub struct BorrowedTemplateWithText<'a> {
 impl<'a> BorrowedTemplateWithText<'a> {
     pub fn new(
         wikitext: &'a str,
-        name: &'a str,
+        name: &str,
         parameters: &'a [dump_parser::Parameter<'a>],
         template: &'a Node,
     ) -> StdResult<Self, &'static str> {
-        let name = if let Ok(Some(name)) =
-            normalize_title(name).map(|n| StringWrapper::from_str_safe(&n))
-        {
-            name
-        } else {
-            return Err("invalid template name");
-        };
+        let name = StringWrapper::from_str_safe(name)
+            .ok_or("template name too long")?;
         let parameters = template_parameters::enumerate(parameters)
             .map(|(key, value)| {
                 let key = match key {
@@ -87,56 +84,63 @@ impl<'lua, 'a, 'b> ToLua<'lua> for SliceOfBorrowedTemplateWithText<'a, 'b> {
     }
 }
 
-const LOWEST_HEADER: usize = 1;
-const HIGHEST_HEADER: usize = 6;
-struct HeaderStack<'a>([Option<&'a str>; HIGHEST_HEADER]);
-
-impl<'a> HeaderStack<'a> {
-    fn new() -> Self {
-        HeaderStack([None; HIGHEST_HEADER])
-    }
-}
-
-impl<'a> Deref for HeaderStack<'a> {
-    type Target = [Option<&'a str>; HIGHEST_HEADER];
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-impl<'a> DerefMut for HeaderStack<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
-    }
-}
-
-impl<'a> Index<usize> for HeaderStack<'a> {
-    type Output = Option<&'a str>;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.0[index - LOWEST_HEADER]
-    }
+// The normalized name of the template that a template node transcludes, if
+// it is in the Template namespace and in `template_filter`.
+pub fn filtered_template_name(
+    name: &str,
+    template_filter: &HashSet<String>,
+    namespace_table: &NamespaceTable,
+) -> Option<String> {
+    Title::new(name, Namespace::Template, namespace_table)
+        .ok()
+        .filter(|title| {
+            title.namespace() == Namespace::Template
+                && template_filter.contains(title.name())
+        })
+        .map(Title::into_name)
 }
 
-impl<'a> IndexMut<usize> for HeaderStack<'a> {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.0[index - LOWEST_HEADER]
-    }
-}
+// The headers of a section and the sections that contain it, as a table
+// from level to header text.
+pub struct SectionPath<'a, 'b>(pub &'b [&'b Section<'a>]);
 
-impl<'a, 'b, 'lua> ToLua<'lua> for &'a HeaderStack<'b> {
+impl<'lua> ToLua<'lua> for SectionPath<'_, '_> {
     fn to_lua(self, lua: Context<'lua>) -> LuaResult<Value<'lua>> {
         let table = lua.create_table()?;
-        for i in LOWEST_HEADER..HIGHEST_HEADER {
-            table.set(i, self[i])?;
+        for section in self.0 {
+            if let Some(heading) = section.heading {
+                table.set(section.level, heading)?;
+            }
         }
         Ok(Value::Table(table))
     }
 }
 
-// This error type is solely to make it easier to exit from Visitor::do_visit.
-// Also used by process_templates.
+// Calls `func` with the items that `collect` finds in each section of a page
+// that has any, along with the path to the section.
+pub fn for_each_section<'a, T, C, F>(
+    wikitext: &'a str,
+    nodes: &'a [Node<'a>],
+    mut collect: C,
+    mut func: F,
+) -> LuaResult<bool>
+where
+    C: FnMut(&'a [Node<'a>]) -> Vec<T>,
+    F: FnMut(&[T], SectionPath) -> LuaResult<bool>,
+{
+    let root = Section::new(wikitext, nodes);
+    let result = root.try_for_each_path(|path| {
+        let items = collect(path[path.len() - 1].nodes);
+        if items.is_empty() {
+            return Ok(());
+        }
+        VisitError::check(func(&items, SectionPath(path)))
+    });
+    VisitError::into_result(result)
+}
+
+// This error type is solely to make it easier to exit from the walk over
+// the nodes. Also used by the other visitors.
 pub enum VisitError {
     LuaError(LuaError),
     StopParsing,
@@ -148,170 +152,77 @@ impl From<LuaError> for VisitError {
     }
 }
 
-struct Visitor<'a, 'b> {
+impl VisitError {
+    // Stops the walk if the Lua function returned false or an error.
+    pub fn check(continue_parsing: LuaResult<bool>) -> StdResult<(), Self> {
+        if continue_parsing? {
+            Ok(())
+        } else {
+            Err(VisitError::StopParsing)
+        }
+    }
+
+    // Converts the result of the walk back into whether to continue parsing.
+    pub fn into_result(result: StdResult<(), Self>) -> LuaResult<bool> {
+        match result {
+            Ok(()) => Ok(true),
+            Err(VisitError::StopParsing) => Ok(false),
+            Err(VisitError::LuaError(e)) => Err(e),
+        }
+    }
+}
+
+// Collects the templates in the filter in the order in which they end.
+struct Templates<'a, 'b> {
     wikitext: &'a str,
-    templates: Vec<BorrowedTemplateWithText<'a>>,
-    headers: HeaderStack<'a>,
     template_filter: &'b HashSet<String>,
+    namespace_table: &'b NamespaceTable,
+    templates: Vec<BorrowedTemplateWithText<'a>>,
 }
 
-impl<'a, 'b> Visitor<'a, 'b> {
-    pub fn new(
+impl<'a, 'b> Templates<'a, 'b> {
+    fn collect(
         wikitext: &'a str,
         template_filter: &'b HashSet<String>,
-    ) -> Self {
-        Visitor {
+        namespace_table: &'b NamespaceTable,
+        nodes: &'a [Node<'a>],
+    ) -> Vec<BorrowedTemplateWithText<'a>> {
+        let mut visitor = Templates {
             wikitext,
-            templates: Vec::new(),
-            headers: HeaderStack::new(),
             template_filter,
-        }
-    }
-
-    fn visit<F>(
-        &mut self,
-        nodes: &'a [Node<'a>],
-        func: &mut F,
-    ) -> LuaResult<bool>
-    where
-        F: FnMut(
-            &[BorrowedTemplateWithText],
-            &HeaderStack<'a>,
-        ) -> LuaResult<bool>,
-    {
-        match self.do_visit(nodes, func) {
-            Err(VisitError::LuaError(e)) => return Err(e),
-            Err(VisitError::StopParsing) | Ok(false) => return Ok(false),
-            _ => (),
+            namespace_table,
+            templates: Vec::new(),
         };
-        // Process templates in the last section.
-        if !self.templates.is_empty() {
-            let result = func(self.templates.as_slice(), &self.headers);
-            self.templates.clear();
-            result
-        } else {
-            Ok(true)
-        }
+        let _ = walk(nodes, &mut visitor);
+        visitor.templates
     }
+}
 
-    fn do_visit<F>(
-        &mut self,
-        nodes: &'a [Node<'a>],
-        func: &mut F,
-    ) -> StdResult<bool, VisitError>
-    where
-        F: FnMut(
-            &[BorrowedTemplateWithText],
-            &HeaderStack<'a>,
-        ) -> LuaResult<bool>,
-    {
-        use dump_parser::Node::*;
-        for node in nodes {
-            match node {
-                DefinitionList { items, .. } => {
-                    for item in items {
-                        self.do_visit(&item.nodes, func)?;
-                    }
-                }
-                Heading { nodes, level, .. } => {
-                    // Process all templates under the previously encountered header
-                    // (or at the beginning of the page).
-                    if !self.templates.is_empty() {
-                        let continue_parsing =
-                            func(self.templates.as_slice(), &self.headers)?;
-                        if !continue_parsing {
-                            return Err(VisitError::StopParsing);
-                        }
-                    }
-                    self.templates.clear();
-                    let level = *level as usize;
-                    self.headers[level] =
-                        Some(&nodes.get_text_from(&self.wikitext));
-                    for i in level + 1..HIGHEST_HEADER {
-                        self.headers[i] = None;
-                    }
+impl<'a, 'b> NodeVisitor<'a> for Templates<'a, 'b> {
+    type Break = Infallible;
 
-                    self.do_visit(&nodes, func)?;
-                }
-                Preformatted { nodes, .. } | Tag { nodes, .. } => {
-                    self.do_visit(&nodes, func)?;
-                }
-                Image { text, .. } | Link { text, .. } => {
-                    self.do_visit(&text, func)?;
-                }
-                OrderedList { items, .. } | UnorderedList { items, .. } => {
-                    for item in items {
-                        self.do_visit(&item.nodes, func)?;
-                    }
-                }
-                Parameter { name, default, .. } => {
-                    if let Some(nodes) = default {
-                        self.do_visit(&nodes, func)?;
-                    }
-                    self.do_visit(&name, func)?;
-                }
-                Table {
-                    attributes,
-                    captions,
-                    rows,
-                    ..
-                } => {
-                    self.do_visit(&attributes, func)?;
-                    for caption in captions {
-                        if let Some(attributes) = &caption.attributes {
-                            self.do_visit(attributes, func)?;
-                        }
-                        self.do_visit(&caption.content, func)?;
-                    }
-                    for row in rows {
-                        self.do_visit(&row.attributes, func)?;
-                        for cell in &row.cells {
-                            if let Some(attributes) = &cell.attributes {
-                                self.do_visit(attributes, func)?;
-                            }
-                            self.do_visit(&cell.content, func)?;
-                        }
-                    }
-                }
-                Template {
-                    name, parameters, ..
-                } => {
-                    self.do_visit(&name, func)?;
-                    for parameter in parameters {
-                        if let Some(name) = &parameter.name {
-                            self.do_visit(name, func)?;
-                        }
-                        self.do_visit(&parameter.value, func)?;
-                    }
-                    let name = name.get_text_from(&self.wikitext);
-                    if self.template_filter.contains(name) {
-                        if let Ok(template) = BorrowedTemplateWithText::new(
-                            &self.wikitext,
-                            &name,
-                            &parameters,
-                            &node,
-                        ) {
-                            self.templates.push(template);
-                        }
-                    }
-                }
-                Bold { .. }
-                | BoldItalic { .. }
-                | Category { .. }
-                | CharacterEntity { .. }
-                | Comment { .. }
-                | EndTag { .. }
-                | ExternalLink { .. }
-                | HorizontalDivider { .. }
-                | Italic { .. }
-                | MagicWord { .. }
-                | ParagraphBreak { .. }
-                | Redirect { .. }
-                | StartTag { .. }
-                | Text { .. } => {}
+    fn leave_template(
+        &mut self,
+        node: &'a Node<'a>,
+        name: &'a [Node<'a>],
+        parameters: &'a [Parameter<'a>],
+        _ancestors: &[&'a Node<'a>],
+    ) -> StdResult<(), Self::Break> {
+        if let Some(name) = filtered_template_name(
+            name.get_text_from(self.wikitext),
+            self.template_filter,
+            self.namespace_table,
+        ) {
+            if let Ok(template) = BorrowedTemplateWithText::new(
+                self.wikitext,
+                &name,
+                parameters,
+                node,
+            ) {
+                self.templates.push(template);
             }
         }
-        Ok(true)
+        Ok(())
     }
 }
 
@@ -320,8 +231,9 @@ pub fn process_templates_and_headers_with_function<R: BufRead>(
     lua_func: Function,
     namespaces: HashSet<Namespace>,
     templates: HashSet<String>,
+    configuration: &Configuration,
+    namespace_table: &NamespaceTable,
 ) -> LuaResult<()> {
-    let configuration = wiktionary_configuration();
     let parser = dump_parser::parse(dump_file).map(|result| {
         result.unwrap_or_else(|e| {
             exit_with_error!("Error while parsing dump: {}", e);
@@ -331,14 +243,23 @@ pub fn process_templates_and_headers_with_function<R: BufRead>(
         if namespaces.contains(&page.namespace) {
             let wikitext = &page.text;
             let parser_output = configuration.parse(&page.text);
-            let continue_parsing = Visitor::new(wikitext, &templates).visit(
+            let continue_parsing = for_each_section(
+                wikitext,
                 &parser_output.nodes,
-                &mut |templates, headers| {
-                    Ok(lua_func.call((
-                        SliceOfBorrowedTemplateWithText(&templates),
+                |nodes| {
+                    Templates::collect(
+                        wikitext,
+                        &templates,
+                        namespace_table,
+                        nodes,
+                    )
+                },
+                |templates, headers| {
+                    lua_func.call((
+                        SliceOfBorrowedTemplateWithText(templates),
                         headers,
                         page.title.as_str(),
-                    ))?)
+                    ))
                 },
             )?;
             if !continue_parsing {
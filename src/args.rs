@@ -1,20 +1,28 @@
-use bzip2::bufread::BzDecoder;
 use std::{
-    collections::HashMap,
-    convert::{AsRef, From},
-    fmt::Display,
+    collections::{HashMap, HashSet},
+    convert::AsRef,
     fs::File,
     io::{BufRead, BufReader, Read},
     path::{Path, PathBuf},
-    rc::Rc,
     result::Result as StdResult,
     str::FromStr,
+    sync::Arc,
 };
 use structopt::clap::{AppSettings::ColoredHelp, Shell};
 use structopt::StructOpt;
-use dump_parser::Namespace;
+use dump_parser::{Configuration, Namespace, NamespaceTable};
+use language_sections::LanguageCodes;
+use serde::Deserialize;
+use template_data::TemplateDataMap;
+use template_iter::Title;
 
+use crate::compression::OutputCompression;
 use crate::error::{Error, Result};
+use crate::query_templates::{Field, Predicate, Query, QueryOutput};
+use crate::run::{read_job_file, JobSpec};
+use crate::template_redirects::{
+    redirect_to_target, redirects_from_dump, RedirectSource,
+};
 
 #[derive(StructOpt)]
 #[structopt(
@@ -34,17 +42,49 @@ enum Command {
     #[structopt(setting(ColoredHelp))]
     DumpParsedTemplates {
         #[structopt(long, short)]
-        /// format: cbor (CBOR stream) or json (JSON Lines)
+        /// format: cbor (CBOR stream), json (JSON Lines) or parquet (if
+        /// built with the parquet feature)
         format: SerializationFormat,
         #[structopt(long = "templates", short, required = true)]
         /// path to file containing template names with optional tab and output filepath
         template_filepaths: Vec<PathBuf>,
+        #[structopt(long)]
+        /// compress the files with gzip or zstd [default: guessed from the
+        /// extension of output filepaths]
+        compression: Option<OutputCompression>,
         #[structopt(long, short = "I")]
         /// whether to include source code of templates
         include_text: bool,
+        #[structopt(long, short = "D")]
+        /// include the keys that were given more than once and the values
+        /// that later ones overrode
+        include_duplicates: bool,
+        #[structopt(long, short = "C")]
+        /// include the byte range of each template, the headings of the
+        /// sections it is in, and how deeply it is nested in other templates
+        /// and the name of the template that contains it
+        include_context: bool,
+        #[structopt(long)]
+        /// print parameter values as lists of text, link and template nodes
+        /// with comments removed, instead of as wikitext
+        parse_values: bool,
         #[structopt(long = "template-normalizations", short = "T")]
         /// JSON file mapping from template name to an array of aliases.
         template_normalization_filepath: Option<PathBuf>,
+        #[structopt(long, short = "R")]
+        /// dump uses of redirects in the Template namespace as uses of the
+        /// templates they redirect to, with the name that was used in alias
+        resolve_redirects: bool,
+        #[structopt(
+            long,
+            requires_all = &["resolve-redirects", "redirect-sql"]
+        )]
+        /// path to page.sql, optionally compressed, to find redirects in
+        /// instead of reading the dump twice
+        page_sql: Option<PathBuf>,
+        #[structopt(long, requires = "page-sql")]
+        /// path to redirect.sql, optionally compressed
+        redirect_sql: Option<PathBuf>,
         #[structopt(flatten)]
         dump_args: DumpArgs,
     },
@@ -53,6 +93,14 @@ enum Command {
         #[structopt(long, short = "P")]
         /// print pretty JSON
         pretty: bool,
+        #[structopt(long)]
+        /// write to this file instead of standard output, as a Parquet table
+        /// if its extension is .parquet
+        output: Option<PathBuf>,
+        #[structopt(long, requires = "output")]
+        /// compress the output file with gzip or zstd [default: guessed from
+        /// its extension]
+        compression: Option<OutputCompression>,
         #[structopt(flatten)]
         dump_args: DumpArgs,
     },
@@ -65,6 +113,184 @@ enum Command {
         #[structopt(long, short = "P")]
         /// print pretty JSON
         pretty: bool,
+        #[structopt(long)]
+        /// write to this file instead of standard output, as a Parquet table
+        /// if its extension is .parquet
+        output: Option<PathBuf>,
+        #[structopt(long, requires = "output")]
+        /// compress the output file with gzip or zstd [default: guessed from
+        /// its extension]
+        compression: Option<OutputCompression>,
+        #[structopt(flatten)]
+        dump_args: DumpArgs,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// count the instances of templates, the pages they are on and the
+    /// parameter names they use in each namespace
+    TemplateStats {
+        #[structopt(long = "templates", short)]
+        /// path to file containing template names, one per line; an output
+        /// filepath after a tab is ignored [default: count all templates]
+        template_filepaths: Vec<PathBuf>,
+        #[structopt(long, short, default_value = "json")]
+        /// format: json or csv
+        format: StatsFormat,
+        #[structopt(long, default_value = "5")]
+        /// number of page titles to list for each template
+        samples: usize,
+        #[structopt(long, short = "P")]
+        /// print pretty JSON
+        pretty: bool,
+        #[structopt(flatten)]
+        dump_args: DumpArgs,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// check the instances of templates against the parameters listed in
+    /// their TemplateData and report unknown, missing required and
+    /// duplicate parameters
+    CheckTemplateData {
+        #[structopt(long, short, default_value = "json")]
+        /// format: json or wikitext
+        format: ReportFormat,
+        #[structopt(long, short = "P")]
+        /// print pretty JSON
+        pretty: bool,
+        #[structopt(flatten)]
+        dump_args: DumpArgs,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// print the titles of pages with templates in which a parameter is
+    /// given more than once, like MediaWiki's duplicate arguments category
+    DuplicateArgs {
+        #[structopt(long)]
+        /// print JSON Lines with the templates and the overridden values
+        json: bool,
+        #[structopt(flatten)]
+        dump_args: DumpArgs,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// write pages, their sections and headers, and templates and their
+    /// parameters to a SQLite database (requires the sqlite feature)
+    ExportSqlite {
+        #[structopt(long, short)]
+        /// path to the database, which must not contain the tables yet
+        output: PathBuf,
+        #[structopt(long)]
+        /// path to page.sql from the same dump, optionally compressed, which
+        /// has the ids of the pages
+        page_sql: PathBuf,
+        #[structopt(long = "templates", short)]
+        /// path to file containing template names, one per line; an output
+        /// filepath after a tab is ignored [default: export all templates]
+        template_filepaths: Vec<PathBuf>,
+        #[structopt(flatten)]
+        dump_args: DumpArgs,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// print the template instances in the output of dump-parsed-templates
+    /// that match some predicates
+    QueryTemplates {
+        #[structopt(required = true)]
+        /// paths to .cbor or .jsonl files written by dump-parsed-templates
+        paths: Vec<PathBuf>,
+        #[structopt(long, short)]
+        /// format of the files [default: guessed from the extension]
+        format: Option<SerializationFormat>,
+        #[structopt(long = "title", short = "T")]
+        /// only print instances in these pages, which are found through the
+        /// index of a CBOR file if it has one
+        titles: Vec<String>,
+        #[structopt(long = "template", short)]
+        /// only print instances of these templates
+        templates: Vec<String>,
+        #[structopt(long = "where", short)]
+        /// has:KEY, missing:KEY, KEY=VALUE, KEY!=VALUE, KEY=~REGEX or
+        /// KEY!~REGEX, which must all be true of an instance
+        predicates: Vec<Predicate>,
+        #[structopt(long, short = "F", value_delimiter = ",")]
+        /// title, name or parameter keys (param:KEY for parameters named
+        /// title or name) to print [default: the whole template]
+        fields: Vec<Field>,
+        #[structopt(long, short, default_value = "json")]
+        /// json, tsv or count (number of instances for each combination of
+        /// fields)
+        output: QueryOutput,
+        #[structopt(long)]
+        /// path to siteinfo JSON with namespaces and namespacealiases, used
+        /// to recognize namespace prefixes in template names [default: the
+        /// namespaces of the English Wiktionary]
+        siteinfo: Option<PathBuf>,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// print the level-2 language sections of pages as JSON Lines
+    SplitLanguages {
+        #[structopt(long, short, value_delimiter = ",")]
+        /// only print sections for these languages (names or codes)
+        languages: Vec<String>,
+        #[structopt(long)]
+        /// JSON object mapping language names to codes [default: read the
+        /// data modules of Module:languages from the dump]
+        language_codes: Option<PathBuf>,
+        #[structopt(flatten)]
+        dump_args: DumpArgs,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// print the text of pages from the multistream dump
+    PageText {
+        #[structopt(
+            long = "input",
+            short = "i",
+            default_value = "pages-articles-multistream.xml.bz2"
+        )]
+        /// path to pages-articles-multistream.xml.bz2
+        dump_filepath: PathBuf,
+        #[structopt(
+            long = "index",
+            short = "x",
+            default_value = "pages-articles-multistream-index.txt.bz2"
+        )]
+        /// path to pages-articles-multistream-index.txt[.bz2]
+        index_filepath: PathBuf,
+        #[structopt(required = true)]
+        /// titles of pages, including namespace prefix
+        titles: Vec<String>,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// add the redirects to the templates in a template names file, so that
+    /// dump-parsed-templates dumps them with the templates they redirect to
+    AddTemplateRedirects {
+        #[structopt(long = "templates", short, required = true)]
+        /// path to file containing template names with optional tab and output filepath
+        template_filepaths: Vec<PathBuf>,
+        #[structopt(long, short = "f", default_value = "%s.cbor")]
+        /// output filepath for templates that don't have one, with %s
+        /// standing for the template name
+        path_format: String,
+        #[structopt(
+            long,
+            requires = "redirect-sql",
+            required_unless = "redirects-json"
+        )]
+        /// path to page.sql, optionally compressed
+        page_sql: Option<PathBuf>,
+        #[structopt(long, requires = "page-sql")]
+        /// path to redirect.sql, optionally compressed
+        redirect_sql: Option<PathBuf>,
+        #[structopt(long, conflicts_with_all = &["page-sql", "redirect-sql"])]
+        /// JSON file mapping from template name to an array of redirects
+        redirects_json: Option<PathBuf>,
+        #[structopt(long)]
+        /// path to siteinfo JSON with namespaces and namespacealiases, used
+        /// to recognize namespace prefixes in template names [default: the
+        /// namespaces of the English Wiktionary]
+        siteinfo: Option<PathBuf>,
+    },
+    #[structopt(setting(ColoredHelp))]
+    /// run the extractors listed in a TOML or JSON job file
+    /// in one pass over the dump
+    Run {
+        /// path to job file
+        job_file: PathBuf,
         #[structopt(flatten)]
         dump_args: DumpArgs,
     },
@@ -72,9 +298,13 @@ enum Command {
     Completions { shell: Shell },
 }
 
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SerializationFormat {
     Cbor,
     Json,
+    #[cfg(feature = "parquet")]
+    Parquet,
 }
 
 impl FromStr for SerializationFormat {
@@ -84,6 +314,48 @@ impl FromStr for SerializationFormat {
         let format = match s.to_lowercase().as_str() {
             "json" => SerializationFormat::Json,
             "cbor" => SerializationFormat::Cbor,
+            #[cfg(feature = "parquet")]
+            "parquet" => SerializationFormat::Parquet,
+            #[cfg(not(feature = "parquet"))]
+            "parquet" => return Err("built without the parquet feature"),
+            _ => return Err("unrecognized format"),
+        };
+        Ok(format)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum StatsFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for StatsFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        let format = match s.to_lowercase().as_str() {
+            "csv" => StatsFormat::Csv,
+            "json" => StatsFormat::Json,
+            _ => return Err("unrecognized format"),
+        };
+        Ok(format)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ReportFormat {
+    Json,
+    Wikitext,
+}
+
+impl FromStr for ReportFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        let format = match s.to_lowercase().as_str() {
+            "json" => ReportFormat::Json,
+            "wikitext" => ReportFormat::Wikitext,
             _ => return Err("unrecognized format"),
         };
         Ok(format)
@@ -93,14 +365,27 @@ impl FromStr for SerializationFormat {
 #[derive(StructOpt, Clone)]
 struct DumpArgs {
     #[structopt(long, short, value_delimiter = ",", default_value = "main")]
-    /// namespace to process
-    namespaces: Vec<Namespace>,
+    /// namespace to process (name, alias or number); with run,
+    /// the default for jobs that don't list namespaces
+    namespaces: Vec<String>,
     #[structopt(short, long)]
-    /// number of pages to process [default: unlimited]
+    /// number of pages to read from the dump, including those outside
+    /// the namespaces [default: unlimited]
     pages: Option<usize>,
-    /// path to pages-articles.xml[.bz2] or pages-meta-current.xml[.bz2]
+    /// path to pages-articles.xml or pages-meta-current.xml, optionally
+    /// compressed with bzip2, gzip, xz or zstd, or - for standard input
     #[structopt(long = "input", short = "i")]
     dump_filepath: Option<PathBuf>,
+    #[structopt(long, short)]
+    /// number of threads that parse wikitext and decompress bzip2 dumps
+    /// [default: number of CPUs]
+    jobs: Option<usize>,
+    #[structopt(long)]
+    /// path to siteinfo JSON with general, namespaces, namespacealiases,
+    /// extensiontags, magicwords and protocols, which configures the
+    /// wikitext parser [default: read namespaces from the dump and use
+    /// the built-in parser configuration]
+    siteinfo: Option<PathBuf>,
 }
 
 pub struct Opts {
@@ -112,12 +397,62 @@ pub enum CommandData {
     DumpParsedTemplates(DumpParsedTemplates),
     AllHeaders {
         pretty: bool,
+        output: Option<PathBuf>,
+        compression: Option<OutputCompression>,
         dump_options: DumpOptions,
     },
     FilterHeaders {
         top_level_headers: Vec<String>,
         other_headers: Vec<String>,
         pretty: bool,
+        output: Option<PathBuf>,
+        compression: Option<OutputCompression>,
+        dump_options: DumpOptions,
+    },
+    TemplateStats {
+        templates: Option<HashSet<String>>,
+        format: StatsFormat,
+        samples: usize,
+        pretty: bool,
+        namespace_table: NamespaceTable,
+        dump_options: DumpOptions,
+    },
+    CheckTemplateData {
+        template_data: TemplateDataMap,
+        format: ReportFormat,
+        pretty: bool,
+        namespace_table: NamespaceTable,
+        dump_options: DumpOptions,
+    },
+    DuplicateArgs {
+        json: bool,
+        namespace_table: NamespaceTable,
+        dump_options: DumpOptions,
+    },
+    ExportSqlite(ExportSqlite),
+    QueryTemplates {
+        paths: Vec<PathBuf>,
+        format: Option<SerializationFormat>,
+        query: Query,
+    },
+    SplitLanguages {
+        languages: Vec<String>,
+        language_codes: LanguageCodes,
+        dump_options: DumpOptions,
+    },
+    PageText {
+        dump_path: PathBuf,
+        index_path: PathBuf,
+        titles: Vec<String>,
+    },
+    AddTemplateRedirects {
+        templates: Vec<(String, Option<String>)>,
+        path_format: String,
+        redirects: RedirectSource,
+        namespace_table: NamespaceTable,
+    },
+    Run {
+        jobs: Vec<Job>,
         dump_options: DumpOptions,
     },
     Completions {
@@ -125,18 +460,68 @@ pub enum CommandData {
     },
 }
 
+pub struct Job {
+    pub namespaces: Vec<Namespace>,
+    pub kind: JobKind,
+}
+
+pub enum JobKind {
+    AllHeaders {
+        output: PathBuf,
+        pretty: bool,
+        compression: Option<OutputCompression>,
+    },
+    FilterHeaders {
+        output: PathBuf,
+        pretty: bool,
+        compression: Option<OutputCompression>,
+        top_level_headers: Vec<String>,
+        other_headers: Vec<String>,
+    },
+    DumpParsedTemplates {
+        output_dir: PathBuf,
+        templates: TemplateDumpOptions,
+    },
+}
+
 pub struct DumpParsedTemplates {
+    pub templates: TemplateDumpOptions,
+    pub dump_options: DumpOptions,
+}
+
+// Only read by the `sqlite` module.
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub struct ExportSqlite {
+    pub output: PathBuf,
+    /// Normalized template names, or `None` for all templates.
+    pub templates: Option<HashSet<String>>,
+    /// `page.sql`, for the ids of the pages.
+    pub page_sql: PathBuf,
+    pub namespace_table: NamespaceTable,
+    pub dump_options: DumpOptions,
+}
+
+pub struct TemplateDumpOptions {
     pub format: SerializationFormat,
+    /// Overrides the compression indicated by the extensions of the files.
+    pub compression: Option<OutputCompression>,
     pub files: Vec<(String, Option<String>)>,
-    pub template_normalizations: Option<HashMap<String, Rc<str>>>,
+    pub template_normalizations: Option<HashMap<String, Arc<str>>>,
+    pub template_redirects: Option<HashMap<String, String>>,
     pub include_text: bool,
-    pub dump_options: DumpOptions,
+    pub include_duplicates: bool,
+    pub include_context: bool,
+    pub parse_values: bool,
+    /// Used to tell which template a name in a transclusion refers to.
+    pub namespace_table: NamespaceTable,
 }
 
 pub struct DumpOptions {
     pub pages: usize,
     pub namespaces: Vec<Namespace>,
-    pub dump_file: Box<dyn Read>,
+    pub configuration: Configuration,
+    pub dump_file: Box<dyn Read + Send>,
+    pub jobs: usize,
 }
 
 pub fn collect_template_names_and_files<I, S>(
@@ -178,6 +563,45 @@ where
     Ok(template_and_file)
 }
 
+// Reads the normalized names of the templates to process, or returns `None`
+// to process all templates if no files are given.
+fn load_template_filter(
+    template_filepaths: Vec<PathBuf>,
+    namespace_table: &NamespaceTable,
+) -> Result<Option<HashSet<String>>> {
+    if template_filepaths.is_empty() {
+        return Ok(None);
+    }
+    collect_template_names_and_files(template_filepaths)?
+        .into_iter()
+        .map(|(template, _)| {
+            Title::new(&template, Namespace::Template, namespace_table)
+                .map(Title::into_name)
+                .map_err(|e| Error::TemplateNameNormalization {
+                    title: template,
+                    cause: e,
+                })
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+// Reads the namespaces in siteinfo JSON, for subcommands that don't read
+// the dump, or uses those of the English Wiktionary.
+fn load_namespaces(siteinfo: Option<PathBuf>) -> Result<NamespaceTable> {
+    match siteinfo {
+        Some(path) => {
+            let file = File::open(&path).map_err(|e| Error::IoError {
+                action: "open",
+                path: path.clone(),
+                cause: e,
+            })?;
+            Ok(NamespaceTable::from_siteinfo_json(BufReader::new(file))?)
+        }
+        None => Ok(NamespaceTable::english_wiktionary()),
+    }
+}
+
 fn collect_lines(filepaths: Vec<PathBuf>) -> Result<Vec<String>> {
     let mut lines = Vec::new();
     for path in filepaths {
@@ -197,102 +621,244 @@ fn collect_lines(filepaths: Vec<PathBuf>) -> Result<Vec<String>> {
     Ok(lines)
 }
 
-#[derive(Debug)]
-pub enum DumpFileError {
-    IoError(std::io::Error),
-    DefaultsNotFound,
+fn read_template_normalizations(
+    path: &Path,
+) -> Result<HashMap<String, Arc<str>>> {
+    let file = File::open(&path).map_err(|e| Error::IoError {
+        action: "open",
+        path: path.into(),
+        cause: e,
+    })?;
+    let normalizations: HashMap<String, Vec<String>> =
+        serde_json::from_reader(&file).map_err(|e| {
+            Error::ParseTemplateNormalization {
+                path: path.into(),
+                cause: e,
+            }
+        })?;
+    let capacity = normalizations.iter().map(|(_k, v)| v.len()).sum();
+    let normalizations = normalizations.into_iter().fold(
+        HashMap::with_capacity(capacity),
+        |mut map, (template, aliases)| {
+            let template = template.into();
+            map.extend(
+                aliases
+                    .into_iter()
+                    .map(|alias| (alias, Arc::clone(&template))),
+            );
+            map
+        },
+    );
+    Ok(normalizations)
 }
 
-impl From<std::io::Error> for DumpFileError {
-    fn from(e: std::io::Error) -> DumpFileError {
-        DumpFileError::IoError(e)
-    }
+// Loads the redirects in the Template namespace from the SQL dump if it is
+// given, and otherwise from the XML dump.
+fn load_template_redirects(
+    page_sql: Option<PathBuf>,
+    redirect_sql: Option<PathBuf>,
+    dump_path: Option<&Path>,
+    dump_options: &DumpOptions,
+    namespace_table: &NamespaceTable,
+) -> Result<HashMap<String, String>> {
+    let redirects_by_target = match (page_sql, redirect_sql) {
+        (Some(page), Some(redirect)) => {
+            RedirectSource::Sql { page, redirect }.load()?
+        }
+        _ => redirects_from_dump(
+            dump_path,
+            dump_options.jobs,
+            &dump_options.configuration,
+            namespace_table,
+        )?,
+    };
+    Ok(redirect_to_target(redirects_by_target))
 }
 
-impl Display for DumpFileError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            DumpFileError::IoError(e) => {
-                write!(f, "failed to open dump file: {}", e)
-            }
-            DumpFileError::DefaultsNotFound => write!(
-                f,
-                concat!(
-                    "no dump filepath given, and did not find any of the ",
-                    "following filenames in the current directory: {}"
-                ),
-                DEFAULT_DUMP_FILE_NAMES.join(", ")
-            ),
-        }
+// Loads language codes from a JSON file if it is given, and otherwise from
+// the data modules in the dump.
+fn load_language_codes(
+    path: Option<&Path>,
+    dump_path: Option<&Path>,
+    jobs: usize,
+) -> Result<LanguageCodes> {
+    if let Some(path) = path {
+        let file = File::open(path).map_err(|e| Error::IoError {
+            action: "open",
+            path: path.into(),
+            cause: e,
+        })?;
+        return Ok(serde_json::from_reader(BufReader::new(file))?);
+    }
+    if dump_path == Some(Path::new("-")) {
+        return Err(Error::LanguageCodesFromStandardInput);
     }
+    let dump_file = dump_parser::open_dump(dump_path, jobs)?;
+    Ok(LanguageCodes::from_dump(dump_parser::parse(dump_file))?)
 }
 
-impl std::error::Error for DumpFileError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        if let Self::IoError(e) = self {
-            Some(e)
-        } else {
-            None
-        }
+// Collects TemplateData by reading the dump a first time.
+fn load_template_data(
+    dump_path: Option<&Path>,
+    dump_options: &DumpOptions,
+    namespace_table: &NamespaceTable,
+    verbose: bool,
+) -> Result<TemplateDataMap> {
+    if dump_path == Some(Path::new("-")) {
+        return Err(Error::TemplateDataFromStandardInput);
     }
+    let dump_file = dump_parser::open_dump(dump_path, dump_options.jobs)?;
+    Ok(TemplateDataMap::from_dump(
+        dump_parser::parse(dump_file),
+        &dump_options.configuration,
+        namespace_table,
+        verbose,
+    )?)
 }
 
-const DEFAULT_DUMP_FILE_NAMES: &[&str] = &[
-    "pages-articles.xml",
-    "pages-meta-current.xml",
-    "pages-articles.xml.bz2",
-    "pages-meta-current.xml.bz2",
-];
-
-fn get_dump_file(
-    path: &Option<PathBuf>,
-) -> StdResult<Box<dyn Read>, DumpFileError> {
-    let (file, path) = if let Some(path) = path {
-        (File::open(&path)?, Path::new(path))
-    } else if let Some((file, path)) = DEFAULT_DUMP_FILE_NAMES
-        .iter()
-        .filter_map(|path| {
-            if let Ok(f) = File::open(path) {
-                Some((f, path))
-            } else {
-                None
-            }
-        })
-        .next()
-    {
-        (file, Path::new(path))
-    } else {
-        return Err(DumpFileError::DefaultsNotFound);
+fn make_job(
+    spec: JobSpec,
+    dump_path: Option<&Path>,
+    dump_options: &DumpOptions,
+    namespace_table: &NamespaceTable,
+) -> Result<Job> {
+    let default_namespaces = &dump_options.namespaces;
+    let parse_namespaces = |names: Option<Vec<String>>| match names {
+        Some(names) => names
+            .iter()
+            .map(|name| {
+                namespace_table.parse_namespace(name).map_err(Error::from)
+            })
+            .collect::<Result<_>>(),
+        None => Ok(default_namespaces.to_vec()),
     };
-    Ok(if path.ends_with(".bz2") {
-        Box::new(BzDecoder::new(BufReader::new(file)))
-    } else {
-        Box::new(file)
-    })
+    let job = match spec {
+        JobSpec::AllHeaders {
+            output,
+            pretty,
+            compression,
+            namespaces,
+        } => Job {
+            namespaces: parse_namespaces(namespaces)?,
+            kind: JobKind::AllHeaders {
+                output,
+                pretty,
+                compression,
+            },
+        },
+        JobSpec::FilterHeaders {
+            output,
+            pretty,
+            compression,
+            namespaces,
+            top_level_headers,
+            other_headers,
+        } => Job {
+            namespaces: parse_namespaces(namespaces)?,
+            kind: JobKind::FilterHeaders {
+                output,
+                pretty,
+                compression,
+                top_level_headers: collect_lines(top_level_headers)?,
+                other_headers: collect_lines(other_headers)?,
+            },
+        },
+        JobSpec::DumpParsedTemplates {
+            output_dir,
+            namespaces,
+            format,
+            compression,
+            templates,
+            include_text,
+            include_duplicates,
+            include_context,
+            parse_values,
+            template_normalizations,
+            resolve_redirects,
+            page_sql,
+            redirect_sql,
+        } => Job {
+            namespaces: parse_namespaces(namespaces)?,
+            kind: JobKind::DumpParsedTemplates {
+                output_dir: output_dir.unwrap_or_default(),
+                templates: TemplateDumpOptions {
+                    format,
+                    compression,
+                    files: collect_template_names_and_files(templates)?,
+                    template_normalizations: template_normalizations
+                        .as_deref()
+                        .map(read_template_normalizations)
+                        .transpose()?,
+                    template_redirects: if resolve_redirects {
+                        Some(load_template_redirects(
+                            page_sql,
+                            redirect_sql,
+                            dump_path,
+                            dump_options,
+                            namespace_table,
+                        )?)
+                    } else {
+                        None
+                    },
+                    include_text,
+                    include_duplicates,
+                    include_context,
+                    parse_values,
+                    namespace_table: namespace_table.clone(),
+                },
+            },
+        },
+    };
+    Ok(job)
 }
 
 pub fn get_opts() -> Result<Opts> {
     let args = Args::from_args();
     let Args { verbose, cmd } = args;
-    let dump_options = match &cmd {
+    let (dump_options, namespace_table) = match &cmd {
         Command::DumpParsedTemplates { dump_args, .. }
         | Command::AllHeaders { dump_args, .. }
-        | Command::FilterHeaders { dump_args, .. } => {
+        | Command::FilterHeaders { dump_args, .. }
+        | Command::TemplateStats { dump_args, .. }
+        | Command::CheckTemplateData { dump_args, .. }
+        | Command::DuplicateArgs { dump_args, .. }
+        | Command::ExportSqlite { dump_args, .. }
+        | Command::SplitLanguages { dump_args, .. }
+        | Command::Run { dump_args, .. } => {
             let DumpArgs {
                 namespaces,
                 pages,
                 dump_filepath,
+                jobs,
+                siteinfo,
             } = dump_args;
             let pages = pages.unwrap_or(std::usize::MAX);
-            let dump_file = get_dump_file(&dump_filepath)?;
-            Some(DumpOptions {
-                namespaces: namespaces.to_vec(),
+            let jobs = jobs.unwrap_or_else(num_cpus::get);
+            let dump_file =
+                dump_parser::open_dump(dump_filepath.as_ref(), jobs)?;
+            let (namespace_table, dump_file) =
+                dump_parser::load_namespace_table(
+                    siteinfo.as_ref(),
+                    dump_file,
+                )?;
+            let namespaces = namespaces
+                .iter()
+                .map(|name| namespace_table.parse_namespace(name))
+                .collect::<StdResult<_, _>>()?;
+            let configuration =
+                dump_parser::load_configuration(siteinfo.as_ref())?;
+            let dump_options = DumpOptions {
+                namespaces,
+                configuration,
                 pages,
                 dump_file,
-            })
+                jobs,
+            };
+            Some((dump_options, namespace_table))
         }
         _ => None,
-    };
+    }
+    .unzip();
 
     let template_names_and_files = match &cmd {
         Command::DumpParsedTemplates {
@@ -306,71 +872,255 @@ pub fn get_opts() -> Result<Opts> {
             template_normalization_filepath:
                 Some(template_normalization_filepath),
             ..
-        } => {
-            let file =
-                File::open(&template_normalization_filepath).map_err(|e| {
-                    Error::IoError {
-                        action: "open",
-                        path: template_normalization_filepath.into(),
-                        cause: e,
-                    }
-                })?;
-            let normalizations: HashMap<String, Vec<String>> =
-                serde_json::from_reader(&file).map_err(|e| {
-                    Error::ParseTemplateNormalization {
-                        path: template_normalization_filepath.into(),
-                        cause: e,
-                    }
-                })?;
-            let capacity = normalizations.iter().map(|(_k, v)| v.len()).sum();
-            let normalizations = normalizations.into_iter().fold(
-                HashMap::with_capacity(capacity),
-                |mut map, (template, aliases)| {
-                    let template = template.into();
-                    map.extend(
-                        aliases
-                            .into_iter()
-                            .map(|alias| (alias, Rc::clone(&template))),
-                    );
-                    map
-                },
-            );
-            Some(normalizations)
-        }
+        } => Some(read_template_normalizations(
+            template_normalization_filepath,
+        )?),
         _ => None,
     };
 
     let cmd = match cmd {
         Command::DumpParsedTemplates {
             format,
+            compression,
             include_text,
+            include_duplicates,
+            include_context,
+            parse_values,
+            resolve_redirects,
+            page_sql,
+            redirect_sql,
+            dump_args,
             ..
         } => {
             let files = template_names_and_files.unwrap();
             let dump_options = dump_options.unwrap();
+            let namespace_table = namespace_table.unwrap();
+            let template_redirects = if resolve_redirects {
+                Some(load_template_redirects(
+                    page_sql,
+                    redirect_sql,
+                    dump_args.dump_filepath.as_deref(),
+                    &dump_options,
+                    &namespace_table,
+                )?)
+            } else {
+                None
+            };
             CommandData::DumpParsedTemplates(DumpParsedTemplates {
-                files,
+                templates: TemplateDumpOptions {
+                    files,
+                    template_normalizations,
+                    template_redirects,
+                    include_text,
+                    include_duplicates,
+                    include_context,
+                    parse_values,
+                    format,
+                    compression,
+                    namespace_table,
+                },
                 dump_options,
-                template_normalizations,
-                include_text,
-                format,
             })
         }
-        Command::AllHeaders { pretty, .. } => CommandData::AllHeaders {
+        Command::AllHeaders {
+            pretty,
+            output,
+            compression,
+            ..
+        } => CommandData::AllHeaders {
             pretty,
+            output,
+            compression,
             dump_options: dump_options.unwrap(),
         },
         Command::FilterHeaders {
             top_level_header_filepaths,
             other_header_filepaths,
             pretty,
+            output,
+            compression,
             ..
         } => CommandData::FilterHeaders {
             top_level_headers: collect_lines(top_level_header_filepaths)?,
             other_headers: collect_lines(other_header_filepaths)?,
             pretty,
+            output,
+            compression,
+            dump_options: dump_options.unwrap(),
+        },
+        Command::TemplateStats {
+            template_filepaths,
+            format,
+            samples,
+            pretty,
+            ..
+        } => {
+            let namespace_table = namespace_table.unwrap();
+            let templates =
+                load_template_filter(template_filepaths, &namespace_table)?;
+            CommandData::TemplateStats {
+                templates,
+                format,
+                samples,
+                pretty,
+                namespace_table,
+                dump_options: dump_options.unwrap(),
+            }
+        }
+        Command::ExportSqlite {
+            output,
+            page_sql,
+            template_filepaths,
+            ..
+        } => {
+            let namespace_table = namespace_table.unwrap();
+            let templates =
+                load_template_filter(template_filepaths, &namespace_table)?;
+            CommandData::ExportSqlite(ExportSqlite {
+                output,
+                templates,
+                page_sql,
+                namespace_table,
+                dump_options: dump_options.unwrap(),
+            })
+        }
+        Command::CheckTemplateData {
+            format,
+            pretty,
+            dump_args,
+        } => {
+            let dump_options = dump_options.unwrap();
+            let namespace_table = namespace_table.unwrap();
+            let template_data = load_template_data(
+                dump_args.dump_filepath.as_deref(),
+                &dump_options,
+                &namespace_table,
+                verbose,
+            )?;
+            CommandData::CheckTemplateData {
+                template_data,
+                format,
+                pretty,
+                namespace_table,
+                dump_options,
+            }
+        }
+        Command::DuplicateArgs { json, .. } => CommandData::DuplicateArgs {
+            json,
+            namespace_table: namespace_table.unwrap(),
             dump_options: dump_options.unwrap(),
         },
+        Command::SplitLanguages {
+            languages,
+            language_codes,
+            dump_args,
+        } => {
+            let dump_options = dump_options.unwrap();
+            let language_codes = load_language_codes(
+                language_codes.as_deref(),
+                dump_args.dump_filepath.as_deref(),
+                dump_options.jobs,
+            )?;
+            CommandData::SplitLanguages {
+                languages,
+                language_codes,
+                dump_options,
+            }
+        }
+        Command::QueryTemplates {
+            paths,
+            format,
+            titles,
+            templates,
+            predicates,
+            fields,
+            output,
+            siteinfo,
+        } => {
+            let namespace_table = load_namespaces(siteinfo)?;
+            let templates = templates
+                .into_iter()
+                .map(|template| {
+                    Title::new(
+                        &template,
+                        Namespace::Template,
+                        &namespace_table,
+                    )
+                    .map(Title::into_name)
+                    .map_err(|e| {
+                        Error::TemplateNameNormalization {
+                            title: template,
+                            cause: e,
+                        }
+                    })
+                })
+                .collect::<Result<_>>()?;
+            CommandData::QueryTemplates {
+                paths,
+                format,
+                query: Query {
+                    titles,
+                    templates,
+                    predicates,
+                    fields,
+                    output,
+                    namespace_table,
+                },
+            }
+        }
+        Command::PageText {
+            dump_filepath,
+            index_filepath,
+            titles,
+        } => CommandData::PageText {
+            dump_path: dump_filepath,
+            index_path: index_filepath,
+            titles,
+        },
+        Command::AddTemplateRedirects {
+            template_filepaths,
+            path_format,
+            page_sql,
+            redirect_sql,
+            redirects_json,
+            siteinfo,
+        } => {
+            let redirects = match (page_sql, redirect_sql, redirects_json) {
+                (Some(page), Some(redirect), None) => {
+                    RedirectSource::Sql { page, redirect }
+                }
+                (None, None, Some(path)) => RedirectSource::Json(path),
+                _ => unreachable!("clap checks the redirect sources"),
+            };
+            CommandData::AddTemplateRedirects {
+                templates: collect_template_names_and_files(
+                    template_filepaths,
+                )?,
+                path_format,
+                redirects,
+                namespace_table: load_namespaces(siteinfo)?,
+            }
+        }
+        Command::Run {
+            job_file,
+            dump_args,
+        } => {
+            let dump_options = dump_options.unwrap();
+            let namespace_table = namespace_table.unwrap();
+            let jobs = read_job_file(&job_file)?
+                .jobs
+                .into_iter()
+                .map(|spec| {
+                    make_job(
+                        spec,
+                        dump_args.dump_filepath.as_deref(),
+                        &dump_options,
+                        &namespace_table,
+                    )
+                })
+                .collect::<Result<_>>()?;
+            CommandData::Run { jobs, dump_options }
+        }
         Command::Completions { shell } => CommandData::Completions { shell },
     };
     Ok(Opts { verbose, cmd })
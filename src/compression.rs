@@ -0,0 +1,157 @@
+// Compression of the files written by `dump-parsed-templates`, `all-headers`
+// and `filter-headers`. It is chosen with `--compression` or by the extension
+// of the output path, and the files are decompressed by `decompress` in
+// `dump_parser`, which detects the format from the first bytes.
+
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use std::{
+    io::{self, Write},
+    path::Path,
+    result::Result as StdResult,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputCompression {
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for OutputCompression {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        let compression = match s.to_lowercase().as_str() {
+            "gzip" | "gz" => OutputCompression::Gzip,
+            "zstd" | "zst" => OutputCompression::Zstd,
+            _ => return Err("unrecognized compression"),
+        };
+        Ok(compression)
+    }
+}
+
+impl OutputCompression {
+    /// Identifies the compression format from the extension of `path`:
+    /// `.gz` or `.zst`.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(OutputCompression::Gzip),
+            "zst" => Some(OutputCompression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The compression given as an option, or else the one indicated by
+    /// the extension of `path`.
+    pub fn for_path(compression: Option<Self>, path: &Path) -> Option<Self> {
+        compression.or_else(|| Self::from_extension(path))
+    }
+
+    /// The extension, with a leading dot, that is added to default paths.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputCompression::Gzip => ".gz",
+            OutputCompression::Zstd => ".zst",
+        }
+    }
+}
+
+/// A writer that compresses what is written to it, or doesn't if there is
+/// no compression.
+pub enum CompressedWriter<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(
+        writer: W,
+        compression: Option<OutputCompression>,
+    ) -> io::Result<Self> {
+        Ok(match compression {
+            None => CompressedWriter::Plain(writer),
+            Some(OutputCompression::Gzip) => CompressedWriter::Gzip(
+                GzEncoder::new(writer, flate2::Compression::default()),
+            ),
+            Some(OutputCompression::Zstd) => {
+                CompressedWriter::Zstd(zstd::Encoder::new(writer, 0)?)
+            }
+        })
+    }
+
+    /// Writes the end of the compressed stream, without which it can't be
+    /// decompressed, and flushes the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut writer = match self {
+            CompressedWriter::Plain(writer) => writer,
+            CompressedWriter::Gzip(encoder) => encoder.finish()?,
+            CompressedWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressedWriter::Plain(writer) => writer.write(buf),
+            CompressedWriter::Gzip(encoder) => encoder.write(buf),
+            CompressedWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressedWriter::Plain(writer) => writer.flush(),
+            CompressedWriter::Gzip(encoder) => encoder.flush(),
+            CompressedWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressedWriter, OutputCompression};
+    use dump_parser::input::decompress;
+    use std::{
+        io::{Cursor, Read, Write},
+        path::Path,
+    };
+
+    #[test]
+    fn round_trip() {
+        let text = b"{\"title\":\"word\",\"templates\":[]}\n".repeat(100);
+        for compression in &[
+            None,
+            Some(OutputCompression::Gzip),
+            Some(OutputCompression::Zstd),
+        ] {
+            let mut writer =
+                CompressedWriter::new(Vec::new(), *compression).unwrap();
+            writer.write_all(&text).unwrap();
+            let compressed = writer.finish().unwrap();
+            assert_eq!(compressed == text, compression.is_none());
+            let mut decompressed = Vec::new();
+            decompress(Cursor::new(compressed), 1)
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, text);
+        }
+        let from_extension =
+            |path| OutputCompression::from_extension(Path::new(path));
+        assert_eq!(
+            from_extension("l.cbor.zst"),
+            Some(OutputCompression::Zstd)
+        );
+        assert_eq!(
+            from_extension("headers.json.gz"),
+            Some(OutputCompression::Gzip)
+        );
+        assert_eq!(from_extension("l.cbor"), None);
+    }
+}
@@ -1,11 +1,20 @@
-use dump_parser::Error as DumpParsingError;
+use dump_parser::{
+    configuration::ConfigurationError, input::DumpFileError,
+    multistream::MultistreamError, namespaces::NamespaceError,
+    Error as DumpParsingError,
+};
+#[cfg(feature = "parquet")]
+use parquet::errors::ParquetError;
+use parse_sql_dump::SqlDumpError;
+#[cfg(feature = "sqlite")]
+use rusqlite::Error as SqliteError;
 use serde_cbor::Error as SerdeCborError;
 use serde_json::{self, error::Error as SerdeJsonError};
 use std::path::PathBuf;
 use std::{fmt::Display, io::Error as IoError};
 use template_iter::TitleNormalizationError;
 
-use crate::args::DumpFileError;
+use crate::run::JobFileError;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -28,12 +37,37 @@ pub enum Error {
         path: PathBuf,
         cause: SerdeJsonError,
     },
+    ParseJobFile {
+        path: PathBuf,
+        cause: JobFileError,
+    },
+    ParseSqlDump {
+        path: PathBuf,
+        cause: SqlDumpError,
+    },
     FormatError {
         description: &'static str,
         path: PathBuf,
         line_number: usize,
         line: String,
     },
+    MultistreamError(MultistreamError),
+    NamespaceError(NamespaceError),
+    ConfigurationError(ConfigurationError),
+    PageNotFound(String),
+    RedirectsFromStandardInput,
+    LanguageCodesFromStandardInput,
+    TemplateDataFromStandardInput,
+    UnknownTemplateDumpFormat(PathBuf),
+    StaleTemplateIndex(PathBuf),
+    SharedOutput(PathBuf),
+    #[cfg(feature = "parquet")]
+    ParquetError(ParquetError),
+    #[cfg(feature = "parquet")]
+    ParquetUnsupported(&'static str),
+    #[cfg(feature = "sqlite")]
+    SqliteError(SqliteError),
+    FeatureNotEnabled(&'static str),
 }
 
 impl std::error::Error for Error {
@@ -46,7 +80,26 @@ impl std::error::Error for Error {
             Error::TemplateNameNormalization { cause, .. } => Some(cause),
             Error::DumpFileError(e) => Some(e),
             Error::ParseTemplateNormalization { cause, .. } => Some(cause),
+            Error::ParseJobFile { cause, .. } => Some(cause),
+            Error::ParseSqlDump { cause, .. } => Some(cause),
             Error::FormatError { .. } => None,
+            Error::MultistreamError(e) => Some(e),
+            Error::NamespaceError(e) => Some(e),
+            Error::ConfigurationError(e) => Some(e),
+            Error::PageNotFound(_) => None,
+            Error::RedirectsFromStandardInput => None,
+            Error::LanguageCodesFromStandardInput => None,
+            Error::TemplateDataFromStandardInput => None,
+            Error::UnknownTemplateDumpFormat(_) => None,
+            Error::StaleTemplateIndex(_) => None,
+            Error::SharedOutput(_) => None,
+            #[cfg(feature = "parquet")]
+            Error::ParquetError(e) => Some(e),
+            #[cfg(feature = "parquet")]
+            Error::ParquetUnsupported(_) => None,
+            #[cfg(feature = "sqlite")]
+            Error::SqliteError(e) => Some(e),
+            Error::FeatureNotEnabled(_) => None,
         }
     }
 }
@@ -70,12 +123,7 @@ impl Display for Error {
             Error::TemplateNameNormalization { title, cause } => write!(
                 f,
                 "failed to normalize template name {}: {}",
-                title,
-                match cause {
-                    TitleNormalizationError::TooLong => "too long",
-                    TitleNormalizationError::IllegalChar => "illegal character",
-                    _ => "unknown error",
-                },
+                title, cause
             ),
             Error::SerdeCborError(e) => write!(f, "error writing CBOR: {}", e),
             Error::DumpFileError(e) => {
@@ -87,6 +135,18 @@ impl Display for Error {
                 path.display(),
                 cause
             ),
+            Error::ParseJobFile { path, cause } => write!(
+                f,
+                "failed to parse job file {}: {}",
+                path.display(),
+                cause
+            ),
+            Error::ParseSqlDump { path, cause } => write!(
+                f,
+                "failed to parse SQL dump {}: {}",
+                path.display(),
+                cause
+            ),
             Error::FormatError {
                 description,
                 path,
@@ -100,6 +160,59 @@ impl Display for Error {
                 path.display(),
                 line
             ),
+            Error::MultistreamError(e) => write!(f, "{}", e),
+            Error::NamespaceError(e) => write!(f, "{}", e),
+            Error::ConfigurationError(e) => write!(f, "{}", e),
+            Error::PageNotFound(title) => {
+                write!(f, "page [[{}]] not found in index", title)
+            }
+            Error::RedirectsFromStandardInput => write!(
+                f,
+                concat!(
+                    "cannot read template redirects from a dump read from ",
+                    "standard input; use --page-sql and --redirect-sql"
+                )
+            ),
+            Error::LanguageCodesFromStandardInput => write!(
+                f,
+                concat!(
+                    "cannot read language codes from a dump read from ",
+                    "standard input; use --language-codes"
+                )
+            ),
+            Error::TemplateDataFromStandardInput => write!(
+                f,
+                "cannot read TemplateData from a dump read from standard input"
+            ),
+            Error::UnknownTemplateDumpFormat(path) => write!(
+                f,
+                "cannot tell the format of {} from its extension; use --format",
+                path.display()
+            ),
+            Error::StaleTemplateIndex(path) => write!(
+                f,
+                concat!(
+                    "index {} does not match its template dump; ",
+                    "rerun dump-parsed-templates"
+                ),
+                path.display()
+            ),
+            Error::SharedOutput(path) => {
+                write!(f, "more than one job writes to {}", path.display())
+            }
+            #[cfg(feature = "parquet")]
+            Error::ParquetError(e) => write!(f, "error writing Parquet: {}", e),
+            #[cfg(feature = "parquet")]
+            Error::ParquetUnsupported(what) => {
+                write!(f, "{} is not supported with the Parquet format", what)
+            }
+            #[cfg(feature = "sqlite")]
+            Error::SqliteError(e) => write!(f, "error writing SQLite: {}", e),
+            Error::FeatureNotEnabled(feature) => write!(
+                f,
+                "built without the {0} feature; rebuild with --features {0}",
+                feature
+            ),
         }
     }
 }
@@ -125,5 +238,27 @@ macro_rules! impl_from {
 }
 
 impl_from! {
-    Error <- [DumpFileError, DumpParsingError, SerdeCborError, SerdeJsonError]
+    Error <- [
+        ConfigurationError,
+        DumpFileError,
+        DumpParsingError,
+        MultistreamError,
+        NamespaceError,
+        SerdeCborError,
+        SerdeJsonError,
+    ]
+}
+
+#[cfg(feature = "parquet")]
+impl From<ParquetError> for Error {
+    fn from(e: ParquetError) -> Error {
+        Error::ParquetError(e)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<SqliteError> for Error {
+    fn from(e: SqliteError) -> Error {
+        Error::SqliteError(e)
+    }
 }
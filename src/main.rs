@@ -1,30 +1,66 @@
 use dump_parser::{
-    parse as parse_dump, parse_wiki_text::Positioned, Page, Warning,
+    parse as parse_dump, parse_pages, parse_wiki_text::Positioned,
+    print_parser_warnings, MultistreamReader, Namespace, NamespaceTable, Node,
+    Page,
 };
 use filter_headers::HeaderFilterer;
 use header_stats::HeaderStats;
+use language_sections::{language_sections, LanguageCodes, LanguageSection};
 use serde::Serialize;
 use std::{
-    borrow::{BorrowMut, Cow},
+    borrow::Cow,
     cell::RefCell,
     collections::{BTreeMap, HashMap},
     fmt::{Error as FmtError, Write as WriteFmt},
     fs::File,
     io::{self, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
     rc::Rc,
+    sync::Arc,
     time::{Duration, Instant},
 };
 use structopt::StructOpt;
-use template_iter::{normalize_title, TemplateBorrowed, TemplateVisitor};
+use template_iter::{
+    parse_parameters, parse_wiki_text_ext::Section, TemplateBorrowed,
+    TemplateOwned, TemplateVisitor, Title, ValueNode,
+};
+use template_data::ValidationReport;
+use template_stats::TemplateStats;
 
 mod args;
 use args::{
-    Args, CommandData, DumpOptions, DumpParsedTemplates, SerializationFormat,
+    Args, CommandData, DumpOptions, DumpParsedTemplates, ReportFormat,
+    SerializationFormat, StatsFormat, TemplateDumpOptions,
 };
 
+mod compression;
+use compression::{CompressedWriter, OutputCompression};
+
 mod error;
 use error::{Error, Result};
 
+mod query_templates;
+use query_templates::query_templates;
+
+mod run;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+use sqlite::export_sqlite;
+
+#[cfg(feature = "parquet")]
+mod tables;
+#[cfg(feature = "parquet")]
+use tables::{TemplateRow, TemplateRows, TemplateTable};
+
+mod template_index;
+use template_index::{index_path, TemplateIndex};
+
+mod template_redirects;
+use template_redirects::{add_redirects, RedirectSource};
+
 fn print_time(time: &Duration) -> std::result::Result<String, FmtError> {
     let mut secs = time.as_secs();
     let mins = secs / 60;
@@ -58,60 +94,201 @@ fn print_time(time: &Duration) -> std::result::Result<String, FmtError> {
 fn do_dumping<S>(dumper: &S, pretty: bool) -> Result<()>
 where
     S: Serialize,
+{
+    write_json(std::io::stdout().lock(), dumper, pretty)
+}
+
+fn write_json<S, W>(writer: W, value: &S, pretty: bool) -> Result<()>
+where
+    S: Serialize,
+    W: Write,
 {
     if pretty {
-        serde_json::to_writer_pretty(std::io::stdout().lock(), &dumper)?
+        serde_json::to_writer_pretty(writer, value)?
     } else {
-        serde_json::to_writer(std::io::stdout().lock(), &dumper)?
+        serde_json::to_writer(writer, value)?
     }
     Ok(())
 }
 
-fn print_parser_warnings(page: &Page, warnings: &[Warning]) {
-    for warning in warnings {
-        let Warning {
-            start,
-            end,
-            message,
-        } = warning;
-        let range = 0..page.text.len();
-        let message = message.message().trim_end_matches('.');
-        if !(range.contains(&start) && range.contains(&end)) {
-            eprintln!("byte position {} or {} in warning {} is out of range of {:?}, size of [[{}]]",
-                start, end, message, range, &page.title);
-        } else {
-            eprintln!(
-                "{} at bytes {}..{} ({:?}) in [[{}]]",
-                &message,
-                start,
-                end,
-                &page.text[*start..*end],
-                &page.title
-            );
+// The output of `all-headers` or `filter-headers`.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Headers<'a> {
+    Stats(&'a HeaderStats),
+    Filtered(&'a HeaderFilterer),
+}
+
+/// Prints headers as JSON, or writes them to `output`, as a Parquet table
+/// if its extension is `.parquet` and as JSON otherwise, compressed with
+/// `compression` or the compression that its extension indicates.
+fn write_headers(
+    headers: Headers,
+    pretty: bool,
+    output: Option<&Path>,
+    compression: Option<OutputCompression>,
+) -> Result<()> {
+    let path = match output {
+        Some(path) => path,
+        None => return do_dumping(&headers, pretty),
+    };
+    let parquet = path.extension().map(|e| e == "parquet") == Some(true);
+    if parquet && !cfg!(feature = "parquet") {
+        return Err(Error::FeatureNotEnabled("parquet"));
+    }
+    let compression = OutputCompression::for_path(compression, path);
+    #[cfg(feature = "parquet")]
+    if parquet && compression.is_some() {
+        return Err(Error::ParquetUnsupported("--compression"));
+    }
+    let create_error = |e| Error::IoError {
+        action: "create",
+        path: path.into(),
+        cause: e,
+    };
+    let file = File::create(path).map_err(create_error)?;
+    let mut file = CompressedWriter::new(BufWriter::new(file), compression)
+        .map_err(create_error)?;
+    if parquet {
+        #[cfg(feature = "parquet")]
+        match headers {
+            Headers::Stats(stats) => {
+                tables::write_header_stats(&mut file, stats)?
+            }
+            Headers::Filtered(filterer) => {
+                tables::write_filtered_headers(&mut file, filterer)?
+            }
         }
+    } else {
+        write_json(&mut file, &headers, pretty)?;
     }
+    file.finish().map(drop).map_err(|e| Error::IoError {
+        action: "write",
+        path: path.into(),
+        cause: e,
+    })
 }
 
 #[derive(Debug, Serialize)]
 struct TemplateToDump<'a> {
     name: Cow<'a, str>,
-    parameters: BTreeMap<Cow<'a, str>, &'a str>,
+    // The name of the redirect that was used instead of `name`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<Cow<'a, str>>,
+    parameters: Parameters<'a>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    overridden: Vec<(Cow<'a, str>, &'a str)>,
     text: Option<&'a str>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    context: Option<TemplateContext<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Parameters<'a> {
+    Wikitext(BTreeMap<Cow<'a, str>, &'a str>),
+    Parsed(BTreeMap<Cow<'a, str>, Vec<ValueNode<'a>>>),
+}
+
+#[cfg(feature = "parquet")]
+impl<'a> TemplateToDump<'a> {
+    // Converts the template to a row of a Parquet table, with parsed
+    // parameter values as JSON.
+    fn into_row(self) -> Result<TemplateRow> {
+        let parameters = match self.parameters {
+            Parameters::Wikitext(parameters) => parameters
+                .into_iter()
+                .map(|(key, value)| (key.into_owned(), value.to_string()))
+                .collect(),
+            Parameters::Parsed(parameters) => parameters
+                .into_iter()
+                .map(|(key, value)| {
+                    Ok((key.into_owned(), serde_json::to_string(&value)?))
+                })
+                .collect::<Result<_>>()?,
+        };
+        Ok(TemplateRow {
+            name: self.name.into_owned(),
+            alias: self.alias.map(Cow::into_owned),
+            parameters,
+            text: self.text.map(String::from),
+        })
+    }
+}
+
+// Where a template is in a page.
+#[derive(Debug, Serialize)]
+struct TemplateContext<'a> {
+    // The byte range of the template in the page.
+    range: Range<usize>,
+    // The headings of the sections that contain the template, outermost
+    // first.
+    headings: Vec<&'a str>,
+    // The number of templates that the template is nested in.
+    depth: usize,
+    // The name of the innermost template that the template is nested in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<&'a str>,
+}
+
+impl<'a> TemplateContext<'a> {
+    fn new(
+        wikitext: &'a str,
+        template: &Node,
+        ancestors: &[&Node],
+        sections: &Section<'a>,
+    ) -> Self {
+        let mut parents = ancestors.iter().rev().filter_map(|node| {
+            if let Node::Template { name, .. } = node {
+                Some(name.get_text_from(wikitext).trim())
+            } else {
+                None
+            }
+        });
+        let parent = parents.next();
+        let depth = parent.map_or(0, |_| 1 + parents.count());
+        let headings = sections
+            .path_to(template.start())
+            .iter()
+            .filter_map(|section| section.heading)
+            .collect();
+        Self {
+            range: template.start()..template.end(),
+            headings,
+            depth,
+            parent,
+        }
+    }
 }
 
 impl<'a> TemplateToDump<'a> {
     fn new(
         wikitext: &'a str,
         template: TemplateBorrowed<'a>,
+        alias: Option<Cow<'a, str>>,
         with_text: bool,
+        with_duplicates: bool,
+        parsed_parameters: Option<BTreeMap<Cow<'a, str>, Vec<ValueNode<'a>>>>,
+        context: Option<TemplateContext<'a>>,
     ) -> Self {
         let name = template.name;
-        let parameters = template.parameters;
+        let parameters = match parsed_parameters {
+            Some(parameters) => Parameters::Parsed(parameters),
+            None => Parameters::Wikitext(template.parameters),
+        };
+        let overridden = if with_duplicates {
+            template.overridden
+        } else {
+            Vec::new()
+        };
         let text = if with_text { Some(wikitext) } else { None };
         Self {
             name,
+            alias,
             parameters,
+            overridden,
             text,
+            context,
         }
     }
 }
@@ -153,8 +330,11 @@ impl<W: Write> Write for HashableWriter<W> {
     }
 }
 
+// A file that templates are written to, compressed or not.
+type OutputFile = CompressedWriter<BufWriter<File>>;
+
 #[derive(PartialEq, Eq, Clone)]
-struct ShareableHashableFile(Rc<RefCell<HashableWriter<BufWriter<File>>>>);
+struct ShareableHashableFile(Rc<RefCell<HashableWriter<OutputFile>>>);
 
 // Cannot derive `Hash` because derive macro does not manage to delegate `Hash`
 // to `HashableWriter`.
@@ -166,6 +346,20 @@ impl Hash for ShareableHashableFile {
     }
 }
 
+impl ShareableHashableFile {
+    fn id(&self) -> usize {
+        (*self.0).borrow().id
+    }
+
+    // Returns the file, which must not be shared any more.
+    fn into_inner(self) -> OutputFile {
+        match Rc::try_unwrap(self.0) {
+            Ok(writer) => writer.into_inner().writer,
+            Err(_) => panic!("template file is still shared"),
+        }
+    }
+}
+
 impl Write for ShareableHashableFile {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         (*self.0).borrow_mut().write(buf)
@@ -178,7 +372,7 @@ impl Write for ShareableHashableFile {
 
 #[derive(Default)]
 struct FilePool {
-    files: HashMap<String, ShareableHashableFile>,
+    files: HashMap<PathBuf, ShareableHashableFile>,
     count: usize,
 }
 
@@ -187,17 +381,22 @@ impl FilePool {
         Default::default()
     }
 
-    fn create(&mut self, path: &str) -> std::io::Result<ShareableHashableFile> {
+    fn create(
+        &mut self,
+        path: &Path,
+        compression: Option<OutputCompression>,
+    ) -> std::io::Result<ShareableHashableFile> {
         match self.files.get(path) {
             Some(f) => Ok((*f).clone()),
             None => {
                 let file = File::create(path)?;
-                let file = BufWriter::new(file);
+                let file =
+                    CompressedWriter::new(BufWriter::new(file), compression)?;
                 let file_ref = ShareableHashableFile(Rc::new(RefCell::new(
                     HashableWriter::new(file, self.get_file_id()),
                 )));
                 let cloned = file_ref.clone();
-                self.files.insert(path.to_string(), file_ref);
+                self.files.insert(path.to_path_buf(), file_ref);
                 Ok(cloned)
             }
         }
@@ -208,6 +407,14 @@ impl FilePool {
         self.count += 1;
         count
     }
+
+    /// Returns the files and their paths, indexed by the ids that they
+    /// were given.
+    fn into_files(self) -> Vec<(PathBuf, ShareableHashableFile)> {
+        let mut files: Vec<_> = self.files.into_iter().collect();
+        files.sort_by_key(|(_, f)| f.id());
+        files
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -216,106 +423,398 @@ struct TemplatesInPage<'a> {
     templates: &'a [TemplateToDump<'a>],
 }
 
+// The templates in a page that go in one file, as serialized by
+// `TemplateExtractor`.
+enum TemplateOutput {
+    Serialized(Vec<u8>),
+    #[cfg(feature = "parquet")]
+    Rows(TemplateRows),
+}
+
+// A file that templates are written to. CBOR files are indexed by title.
+enum TemplateFile {
+    Stream {
+        file: ShareableHashableFile,
+        index: Option<(PathBuf, TemplateIndex)>,
+    },
+    #[cfg(feature = "parquet")]
+    Table(Box<TemplateTable<OutputFile>>),
+}
+
+impl TemplateFile {
+    fn write(&mut self, title: &str, output: TemplateOutput) -> Result<()> {
+        match (self, output) {
+            (
+                TemplateFile::Stream { file, index },
+                TemplateOutput::Serialized(bytes),
+            ) => {
+                file.write_all(&bytes).map_err(|e| Error::IoError {
+                    action: "write",
+                    path: "template dump".into(),
+                    cause: e,
+                })?;
+                if let Some((_, index)) = index {
+                    index.add(title, bytes.len());
+                }
+                Ok(())
+            }
+            #[cfg(feature = "parquet")]
+            (TemplateFile::Table(table), TemplateOutput::Rows(rows)) => {
+                Ok(table.push(rows)?)
+            }
+            #[cfg(feature = "parquet")]
+            _ => unreachable!("templates serialized in the wrong format"),
+        }
+    }
+
+    /// Ends the compressed stream and flushes the file and writes its index,
+    /// or for Parquet writes the footer.
+    fn finish(self) -> Result<()> {
+        match self {
+            TemplateFile::Stream { file, index } => {
+                file.into_inner().finish().map_err(|e| Error::IoError {
+                    action: "write",
+                    path: "template dump".into(),
+                    cause: e,
+                })?;
+                match index {
+                    Some((path, index)) => index.write(&path),
+                    None => Ok(()),
+                }
+            }
+            #[cfg(feature = "parquet")]
+            TemplateFile::Table(table) => Ok(table.close()?),
+        }
+    }
+}
+
+// The part of a template dump that is shared with the threads
+// that parse wikitext.
+struct TemplateExtractor {
+    format: SerializationFormat,
+    template_to_file: HashMap<String, usize>,
+    template_normalizations: Option<HashMap<String, Arc<str>>>,
+    template_redirects: Option<HashMap<String, String>>,
+    include_text: bool,
+    include_duplicates: bool,
+    include_context: bool,
+    parse_values: bool,
+    namespace_table: NamespaceTable,
+}
+
+impl TemplateExtractor {
+    /// Returns the normalized names of the templates to be dumped and the
+    /// paths of the files that they will be written to in `output_dir`.
+    fn paths(
+        options: &TemplateDumpOptions,
+        output_dir: &Path,
+    ) -> Result<Vec<(String, PathBuf)>> {
+        let extension = match options.format {
+            SerializationFormat::Cbor => ".cbor",
+            SerializationFormat::Json => ".jsonl",
+            #[cfg(feature = "parquet")]
+            SerializationFormat::Parquet => ".parquet",
+        }
+        .to_string()
+            + options.compression.map_or("", OutputCompression::extension);
+        options
+            .files
+            .iter()
+            .map(|(template, path)| {
+                let normalized = Title::new(
+                    template,
+                    Namespace::Template,
+                    &options.namespace_table,
+                )
+                .map(Title::into_name)
+                .map_err(|e| {
+                    Error::TemplateNameNormalization {
+                        title: template.clone(),
+                        cause: e,
+                    }
+                })?;
+                let path = output_dir.join(
+                    path.clone()
+                        .unwrap_or_else(|| normalized.clone() + &extension),
+                );
+                Ok((normalized, path))
+            })
+            .collect()
+    }
+
+    /// Creates the files that templates will be written to in `output_dir`
+    /// and returns them, indexed by the file ids in `template_to_file`.
+    fn new(
+        options: TemplateDumpOptions,
+        output_dir: &Path,
+    ) -> Result<(Self, Vec<TemplateFile>)> {
+        let template_to_file = Self::paths(&options, output_dir)?;
+        let TemplateDumpOptions {
+            format,
+            compression,
+            files: _,
+            template_normalizations,
+            template_redirects,
+            include_text,
+            include_duplicates,
+            include_context,
+            parse_values,
+            namespace_table,
+        } = options;
+        #[cfg(feature = "parquet")]
+        if let SerializationFormat::Parquet = format {
+            if include_duplicates {
+                return Err(Error::ParquetUnsupported("--include-duplicates"));
+            }
+            if include_context {
+                return Err(Error::ParquetUnsupported("--include-context"));
+            }
+        }
+        let mut files = FilePool::new();
+        let template_to_file = template_to_file
+            .into_iter()
+            .map(|(normalized, path)| {
+                let file_compression =
+                    OutputCompression::for_path(compression, &path);
+                #[cfg(feature = "parquet")]
+                if let (SerializationFormat::Parquet, Some(_)) =
+                    (format, file_compression)
+                {
+                    return Err(Error::ParquetUnsupported("compression"));
+                }
+                let file =
+                    files.create(&path, file_compression).map_err(|e| {
+                        Error::IoError {
+                            action: "create",
+                            path,
+                            cause: e,
+                        }
+                    })?;
+                Ok((normalized, file.id()))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        let extractor = Self {
+            format,
+            template_to_file,
+            template_normalizations,
+            template_redirects,
+            include_text,
+            include_duplicates,
+            include_context,
+            parse_values,
+            namespace_table,
+        };
+        let files =
+            files
+                .into_files()
+                .into_iter()
+                .map(|(path, file)| match format {
+                    // Offsets in compressed files can't be sought to.
+                    SerializationFormat::Cbor
+                        if OutputCompression::for_path(compression, &path)
+                            .is_none() =>
+                    {
+                        Ok(TemplateFile::Stream {
+                            file,
+                            index: Some((
+                                index_path(&path),
+                                TemplateIndex::new(),
+                            )),
+                        })
+                    }
+                    SerializationFormat::Cbor | SerializationFormat::Json => {
+                        Ok(TemplateFile::Stream { file, index: None })
+                    }
+                    #[cfg(feature = "parquet")]
+                    SerializationFormat::Parquet => {
+                        let table = TemplateTable::new(file.into_inner())?;
+                        Ok(TemplateFile::Table(Box::new(table)))
+                    }
+                });
+        Ok((extractor, files.collect::<Result<_>>()?))
+    }
+
+    /// Serializes the templates in `nodes` that are to be dumped,
+    /// grouped by the id of the file that they belong in.
+    fn extract(
+        &self,
+        page: &Page,
+        nodes: &[Node],
+    ) -> Result<Vec<(usize, TemplateOutput)>> {
+        let mut templates_to_print: HashMap<usize, Vec<TemplateToDump>> =
+            HashMap::new();
+        let wikitext = &page.text;
+        let sections = if self.include_context {
+            Some(Section::new(wikitext, nodes))
+        } else {
+            None
+        };
+        let visitor = TemplateVisitor::new(wikitext);
+        visitor.visit_with_ancestors(
+            nodes,
+            &mut |mut template, template_node, ancestors| {
+                let name = Title::new(
+                    &template.name,
+                    Namespace::Template,
+                    &self.namespace_table,
+                )
+                .ok()
+                .filter(|title| title.namespace() == Namespace::Template)
+                .map(Title::into_name);
+                if let Some(name) = name {
+                    let target = self
+                        .template_redirects
+                        .as_ref()
+                        .and_then(|redirects| redirects.get(&name))
+                        .filter(|target| {
+                            self.template_to_file.contains_key(*target)
+                        });
+                    let (name, alias) = match target {
+                        Some(target) => {
+                            let alias = std::mem::replace(
+                                &mut template.name,
+                                Cow::Owned(target.clone()),
+                            );
+                            (target.clone(), Some(alias))
+                        }
+                        None => (name, None),
+                    };
+                    if let Some(&file) = self.template_to_file.get(&name) {
+                        if let Some(normalizations) =
+                            &self.template_normalizations
+                        {
+                            template.name = normalizations
+                                .get(&name)
+                                .map(|normalized| {
+                                    Cow::Borrowed(normalized.as_ref())
+                                })
+                                .unwrap_or_else(|| Cow::Owned(name));
+                        }
+                        let templates = templates_to_print
+                            .entry(file)
+                            .or_insert_with(Vec::new);
+                        let parsed_parameters = match template_node {
+                            Node::Template { parameters, .. }
+                                if self.parse_values =>
+                            {
+                                Some(parse_parameters(wikitext, parameters))
+                            }
+                            _ => None,
+                        };
+                        let context = sections.as_ref().map(|sections| {
+                            TemplateContext::new(
+                                wikitext,
+                                template_node,
+                                ancestors,
+                                sections,
+                            )
+                        });
+                        templates.push(TemplateToDump::new(
+                            template_node.get_text_from(&wikitext),
+                            template,
+                            alias,
+                            self.include_text,
+                            self.include_duplicates,
+                            parsed_parameters,
+                            context,
+                        ));
+                    }
+                }
+            },
+        );
+        templates_to_print
+            .into_iter()
+            .map(|(file, templates)| {
+                let output = TemplatesInPage {
+                    title: &page.title,
+                    templates: &templates,
+                };
+                let mut serialized = Vec::new();
+                match self.format {
+                    SerializationFormat::Json => {
+                        serde_json::to_writer(&mut serialized, &output)?;
+                        serialized.push(b'\n');
+                    }
+                    SerializationFormat::Cbor => {
+                        serde_cbor::to_writer(&mut serialized, &output)?;
+                    }
+                    #[cfg(feature = "parquet")]
+                    SerializationFormat::Parquet => {
+                        let rows = TemplateRows {
+                            title: page.title.clone(),
+                            templates: templates
+                                .into_iter()
+                                .map(TemplateToDump::into_row)
+                                .collect::<Result<_>>()?,
+                        };
+                        return Ok((file, TemplateOutput::Rows(rows)));
+                    }
+                }
+                Ok((file, TemplateOutput::Serialized(serialized)))
+            })
+            .collect()
+    }
+}
+
+fn write_templates(
+    files: &mut [TemplateFile],
+    title: &str,
+    outputs: Vec<(usize, TemplateOutput)>,
+) -> Result<()> {
+    for (file, output) in outputs {
+        files[file].write(title, output)?;
+    }
+    Ok(())
+}
+
+fn finish_templates(files: Vec<TemplateFile>) -> Result<()> {
+    for file in files {
+        file.finish()?;
+    }
+    Ok(())
+}
+
 fn dump_parsed_templates(
     options: DumpParsedTemplates,
     main_start: Instant,
     verbose: bool,
 ) -> Result<()> {
     let DumpParsedTemplates {
-        format,
-        files: template_to_file,
-        template_normalizations,
-        include_text,
+        templates,
         dump_options:
             DumpOptions {
                 pages,
                 namespaces,
+                configuration,
                 dump_file,
+                jobs,
             },
     } = options;
-    let template_normalizations_ref = template_normalizations.as_ref();
-    let parser = parse_dump(dump_file).take(pages);
-    let mut files = FilePool::new();
-    let extension = match format {
-        SerializationFormat::Cbor => ".cbor",
-        SerializationFormat::Json => ".jsonl",
-    };
-    let template_to_file = template_to_file
-        .into_iter()
-        .map(|(template, path)| {
-            let normalized = normalize_title(&template).map_err(|e| {
-                Error::TemplateNameNormalization { title: template, cause: e }
-            })?;
-            let path =
-            path.unwrap_or_else(|| normalized.clone() + extension);
-            let file = files.create(&path).map_err(|e| {
-                Error::IoError { action: "create", path: path.into(), cause: e }
-            })?;
-            Ok((
-                normalized,
-                file,
-            ))
-        })
-        .collect::<Result<HashMap<_, _>>>()?;
-    let configuration = dump_parser::wiktionary_configuration();
+    let parser = parse_dump(dump_file)
+        .take(pages)
+        .filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+    let (extractor, mut files) =
+        TemplateExtractor::new(templates, Path::new(""))?;
     let start_time = main_start.elapsed();
     let parse_start = Instant::now();
-    for page in parser {
-        let page = page?;
-        if !namespaces.contains(
-            &page.namespace
-        ) {
-            continue;
-        }
-        let mut templates_to_print: HashMap<
-            ShareableHashableFile,
-            Vec<TemplateToDump>,
-        > = HashMap::new();
-        let wikitext = &page.text;
-        let output = configuration.parse(wikitext);
-        if verbose {
-            print_parser_warnings(&page, &output.warnings);
-        }
-        let visitor = TemplateVisitor::new(wikitext);
-        visitor.visit(&output.nodes, &mut |mut template, template_node| {
-            if let Ok(name) = normalize_title(&template.name) {
-                if let Some(file) = template_to_file.get(&name) {
-                    if let Some(normalizations) = template_normalizations_ref {
-                        template.name = normalizations
-                            .get(&name)
-                            .map(|normalized| {
-                                Cow::Borrowed(normalized.as_ref())
-                            })
-                            .unwrap_or_else(|| Cow::Owned(name));
-                    }
-                    let templates = templates_to_print
-                        .entry(file.clone())
-                        .or_insert_with(Vec::new);
-                    templates.push(TemplateToDump::new(
-                        template_node.get_text_from(&wikitext),
-                        template,
-                        include_text,
-                    ));
-                }
-            }
-        });
-        for (mut file, templates) in templates_to_print {
-            let output = TemplatesInPage {
-                title: &page.title,
-                templates: &templates,
-            };
-            let mut writer = &mut *file.borrow_mut();
-            match format {
-                SerializationFormat::Json => {
-                    serde_json::to_writer(&mut writer, &output)?;
-                    write!(&mut writer, "\n").unwrap();
-                }
-                SerializationFormat::Cbor => {
-                    serde_cbor::to_writer(&mut writer, &output)?;
-                }
+    parse_pages(
+        parser,
+        &configuration,
+        jobs,
+        |page, output| {
+            if verbose {
+                print_parser_warnings(page, &output.warnings);
             }
-        }
-    }
+            extractor.extract(page, &output.nodes)
+        },
+        |page, serialized| -> Result<()> {
+            write_templates(&mut files, &page.title, serialized?)
+        },
+    )?;
+    finish_templates(files)?;
     let parse_time = parse_start.elapsed();
     eprintln!(
         "startup took {}, parsing and printing {}",
@@ -325,6 +824,235 @@ fn dump_parsed_templates(
     Ok(())
 }
 
+#[derive(Debug, PartialEq, Serialize)]
+struct DuplicateArgsInTemplate {
+    name: String,
+    overridden: Vec<(String, String)>,
+}
+
+#[derive(Serialize)]
+struct DuplicateArgsInPage<'a> {
+    title: &'a str,
+    templates: Vec<DuplicateArgsInTemplate>,
+}
+
+// Parser functions like `{{#switch:}}` are skipped because MediaWiki only
+// tracks duplicate arguments to templates.
+fn duplicate_args_in_page(
+    wikitext: &str,
+    nodes: &[Node],
+    namespace_table: &NamespaceTable,
+) -> Vec<DuplicateArgsInTemplate> {
+    let mut templates = Vec::new();
+    TemplateVisitor::new(wikitext).visit(nodes, &mut |template, _| {
+        let is_template = matches!(
+            Title::new(&template.name, Namespace::Template, namespace_table),
+            Ok(title) if title.namespace() == Namespace::Template
+        );
+        if is_template && template.has_duplicates() {
+            let template = TemplateOwned::from(template);
+            templates.push(DuplicateArgsInTemplate {
+                name: template.name,
+                overridden: template.overridden,
+            });
+        }
+    });
+    templates
+}
+
+fn duplicate_args(
+    json: bool,
+    namespace_table: NamespaceTable,
+    dump_options: DumpOptions,
+    verbose: bool,
+) -> Result<()> {
+    let DumpOptions {
+        pages,
+        namespaces,
+        configuration,
+        dump_file,
+        jobs,
+    } = dump_options;
+    let parser = parse_dump(dump_file)
+        .take(pages)
+        .filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+    let stdout = io::stdout();
+    let mut stdout = BufWriter::new(stdout.lock());
+    parse_pages(
+        parser,
+        &configuration,
+        jobs,
+        |page, output| {
+            if verbose {
+                print_parser_warnings(page, &output.warnings);
+            }
+            duplicate_args_in_page(&page.text, &output.nodes, &namespace_table)
+        },
+        |page, templates| -> Result<()> {
+            if templates.is_empty() {
+                return Ok(());
+            }
+            let written = if json {
+                let entry = DuplicateArgsInPage {
+                    title: &page.title,
+                    templates,
+                };
+                serde_json::to_writer(&mut stdout, &entry)?;
+                writeln!(stdout)
+            } else {
+                writeln!(stdout, "{}", page.title)
+            };
+            written.map_err(|e| Error::IoError {
+                action: "write",
+                path: "stdout".into(),
+                cause: e,
+            })
+        },
+    )?;
+    stdout.flush().map_err(|e| Error::IoError {
+        action: "write",
+        path: "stdout".into(),
+        cause: e,
+    })
+}
+
+#[derive(Serialize)]
+struct LanguageSectionToDump<'a> {
+    title: &'a str,
+    #[serde(flatten)]
+    section: LanguageSection<'a>,
+}
+
+fn split_languages(
+    languages: Vec<String>,
+    language_codes: LanguageCodes,
+    dump_options: DumpOptions,
+    verbose: bool,
+) -> Result<()> {
+    let DumpOptions {
+        pages,
+        namespaces,
+        configuration,
+        dump_file,
+        jobs,
+    } = dump_options;
+    let parser = parse_dump(dump_file)
+        .take(pages)
+        .filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+    let stdout = io::stdout();
+    let mut stdout = BufWriter::new(stdout.lock());
+    parse_pages(
+        parser,
+        &configuration,
+        jobs,
+        |page, output| {
+            if verbose {
+                print_parser_warnings(page, &output.warnings);
+            }
+            // Send the language names and ranges, which don't borrow the
+            // parser output, back to this thread.
+            language_sections(&page.text, &output.nodes, &language_codes)
+                .into_iter()
+                .filter(|section| {
+                    languages.is_empty()
+                        || languages.iter().any(|l| section.is_language(l))
+                })
+                .map(|section| (section.language.to_string(), section.range))
+                .collect::<Vec<_>>()
+        },
+        |page, sections| -> Result<()> {
+            for (language, range) in sections {
+                let section = LanguageSection::new(
+                    &page.text,
+                    &language,
+                    range,
+                    &language_codes,
+                );
+                let entry = LanguageSectionToDump {
+                    title: &page.title,
+                    section,
+                };
+                serde_json::to_writer(&mut stdout, &entry)?;
+                writeln!(stdout).map_err(|e| Error::IoError {
+                    action: "write",
+                    path: "stdout".into(),
+                    cause: e,
+                })?;
+            }
+            Ok(())
+        },
+    )?;
+    stdout.flush().map_err(|e| Error::IoError {
+        action: "write",
+        path: "stdout".into(),
+        cause: e,
+    })
+}
+
+fn print_page_text(
+    dump_path: PathBuf,
+    index_path: PathBuf,
+    titles: Vec<String>,
+) -> Result<()> {
+    let mut reader = MultistreamReader::open(dump_path, index_path)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for title in titles {
+        let page = reader
+            .get_by_title(&title)?
+            .ok_or_else(|| Error::PageNotFound(title))?;
+        writeln!(stdout, "{}", page.text).map_err(|e| Error::IoError {
+            action: "write",
+            path: "stdout".into(),
+            cause: e,
+        })?;
+    }
+    Ok(())
+}
+
+fn print_template_redirects(
+    templates: Vec<(String, Option<String>)>,
+    path_format: String,
+    redirects: RedirectSource,
+    namespace_table: NamespaceTable,
+) -> Result<()> {
+    let redirects_by_target = redirects.load()?;
+    let stdout = io::stdout();
+    let mut stdout = BufWriter::new(stdout.lock());
+    let with_redirects = add_redirects(
+        templates,
+        &redirects_by_target,
+        &path_format,
+        &namespace_table,
+    )?;
+    with_redirects
+        .iter()
+        .try_for_each(|(template, path)| {
+            writeln!(stdout, "{}\t{}", template, path)
+        })
+        .and_then(|_| stdout.flush())
+        .map_err(|e| Error::IoError {
+            action: "write",
+            path: "stdout".into(),
+            cause: e,
+        })
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn export_sqlite(
+    _options: args::ExportSqlite,
+    _main_start: Instant,
+    _verbose: bool,
+) -> Result<()> {
+    Err(Error::FeatureNotEnabled("sqlite"))
+}
+
 fn try_main() -> Result<()> {
     let main_start = Instant::now();
     let opts = args::get_opts()?;
@@ -335,14 +1063,28 @@ fn try_main() -> Result<()> {
         }
         CommandData::AllHeaders {
             pretty,
+            output,
+            compression,
             dump_options: opts,
         } => {
             let parser = parse_dump(opts.dump_file);
             let mut dumper = HeaderStats::new();
             let start_time = main_start.elapsed();
             let parse_start = Instant::now();
-            dumper.parse(parser, opts.pages, opts.namespaces, verbose);
-            do_dumping(&dumper, pretty).unwrap_or_else(|e| eprintln!("{}", e));
+            dumper.parse(
+                parser,
+                &opts.configuration,
+                opts.pages,
+                opts.namespaces,
+                opts.jobs,
+                verbose,
+            )?;
+            write_headers(
+                Headers::Stats(&dumper),
+                pretty,
+                output.as_deref(),
+                compression,
+            )?;
             let parse_time = parse_start.elapsed();
             eprintln!(
                 "startup took {}, parsing and printing {}",
@@ -354,6 +1096,8 @@ fn try_main() -> Result<()> {
             top_level_headers,
             other_headers,
             pretty,
+            output,
+            compression,
             dump_options: opts,
         } => {
             let parser = parse_dump(opts.dump_file);
@@ -361,8 +1105,126 @@ fn try_main() -> Result<()> {
                 HeaderFilterer::new(top_level_headers, other_headers);
             let start_time = main_start.elapsed();
             let parse_start = Instant::now();
-            filterer.parse(parser, opts.pages, opts.namespaces, verbose);
-            do_dumping(&filterer, pretty)?;
+            filterer.parse(
+                parser,
+                &opts.configuration,
+                opts.pages,
+                opts.namespaces,
+                opts.jobs,
+                verbose,
+            )?;
+            write_headers(
+                Headers::Filtered(&filterer),
+                pretty,
+                output.as_deref(),
+                compression,
+            )?;
+            let parse_time = parse_start.elapsed();
+            eprintln!(
+                "startup took {}, parsing and printing {}",
+                print_time(&start_time).unwrap(),
+                print_time(&parse_time).unwrap()
+            );
+        }
+        CommandData::AddTemplateRedirects {
+            templates,
+            path_format,
+            redirects,
+            namespace_table,
+        } => {
+            print_template_redirects(
+                templates,
+                path_format,
+                redirects,
+                namespace_table,
+            )?;
+        }
+        CommandData::Run { jobs, dump_options } => {
+            run::run_jobs(jobs, dump_options, main_start, verbose)?;
+        }
+        CommandData::TemplateStats {
+            templates,
+            format,
+            samples,
+            pretty,
+            namespace_table,
+            dump_options: opts,
+        } => {
+            let parser = parse_dump(opts.dump_file);
+            let mut stats = TemplateStats::new(samples);
+            let start_time = main_start.elapsed();
+            let parse_start = Instant::now();
+            stats.parse(
+                parser,
+                &opts.configuration,
+                &namespace_table,
+                templates.as_ref(),
+                opts.pages,
+                opts.namespaces,
+                opts.jobs,
+                verbose,
+            )?;
+            match format {
+                StatsFormat::Json => do_dumping(&stats, pretty)?,
+                StatsFormat::Csv => {
+                    let stdout = io::stdout();
+                    let mut stdout = BufWriter::new(stdout.lock());
+                    stats
+                        .write_csv(&mut stdout)
+                        .and_then(|_| stdout.flush())
+                        .map_err(|e| Error::IoError {
+                            action: "write",
+                            path: "stdout".into(),
+                            cause: e,
+                        })?;
+                }
+            }
+            let parse_time = parse_start.elapsed();
+            eprintln!(
+                "startup took {}, parsing and printing {}",
+                print_time(&start_time).unwrap(),
+                print_time(&parse_time).unwrap()
+            );
+        }
+        CommandData::CheckTemplateData {
+            template_data,
+            format,
+            pretty,
+            namespace_table,
+            dump_options: opts,
+        } => {
+            let parser = parse_dump(opts.dump_file);
+            let mut report = ValidationReport::new();
+            let start_time = main_start.elapsed();
+            let parse_start = Instant::now();
+            report.parse(
+                parser,
+                &opts.configuration,
+                &template_data,
+                &namespace_table,
+                opts.pages,
+                opts.namespaces,
+                opts.jobs,
+                verbose,
+            )?;
+            match format {
+                ReportFormat::Json => do_dumping(&report, pretty)?,
+                ReportFormat::Wikitext => {
+                    let template_prefix = namespace_table
+                        .name(Namespace::Template)
+                        .unwrap_or("Template");
+                    let stdout = io::stdout();
+                    let mut stdout = BufWriter::new(stdout.lock());
+                    report
+                        .write_wikitext(&mut stdout, template_prefix)
+                        .and_then(|_| stdout.flush())
+                        .map_err(|e| Error::IoError {
+                            action: "write",
+                            path: "stdout".into(),
+                            cause: e,
+                        })?;
+                }
+            }
             let parse_time = parse_start.elapsed();
             eprintln!(
                 "startup took {}, parsing and printing {}",
@@ -370,6 +1232,37 @@ fn try_main() -> Result<()> {
                 print_time(&parse_time).unwrap()
             );
         }
+        CommandData::DuplicateArgs {
+            json,
+            namespace_table,
+            dump_options,
+        } => {
+            duplicate_args(json, namespace_table, dump_options, verbose)?;
+        }
+        CommandData::ExportSqlite(options) => {
+            export_sqlite(options, main_start, verbose)?;
+        }
+        CommandData::QueryTemplates {
+            paths,
+            format,
+            query,
+        } => {
+            query_templates(&paths, format, &query)?;
+        }
+        CommandData::SplitLanguages {
+            languages,
+            language_codes,
+            dump_options,
+        } => {
+            split_languages(languages, language_codes, dump_options, verbose)?;
+        }
+        CommandData::PageText {
+            dump_path,
+            index_path,
+            titles,
+        } => {
+            print_page_text(dump_path, index_path, titles)?;
+        }
         CommandData::Completions { shell } => {
             Args::clap().gen_completions_to(
                 env!("CARGO_PKG_NAME"),
@@ -387,3 +1280,63 @@ fn main() {
         std::process::exit(1);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        duplicate_args_in_page, DuplicateArgsInTemplate, TemplateContext,
+    };
+    use dump_parser::{Configuration, NamespaceTable};
+    use template_iter::{parse_wiki_text_ext::Section, TemplateVisitor};
+
+    #[test]
+    fn template_context() {
+        let configuration = Configuration::default();
+        let wikitext = "{{top}}\n==English==\n===Noun===\n{{a|{{b|{{c}}}}}}";
+        let output = configuration.parse(wikitext);
+        let sections = Section::new(wikitext, &output.nodes);
+        let mut contexts = Vec::new();
+        TemplateVisitor::new(wikitext).visit_with_ancestors(
+            &output.nodes,
+            &mut |template, node, ancestors| {
+                let context =
+                    TemplateContext::new(wikitext, node, ancestors, &sections);
+                contexts.push((
+                    template.name.into_owned(),
+                    context.range,
+                    context.headings,
+                    context.depth,
+                    context.parent,
+                ));
+            },
+        );
+        let english_noun = vec!["English", "Noun"];
+        assert_eq!(
+            contexts,
+            vec![
+                ("top".to_string(), 0..7, vec![], 0, None),
+                ("c".to_string(), 39..44, english_noun.clone(), 2, Some("b")),
+                ("b".to_string(), 35..46, english_noun.clone(), 1, Some("a")),
+                ("a".to_string(), 31..48, english_noun, 0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_args() {
+        let configuration = Configuration::default();
+        let table = NamespaceTable::english_wiktionary();
+        let wikitext = "{{t|1=a|b}}{{#switch:a|b=1|b=2}}{{u| x =1|x=2}}\
+            {{v|a|b}}";
+        let output = configuration.parse(wikitext);
+        let entry =
+            |name: &str, key: &str, value: &str| DuplicateArgsInTemplate {
+                name: name.to_string(),
+                overridden: vec![(key.to_string(), value.to_string())],
+            };
+        assert_eq!(
+            duplicate_args_in_page(wikitext, &output.nodes, &table),
+            vec![entry("t", "1", "a"), entry("u", " x ", "1")]
+        );
+    }
+}
@@ -0,0 +1,590 @@
+// The `query-templates` subcommand, which reads the CBOR or JSON Lines files
+// written by `dump-parsed-templates` and prints the template instances that
+// match some predicates, without reading the XML dump. When the query is
+// limited to some pages, CBOR files are read through their indexes if they
+// have them.
+
+use dump_parser::{input::decompress, Namespace, NamespaceTable};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    result::Result as StdResult,
+    str::FromStr,
+};
+use template_iter::Title;
+
+use crate::{
+    args::SerializationFormat,
+    compression::OutputCompression,
+    error::{Error, Result},
+    template_index::{
+        index_path, DumpedTemplate, IndexedTemplateDump, ParameterValue,
+        TemplatesInPage,
+    },
+};
+
+/// A condition on the parameters of a template instance.
+#[derive(Debug)]
+pub enum Predicate {
+    /// `has:KEY`
+    Has(String),
+    /// `missing:KEY`
+    Missing(String),
+    /// `KEY=VALUE` or, negated, `KEY!=VALUE`
+    Equals {
+        key: String,
+        value: String,
+        negated: bool,
+    },
+    /// `KEY=~REGEX` or, negated, `KEY!~REGEX`
+    Matches {
+        key: String,
+        regex: Regex,
+        negated: bool,
+    },
+}
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        if let Some(key) = s.strip_prefix("has:") {
+            return Ok(Predicate::Has(key.into()));
+        }
+        if let Some(key) = s.strip_prefix("missing:") {
+            return Ok(Predicate::Missing(key.into()));
+        }
+        let operators = ["!~", "=~", "!=", "="];
+        let (i, operator) = operators
+            .iter()
+            .filter_map(|op| s.find(op).map(|i| (i, *op)))
+            .min_by_key(|&(i, op)| (i, std::cmp::Reverse(op.len())))
+            .ok_or_else(|| {
+                format!(
+                    "invalid predicate {}: expected has:KEY, missing:KEY, \
+                     KEY=VALUE, KEY!=VALUE, KEY=~REGEX or KEY!~REGEX",
+                    s
+                )
+            })?;
+        let key = s[..i].to_string();
+        let value = &s[i + operator.len()..];
+        let negated = operator.starts_with('!');
+        if operator.ends_with('~') {
+            let regex = Regex::new(value)
+                .map_err(|e| format!("invalid regex in {}: {}", s, e))?;
+            Ok(Predicate::Matches {
+                key,
+                regex,
+                negated,
+            })
+        } else {
+            Ok(Predicate::Equals {
+                key,
+                value: value.into(),
+                negated,
+            })
+        }
+    }
+}
+
+impl Predicate {
+    /// Whether a template instance satisfies the predicate. A negated
+    /// comparison is satisfied if the parameter is missing. Parsed values
+    /// are compared as JSON.
+    pub fn matches(&self, template: &DumpedTemplate) -> bool {
+        let get = |key| template.parameters.get(key).map(|v| v.as_text());
+        match self {
+            Predicate::Has(key) => template.parameters.contains_key(key),
+            Predicate::Missing(key) => !template.parameters.contains_key(key),
+            Predicate::Equals {
+                key,
+                value,
+                negated,
+            } => (get(key).as_deref() == Some(value)) != *negated,
+            Predicate::Matches {
+                key,
+                regex,
+                negated,
+            } => {
+                let is_match = matches!(
+                    get(key),
+                    Some(v) if regex.is_match(&v)
+                );
+                is_match != *negated
+            }
+        }
+    }
+}
+
+/// A value to print for each matching template instance.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Name,
+    /// A parameter, written as its key or, for parameters named `title` or
+    /// `name`, as `param:KEY`.
+    Parameter(String),
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        let field = match s {
+            "title" => Field::Title,
+            "name" => Field::Name,
+            _ => {
+                Field::Parameter(s.strip_prefix("param:").unwrap_or(s).into())
+            }
+        };
+        Ok(field)
+    }
+}
+
+impl Field {
+    fn name(&self) -> &str {
+        match self {
+            Field::Title => "title",
+            Field::Name => "name",
+            Field::Parameter(key) => key,
+        }
+    }
+
+    // Parsed parameter values are printed as JSON.
+    fn get<'a>(
+        &self,
+        title: &'a str,
+        template: &'a DumpedTemplate,
+    ) -> Option<Cow<'a, str>> {
+        match self {
+            Field::Title => Some(Cow::Borrowed(title)),
+            Field::Name => Some(Cow::Borrowed(&template.name)),
+            Field::Parameter(key) => {
+                template.parameters.get(key).map(ParameterValue::as_text)
+            }
+        }
+    }
+
+    // Like `get`, but keeps parsed parameter values as arrays.
+    fn to_json(&self, title: &str, template: &DumpedTemplate) -> Value {
+        match self {
+            Field::Parameter(key) => match template.parameters.get(key) {
+                Some(ParameterValue::Wikitext(text)) => Value::from(&**text),
+                Some(ParameterValue::Parsed(value)) => value.clone(),
+                None => Value::Null,
+            },
+            _ => self.get(title, template).map_or(Value::Null, Value::from),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum QueryOutput {
+    Json,
+    Tsv,
+    Count,
+}
+
+impl FromStr for QueryOutput {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        let output = match s.to_lowercase().as_str() {
+            "json" => QueryOutput::Json,
+            "tsv" => QueryOutput::Tsv,
+            "count" => QueryOutput::Count,
+            _ => return Err("unrecognized output"),
+        };
+        Ok(output)
+    }
+}
+
+pub struct Query {
+    /// Page titles, or empty for all pages.
+    pub titles: Vec<String>,
+    /// Template names resolved with `namespace_table`, or empty for all
+    /// templates.
+    pub templates: Vec<String>,
+    pub predicates: Vec<Predicate>,
+    pub fields: Vec<Field>,
+    pub output: QueryOutput,
+    /// Resolves the names in the dumps, which are as they were written in
+    /// the pages, so that `{{Template:l}}` is an instance of `l`.
+    pub namespace_table: NamespaceTable,
+}
+
+impl Query {
+    fn includes_page(&self, title: &str) -> bool {
+        self.titles.is_empty() || self.titles.iter().any(|t| t == title)
+    }
+
+    fn matches(&self, template: &DumpedTemplate) -> bool {
+        (self.templates.is_empty()
+            || matches!(
+                Title::new(
+                    &template.name,
+                    Namespace::Template,
+                    &self.namespace_table,
+                ),
+                Ok(title) if title.namespace() == Namespace::Template
+                    && self.templates.iter().any(|t| t == title.name())
+            ))
+            && self.predicates.iter().all(|p| p.matches(template))
+    }
+}
+
+#[derive(Serialize)]
+struct MatchToPrint<'a> {
+    title: &'a str,
+    #[serde(flatten)]
+    template: &'a DumpedTemplate,
+}
+
+// Guesses the format of a template dump from its extension, skipping the
+// extension of a compression format.
+fn dump_format(path: &Path) -> Option<SerializationFormat> {
+    let path = match OutputCompression::from_extension(path) {
+        Some(_) => Path::new(path.file_stem()?),
+        None => path,
+    };
+    match path.extension()?.to_str()? {
+        "cbor" => Some(SerializationFormat::Cbor),
+        "json" | "jsonl" => Some(SerializationFormat::Json),
+        #[cfg(feature = "parquet")]
+        "parquet" => Some(SerializationFormat::Parquet),
+        _ => None,
+    }
+}
+
+// Calls `f` on each record in a template dump, which may be compressed.
+fn for_each_page<F>(
+    path: &Path,
+    format: Option<SerializationFormat>,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(TemplatesInPage) -> Result<()>,
+{
+    let format = format
+        .or_else(|| dump_format(path))
+        .ok_or_else(|| Error::UnknownTemplateDumpFormat(path.into()))?;
+    let open_error = |e| Error::IoError {
+        action: "open",
+        path: path.into(),
+        cause: e,
+    };
+    let file = File::open(path).map_err(open_error)?;
+    let reader = BufReader::new(decompress(file, 1).map_err(open_error)?);
+    match format {
+        SerializationFormat::Cbor => {
+            for page in
+                serde_cbor::Deserializer::from_reader(reader).into_iter()
+            {
+                f(page?)?;
+            }
+        }
+        SerializationFormat::Json => {
+            for page in
+                serde_json::Deserializer::from_reader(reader).into_iter()
+            {
+                f(page?)?;
+            }
+        }
+        #[cfg(feature = "parquet")]
+        SerializationFormat::Parquet => {
+            return Err(Error::ParquetUnsupported("query-templates"))
+        }
+    }
+    Ok(())
+}
+
+// Writes a TSV field, escaping backslashes, tabs and line breaks.
+fn write_tsv_field<W: Write>(writer: &mut W, field: &str) -> io::Result<()> {
+    for c in field.chars() {
+        match c {
+            '\\' => writer.write_all(b"\\\\")?,
+            '\t' => writer.write_all(b"\\t")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            _ => write!(writer, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_tsv_row<'a, W, I>(writer: &mut W, fields: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a str>,
+{
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b"\t")?;
+        }
+        write_tsv_field(writer, field)?;
+    }
+    writeln!(writer)
+}
+
+/// Prints the template instances in the dumps at `paths` that match the
+/// query.
+pub fn query_templates(
+    paths: &[PathBuf],
+    format: Option<SerializationFormat>,
+    query: &Query,
+) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = BufWriter::new(stdout.lock());
+    let write_error = |e| Error::IoError {
+        action: "write",
+        path: "stdout".into(),
+        cause: e,
+    };
+    let mut counts: HashMap<Vec<String>, usize> = HashMap::new();
+    let mut print_page = |page: TemplatesInPage| -> Result<()> {
+        let title = &page.title;
+        for template in &page.templates {
+            if !query.matches(template) {
+                continue;
+            }
+            let values = query
+                .fields
+                .iter()
+                .map(|field| field.get(title, template).unwrap_or_default());
+            match query.output {
+                QueryOutput::Json if query.fields.is_empty() => {
+                    let output = MatchToPrint { title, template };
+                    serde_json::to_writer(&mut stdout, &output)?;
+                    writeln!(stdout).map_err(write_error)?;
+                }
+                QueryOutput::Json => {
+                    let object: Map<_, _> = query
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            let value = field.to_json(title, template);
+                            (field.name().to_string(), value)
+                        })
+                        .collect();
+                    serde_json::to_writer(&mut stdout, &object)?;
+                    writeln!(stdout).map_err(write_error)?;
+                }
+                QueryOutput::Tsv if query.fields.is_empty() => {
+                    let row = vec![title.as_str(), template.name.as_str()];
+                    write_tsv_row(&mut stdout, row).map_err(write_error)?;
+                }
+                QueryOutput::Tsv => {
+                    let values: Vec<_> = values.collect();
+                    let row = values.iter().map(AsRef::as_ref);
+                    write_tsv_row(&mut stdout, row).map_err(write_error)?;
+                }
+                QueryOutput::Count => {
+                    let key = values.map(Cow::into_owned).collect();
+                    *counts.entry(key).or_insert(0) += 1;
+                }
+            }
+        }
+        Ok(())
+    };
+    for path in paths {
+        let is_cbor = matches!(
+            format.or_else(|| dump_format(path)),
+            Some(SerializationFormat::Cbor)
+        );
+        if !query.titles.is_empty() && is_cbor && index_path(path).exists() {
+            let mut dump = IndexedTemplateDump::open(path)?;
+            for title in &query.titles {
+                if let Some(page) = dump.get(title)? {
+                    print_page(page)?;
+                }
+            }
+        } else {
+            for_each_page(path, format, |page| {
+                if query.includes_page(&page.title) {
+                    print_page(page)?;
+                }
+                Ok(())
+            })?;
+        }
+    }
+    if let QueryOutput::Count = query.output {
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+        for (values, count) in counts {
+            let count = count.to_string();
+            let row = std::iter::once(count.as_str())
+                .chain(values.iter().map(String::as_str));
+            write_tsv_row(&mut stdout, row).map_err(write_error)?;
+        }
+    }
+    stdout.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dump_format, Field, Predicate, Query, QueryOutput};
+    use crate::{
+        args::SerializationFormat,
+        template_index::{DumpedTemplate, ParameterValue, TemplatesInPage},
+        TemplateContext, TemplateToDump,
+    };
+    use dump_parser::{Configuration, NamespaceTable, Node, Positioned};
+    use std::{borrow::Cow, path::Path};
+    use template_iter::{
+        parse_parameters, parse_wiki_text_ext::Section, TemplateVisitor,
+    };
+
+    #[test]
+    fn read_dumped_templates() {
+        let configuration = Configuration::default();
+        let wikitext = "==English==\n{{t|{{l|en|a}}|lang=en|lang=fr}}";
+        let output = configuration.parse(wikitext);
+        let sections = Section::new(wikitext, &output.nodes);
+        for parse_values in &[false, true] {
+            let mut templates = Vec::new();
+            TemplateVisitor::new(wikitext).visit_with_ancestors(
+                &output.nodes,
+                &mut |template, node, ancestors| {
+                    let parsed = match node {
+                        Node::Template { parameters, .. } if *parse_values => {
+                            Some(parse_parameters(wikitext, parameters))
+                        }
+                        _ => None,
+                    };
+                    let context = TemplateContext::new(
+                        wikitext, node, ancestors, &sections,
+                    );
+                    templates.push(TemplateToDump::new(
+                        node.get_text_from(wikitext),
+                        template,
+                        Some(Cow::Borrowed("alias")),
+                        true,
+                        true,
+                        parsed,
+                        Some(context),
+                    ));
+                },
+            );
+            let record = crate::TemplatesInPage {
+                title: "a",
+                templates: &templates,
+            };
+            let json = serde_json::to_vec(&record).unwrap();
+            let cbor = serde_cbor::to_vec(&record).unwrap();
+            let pages: [TemplatesInPage; 2] = [
+                serde_json::from_slice(&json).unwrap(),
+                serde_cbor::from_slice(&cbor).unwrap(),
+            ];
+            for page in &pages {
+                assert_eq!(page.title, "a");
+                assert_eq!(
+                    serde_json::to_value(&page.templates).unwrap(),
+                    serde_json::to_value(&templates).unwrap(),
+                );
+                let template = &page.templates[1];
+                assert_eq!(template.name, "t");
+                assert_eq!(
+                    matches!(
+                        template.parameters["1"],
+                        ParameterValue::Parsed(_)
+                    ),
+                    *parse_values
+                );
+                let lang = if *parse_values {
+                    r#"[{"text":"fr","type":"text"}]"#
+                } else {
+                    "fr"
+                };
+                assert_eq!(template.parameters["lang"].as_text(), lang);
+                for field in &["alias", "text", "overridden", "headings"] {
+                    assert!(template.other.contains_key(*field), "{}", field);
+                }
+                assert!(template.other.get("parent").is_none());
+                assert_eq!(page.templates[0].other["parent"], "t");
+            }
+        }
+    }
+
+    #[test]
+    fn predicates() {
+        let template = DumpedTemplate {
+            name: "t".into(),
+            parameters: vec![("1", "en"), ("2", "word"), ("tr", "")]
+                .into_iter()
+                .map(|(k, v)| {
+                    (k.to_string(), ParameterValue::Wikitext(v.to_string()))
+                })
+                .collect(),
+            other: Default::default(),
+        };
+        let matches = |predicate: &str| {
+            predicate.parse::<Predicate>().unwrap().matches(&template)
+        };
+        assert!(matches("has:tr"));
+        assert!(!matches("has:alt"));
+        assert!(matches("missing:alt"));
+        assert!(matches("1=en"));
+        assert!(!matches("1=fr"));
+        assert!(matches("1!=fr"));
+        assert!(matches("alt!=fr"));
+        assert!(!matches("alt=fr"));
+        assert!(matches("1=~^e"));
+        assert!(matches("2!~^e"));
+        assert!(!matches("2=~^e"));
+        assert!(matches("alt!~x"));
+        assert!(matches("tr="));
+        assert!(matches("2=~=|o"));
+        assert!("no operator".parse::<Predicate>().is_err());
+        assert!("1=~(".parse::<Predicate>().is_err());
+        assert_eq!("title".parse(), Ok(Field::Title));
+        assert_eq!(
+            "param:title".parse(),
+            Ok(Field::Parameter("title".into()))
+        );
+        assert_eq!("lang".parse(), Ok(Field::Parameter("lang".into())));
+    }
+
+    #[test]
+    fn template_names() {
+        let query = Query {
+            titles: Vec::new(),
+            templates: vec!["l".into()],
+            predicates: Vec::new(),
+            fields: Vec::new(),
+            output: QueryOutput::Json,
+            namespace_table: NamespaceTable::english_wiktionary(),
+        };
+        let template = |name: &str| DumpedTemplate {
+            name: name.into(),
+            parameters: Default::default(),
+            other: Default::default(),
+        };
+        for name in &["l", "Template:l", "T:l", " template : l "] {
+            assert!(query.matches(&template(name)), "{}", name);
+        }
+        for name in &["L", "m", "User:l", "#if:l"] {
+            assert!(!query.matches(&template(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn dump_formats() {
+        let format = |path| dump_format(Path::new(path));
+        assert!(matches!(format("l.cbor"), Some(SerializationFormat::Cbor)));
+        assert!(matches!(
+            format("l.cbor.zst"),
+            Some(SerializationFormat::Cbor)
+        ));
+        assert!(matches!(
+            format("l.jsonl.gz"),
+            Some(SerializationFormat::Json)
+        ));
+        assert!(format("l.zst").is_none());
+    }
+}
@@ -0,0 +1,564 @@
+// The `run` subcommand, which feeds each page of the dump to several
+// extractors, so that the dump only has to be decompressed and parsed once.
+// The extractors are listed in a job file in TOML or JSON:
+//
+// ```toml
+// [[jobs]]
+// kind = "all-headers"
+// output = "all_headers.json"
+//
+// [[jobs]]
+// kind = "dump-parsed-templates"
+// namespaces = ["main", "reconstruction"]
+// templates = ["template_names.txt"]
+// format = "cbor"
+// output_dir = "cbor"
+// ```
+//
+// Headers are written as a Parquet table if the output path ends in
+// `.parquet`, and templates if the format is `parquet`, when built with the
+// `parquet` feature. Output is compressed if a job has `compression = "zstd"`
+// or `"gzip"`, or if the output path ends in `.zst` or `.gz`.
+
+use dump_parser::{
+    parse as parse_dump, parse_pages, print_parser_warnings, Namespace,
+};
+use filter_headers::HeaderFilterer;
+use header_stats::{HeaderLevel, HeaderStats};
+use serde::Deserialize;
+use serde_json::Error as SerdeJsonError;
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    args::{DumpOptions, Job, JobKind, SerializationFormat},
+    compression::OutputCompression,
+    error::{Error, Result},
+    finish_templates, print_time, write_headers, write_templates, Headers,
+    TemplateExtractor, TemplateFile, TemplateOutput,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobFile {
+    pub jobs: Vec<JobSpec>,
+}
+
+/// A job as written in the job file. Namespaces default to those given by
+/// `--namespaces`, and paths are relative to the current directory.
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum JobSpec {
+    AllHeaders {
+        output: PathBuf,
+        #[serde(default)]
+        pretty: bool,
+        compression: Option<OutputCompression>,
+        namespaces: Option<Vec<String>>,
+    },
+    FilterHeaders {
+        output: PathBuf,
+        #[serde(default)]
+        pretty: bool,
+        compression: Option<OutputCompression>,
+        namespaces: Option<Vec<String>>,
+        #[serde(default)]
+        top_level_headers: Vec<PathBuf>,
+        #[serde(default)]
+        other_headers: Vec<PathBuf>,
+    },
+    DumpParsedTemplates {
+        output_dir: Option<PathBuf>,
+        namespaces: Option<Vec<String>>,
+        format: SerializationFormat,
+        compression: Option<OutputCompression>,
+        templates: Vec<PathBuf>,
+        #[serde(default)]
+        include_text: bool,
+        #[serde(default)]
+        include_duplicates: bool,
+        #[serde(default)]
+        include_context: bool,
+        #[serde(default)]
+        parse_values: bool,
+        template_normalizations: Option<PathBuf>,
+        #[serde(default)]
+        resolve_redirects: bool,
+        page_sql: Option<PathBuf>,
+        redirect_sql: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug)]
+pub enum JobFileError {
+    TomlError(toml::de::Error),
+    JsonError(SerdeJsonError),
+}
+
+impl Display for JobFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobFileError::TomlError(e) => write!(f, "{}", e),
+            JobFileError::JsonError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for JobFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JobFileError::TomlError(e) => Some(e),
+            JobFileError::JsonError(e) => Some(e),
+        }
+    }
+}
+
+/// Reads a job file, which is JSON if its extension is `.json`
+/// and TOML otherwise.
+pub fn read_job_file(path: &Path) -> Result<JobFile> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| Error::IoError {
+            action: "read",
+            path: path.into(),
+            cause: e,
+        })?;
+    parse_job_file(path, &contents).map_err(|cause| Error::ParseJobFile {
+        path: path.into(),
+        cause,
+    })
+}
+
+fn parse_job_file(
+    path: &Path,
+    contents: &str,
+) -> std::result::Result<JobFile, JobFileError> {
+    if path.extension().map(|e| e == "json") == Some(true) {
+        serde_json::from_str(contents).map_err(JobFileError::JsonError)
+    } else {
+        toml::from_str(contents).map_err(JobFileError::TomlError)
+    }
+}
+
+// The part of a job that is shared with the threads that parse wikitext.
+enum Extractor {
+    Headers,
+    Templates(TemplateExtractor),
+}
+
+// The part of a job that receives what was extracted from each page.
+enum Sink {
+    AllHeaders {
+        output: PathBuf,
+        pretty: bool,
+        compression: Option<OutputCompression>,
+        stats: HeaderStats,
+    },
+    FilterHeaders {
+        output: PathBuf,
+        pretty: bool,
+        compression: Option<OutputCompression>,
+        filterer: HeaderFilterer,
+    },
+    Templates {
+        files: Vec<TemplateFile>,
+    },
+}
+
+// What was extracted from a page for all jobs.
+struct Extracted {
+    // Empty unless a job that collects headers processes the page.
+    headers: Vec<(String, HeaderLevel)>,
+    // For each job, serialized templates grouped by file id.
+    templates: Vec<Vec<(usize, TemplateOutput)>>,
+}
+
+// Each job creates its own output files, so jobs that share a file would
+// overwrite each other's output.
+fn check_outputs(jobs: &[Job]) -> Result<()> {
+    let mut outputs = HashSet::new();
+    for Job { kind, .. } in jobs {
+        let paths: HashSet<PathBuf> = match kind {
+            JobKind::AllHeaders { output, .. }
+            | JobKind::FilterHeaders { output, .. } => {
+                std::iter::once(output.clone()).collect()
+            }
+            JobKind::DumpParsedTemplates {
+                output_dir,
+                templates,
+            } => TemplateExtractor::paths(templates, output_dir)?
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect(),
+        };
+        for path in paths {
+            if !outputs.insert(path.clone()) {
+                return Err(Error::SharedOutput(path));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run_jobs(
+    jobs: Vec<Job>,
+    dump_options: DumpOptions,
+    main_start: Instant,
+    verbose: bool,
+) -> Result<()> {
+    let DumpOptions {
+        pages,
+        configuration,
+        dump_file,
+        jobs: thread_count,
+        ..
+    } = dump_options;
+    check_outputs(&jobs)?;
+    let mut job_namespaces = Vec::new();
+    let mut extractors = Vec::new();
+    let mut sinks = Vec::new();
+    for Job { namespaces, kind } in jobs {
+        let (extractor, sink) = match kind {
+            JobKind::AllHeaders {
+                output,
+                pretty,
+                compression,
+            } => (
+                Extractor::Headers,
+                Sink::AllHeaders {
+                    output,
+                    pretty,
+                    compression,
+                    stats: HeaderStats::new(),
+                },
+            ),
+            JobKind::FilterHeaders {
+                output,
+                pretty,
+                compression,
+                top_level_headers,
+                other_headers,
+            } => (
+                Extractor::Headers,
+                Sink::FilterHeaders {
+                    output,
+                    pretty,
+                    compression,
+                    filterer: HeaderFilterer::new(
+                        top_level_headers,
+                        other_headers,
+                    ),
+                },
+            ),
+            JobKind::DumpParsedTemplates {
+                output_dir,
+                templates,
+            } => {
+                let (extractor, files) =
+                    TemplateExtractor::new(templates, &output_dir)?;
+                (Extractor::Templates(extractor), Sink::Templates { files })
+            }
+        };
+        job_namespaces.push(namespaces.into_iter().collect::<HashSet<_>>());
+        extractors.push(extractor);
+        sinks.push(sink);
+    }
+
+    let all_namespaces: HashSet<Namespace> =
+        job_namespaces.iter().flatten().copied().collect();
+    let parser = parse_dump(dump_file)
+        .take(pages)
+        .filter(|result| match result {
+            Ok(page) => all_namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+    let start_time = main_start.elapsed();
+    let parse_start = Instant::now();
+    parse_pages(
+        parser,
+        &configuration,
+        thread_count,
+        |page, output| {
+            if verbose {
+                print_parser_warnings(page, &output.warnings);
+            }
+            let jobs = || job_namespaces.iter().zip(&extractors);
+            let processes = |namespaces: &HashSet<Namespace>| {
+                namespaces.contains(&page.namespace)
+            };
+            let headers = if jobs().any(|(namespaces, extractor)| {
+                processes(namespaces)
+                    && matches!(extractor, Extractor::Headers)
+            }) {
+                HeaderStats::headers(page, &output.nodes)
+            } else {
+                Vec::new()
+            };
+            let templates = jobs()
+                .map(|(namespaces, extractor)| match extractor {
+                    Extractor::Templates(extractor)
+                        if processes(namespaces) =>
+                    {
+                        extractor.extract(page, &output.nodes)
+                    }
+                    _ => Ok(Vec::new()),
+                })
+                .collect::<Result<_>>()?;
+            Ok(Extracted { headers, templates })
+        },
+        |page, extracted: Result<Extracted>| -> Result<()> {
+            let Extracted { headers, templates } = extracted?;
+            for ((sink, namespaces), templates) in
+                sinks.iter_mut().zip(&job_namespaces).zip(templates)
+            {
+                if !namespaces.contains(&page.namespace) {
+                    continue;
+                }
+                match sink {
+                    Sink::AllHeaders { stats, .. } => {
+                        for (header, level) in &headers {
+                            stats.add_header(header.clone(), *level);
+                        }
+                    }
+                    Sink::FilterHeaders { filterer, .. } => {
+                        let headers = headers
+                            .iter()
+                            .map(|(header, level)| (header.as_str(), *level));
+                        filterer.add_page(&page.title, headers);
+                    }
+                    Sink::Templates { files } => {
+                        write_templates(files, &page.title, templates)?;
+                    }
+                }
+            }
+            Ok(())
+        },
+    )?;
+
+    for sink in sinks {
+        match sink {
+            Sink::AllHeaders {
+                output,
+                pretty,
+                compression,
+                stats,
+            } => write_headers(
+                Headers::Stats(&stats),
+                pretty,
+                Some(&output),
+                compression,
+            )?,
+            Sink::FilterHeaders {
+                output,
+                pretty,
+                compression,
+                filterer,
+            } => write_headers(
+                Headers::Filtered(&filterer),
+                pretty,
+                Some(&output),
+                compression,
+            )?,
+            Sink::Templates { files } => finish_templates(files)?,
+        }
+    }
+    let parse_time = parse_start.elapsed();
+    eprintln!(
+        "startup took {}, parsing and printing {}",
+        print_time(&start_time).unwrap(),
+        print_time(&parse_time).unwrap()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_outputs, parse_job_file, run_jobs, JobSpec};
+    use crate::{
+        args::{
+            DumpOptions, Job, JobKind, SerializationFormat,
+            TemplateDumpOptions,
+        },
+        error::Error,
+    };
+    use dump_parser::{Configuration, Namespace, NamespaceTable};
+    use std::{fs, io::Cursor, path::Path, time::Instant};
+
+    #[test]
+    fn job_files() {
+        let toml = r#"
+            [[jobs]]
+            kind = "all-headers"
+            output = "all_headers.json"
+
+            [[jobs]]
+            kind = "dump-parsed-templates"
+            namespaces = ["main", "reconstruction"]
+            templates = ["template_names.txt"]
+            format = "cbor"
+            compression = "zstd"
+        "#;
+        let json = r#"{"jobs": [
+            {"kind": "filter-headers", "output": "f.json", "pretty": true},
+            {"kind": "all-headers", "output": "a.json.gz"}
+        ]}"#;
+        let jobs = parse_job_file(Path::new("jobs.toml"), toml).unwrap().jobs;
+        assert!(matches!(
+            &jobs[..],
+            [
+                JobSpec::AllHeaders {
+                    pretty: false,
+                    compression: None,
+                    namespaces: None,
+                    ..
+                },
+                JobSpec::DumpParsedTemplates {
+                    format: SerializationFormat::Cbor,
+                    compression: Some(_),
+                    namespaces: Some(namespaces),
+                    include_text: false,
+                    ..
+                },
+            ] if namespaces.len() == 2
+        ));
+        let jobs = parse_job_file(Path::new("jobs.json"), json).unwrap().jobs;
+        assert!(matches!(
+            &jobs[..],
+            [
+                JobSpec::FilterHeaders {
+                    pretty: true,
+                    top_level_headers,
+                    ..
+                },
+                JobSpec::AllHeaders { .. },
+            ] if top_level_headers.is_empty()
+        ));
+        for invalid in &[
+            "[[jobs]]\nkind = \"all-headers\"",
+            "[[jobs]]\nkind = \"headers\"\noutput = \"h.json\"",
+            "[[jobs]]\nkind = \"all-headers\"\noutput = \"h\"\nx = 1",
+        ] {
+            assert!(parse_job_file(Path::new("jobs.toml"), invalid).is_err());
+        }
+        assert!(parse_job_file(Path::new("jobs.json"), toml).is_err());
+    }
+
+    fn template_job(
+        output_dir: &Path,
+        templates: &[&str],
+        namespaces: Vec<Namespace>,
+    ) -> Job {
+        Job {
+            namespaces,
+            kind: JobKind::DumpParsedTemplates {
+                output_dir: output_dir.into(),
+                templates: TemplateDumpOptions {
+                    format: SerializationFormat::Json,
+                    compression: None,
+                    files: templates
+                        .iter()
+                        .map(|name| (name.to_string(), None))
+                        .collect(),
+                    template_normalizations: None,
+                    template_redirects: None,
+                    include_text: false,
+                    include_duplicates: false,
+                    include_context: false,
+                    parse_values: false,
+                    namespace_table: NamespaceTable::english_wiktionary(),
+                },
+            },
+        }
+    }
+
+    fn page(title: &str, namespace: Namespace, text: &str) -> String {
+        format!(
+            "<page><title>{}</title><ns>{}</ns>\
+            <revision><text xml:space=\"preserve\">{}</text></revision>\
+            </page>",
+            title, namespace.0, text
+        )
+    }
+
+    #[test]
+    fn dispatch_pages_to_jobs() {
+        let dir = std::env::temp_dir()
+            .join(format!("wiktionary-data-run-{}", std::process::id()));
+        let (main_dir, both_dir) = (dir.join("main"), dir.join("both"));
+        for dir in &[&main_dir, &both_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let dump = format!(
+            "<mediawiki>{}{}{}</mediawiki>",
+            page("word", Namespace::Main, "==English==\n{{l|en|a}}{{m|en|b}}"),
+            page(
+                "Reconstruction:word",
+                Namespace::Reconstruction,
+                "==Proto-Germanic==\n{{l|x|c}}{{m|x|d}}"
+            ),
+            page("Template:l", Namespace::Template, "==Usage==\n{{m|x|e}}"),
+        );
+        let headers_path = dir.join("all_headers.json");
+        let both = vec![Namespace::Main, Namespace::Reconstruction];
+        let jobs = vec![
+            Job {
+                namespaces: vec![Namespace::Main],
+                kind: JobKind::AllHeaders {
+                    output: headers_path.clone(),
+                    pretty: false,
+                    compression: None,
+                },
+            },
+            template_job(&main_dir, &["l"], vec![Namespace::Main]),
+            template_job(&both_dir, &["l", "m"], both.clone()),
+        ];
+        let dump_options = DumpOptions {
+            pages: usize::MAX,
+            namespaces: Vec::new(),
+            configuration: Configuration::default(),
+            dump_file: Box::new(Cursor::new(dump)),
+            jobs: 2,
+        };
+        run_jobs(jobs, dump_options, Instant::now(), false).unwrap();
+
+        let headers = fs::read_to_string(&headers_path).unwrap();
+        assert!(headers.contains("English"));
+        assert!(!headers.contains("Proto-Germanic"));
+        assert!(!headers.contains("Usage"));
+        let titles = |path: &Path| -> Vec<String> {
+            fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let page: serde_json::Value =
+                        serde_json::from_str(line).unwrap();
+                    page["title"].as_str().unwrap().to_string()
+                })
+                .collect()
+        };
+        assert_eq!(titles(&main_dir.join("l.jsonl")), vec!["word"]);
+        for template in &["l.jsonl", "m.jsonl"] {
+            assert_eq!(
+                titles(&both_dir.join(template)),
+                vec!["word", "Reconstruction:word"]
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        let shared = vec![
+            template_job(&main_dir, &["l"], vec![Namespace::Main]),
+            template_job(&main_dir, &["m", "l"], both),
+        ];
+        assert!(matches!(
+            check_outputs(&shared),
+            Err(Error::SharedOutput(path)) if path == main_dir.join("l.jsonl")
+        ));
+    }
+}
@@ -0,0 +1,545 @@
+// The `export-sqlite` subcommand, which writes the pages in a dump, their
+// sections and headers, and the templates in them and their parameters to
+// a SQLite database, so that questions like "which pages use {{der}} under
+// an Etymology header" can be answered with SQL. Only built with the
+// `sqlite` feature.
+//
+// The dump parser doesn't read page ids, so they are looked up by title in
+// `page.sql` from the same dump.
+
+use dump_parser::{
+    parse as parse_dump, parse_pages, parse_wiki_text::Positioned,
+    print_parser_warnings, Namespace, NamespaceTable, Node, Page,
+};
+use parse_sql_dump::Page as PageRow;
+use rusqlite::{
+    params, Connection, OptionalExtension, Statement, Transaction,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::Path,
+    time::Instant,
+};
+use template_iter::{parse_wiki_text_ext::Section, TemplateVisitor, Title};
+
+use crate::{
+    args::{DumpOptions, ExportSqlite},
+    error::Result,
+    print_time,
+    template_redirects::for_each_row,
+};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = OFF;
+    PRAGMA synchronous = OFF;
+    CREATE TABLE pages (
+        id INTEGER PRIMARY KEY,
+        namespace INTEGER NOT NULL,
+        title TEXT NOT NULL
+    );
+    CREATE TABLE headers (
+        id INTEGER PRIMARY KEY,
+        text TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE sections (
+        id INTEGER PRIMARY KEY,
+        page_id INTEGER NOT NULL REFERENCES pages (id),
+        parent_id INTEGER REFERENCES sections (id),
+        header_id INTEGER REFERENCES headers (id),
+        level INTEGER NOT NULL,
+        start INTEGER NOT NULL,
+        end INTEGER NOT NULL
+    );
+    CREATE TABLE templates (
+        id INTEGER PRIMARY KEY,
+        page_id INTEGER NOT NULL REFERENCES pages (id),
+        section_id INTEGER NOT NULL REFERENCES sections (id),
+        parent_id INTEGER REFERENCES templates (id),
+        name TEXT NOT NULL,
+        start INTEGER NOT NULL,
+        end INTEGER NOT NULL
+    );
+    CREATE TABLE parameters (
+        template_id INTEGER NOT NULL REFERENCES templates (id),
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (template_id, key)
+    ) WITHOUT ROWID;
+    CREATE TEMP TABLE page_ids (
+        namespace INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        PRIMARY KEY (namespace, title)
+    ) WITHOUT ROWID;
+";
+
+// Created after the rows are inserted, which is faster than updating them
+// with each insertion.
+const INDEXES: &str = "
+    CREATE INDEX pages_title ON pages (title);
+    CREATE INDEX sections_page ON sections (page_id);
+    CREATE INDEX sections_header ON sections (header_id);
+    CREATE INDEX templates_page ON templates (page_id);
+    CREATE INDEX templates_section ON templates (section_id);
+    CREATE INDEX templates_name ON templates (name);
+    CREATE INDEX parameters_key ON parameters (key, value);
+";
+
+// A section, with the index of the section that contains it, if any.
+struct SectionRow {
+    parent: Option<usize>,
+    heading: Option<String>,
+    level: u8,
+    range: Range<usize>,
+}
+
+// A template, with the index of the innermost section that contains it and
+// of the template that it is nested in, if any.
+struct TemplateRow {
+    section: usize,
+    parent: Option<usize>,
+    name: String,
+    range: Range<usize>,
+    parameters: Vec<(String, String)>,
+}
+
+// What is extracted from a page by the threads that parse wikitext, with
+// parents before their children.
+#[derive(Default)]
+struct PageRows {
+    sections: Vec<SectionRow>,
+    templates: Vec<TemplateRow>,
+}
+
+impl PageRows {
+    fn new(
+        page: &Page,
+        nodes: &[Node],
+        namespace_table: &NamespaceTable,
+        filter: Option<&HashSet<String>>,
+    ) -> Self {
+        let mut rows = PageRows::default();
+        rows.add_section(&Section::new(&page.text, nodes), None);
+        // The templates to keep, each with the starts of the templates that
+        // contain it, innermost last.
+        let mut templates = Vec::new();
+        TemplateVisitor::new(&page.text).visit_with_ancestors(
+            nodes,
+            &mut |template, node, ancestors| {
+                let name = Title::new(
+                    &template.name,
+                    Namespace::Template,
+                    namespace_table,
+                )
+                .ok()
+                .filter(|title| {
+                    title.namespace() == Namespace::Template
+                        && match filter {
+                            Some(filter) => filter.contains(title.name()),
+                            None => true,
+                        }
+                })
+                .map(Title::into_name);
+                if let Some(name) = name {
+                    let enclosing: Vec<_> = ancestors
+                        .iter()
+                        .filter(|node| matches!(node, Node::Template { .. }))
+                        .map(|node| node.start())
+                        .collect();
+                    let parameters = template
+                        .parameters
+                        .into_iter()
+                        .map(|(key, value)| {
+                            (key.trim().to_string(), value.to_string())
+                        })
+                        .collect();
+                    templates.push((name, node, enclosing, parameters));
+                }
+            },
+        );
+        // Templates are visited after the templates nested in them.
+        templates.sort_by_key(|(_, node, _, _)| node.start());
+        let indices: HashMap<usize, usize> = templates
+            .iter()
+            .enumerate()
+            .map(|(i, (_, node, _, _))| (node.start(), i))
+            .collect();
+        for (name, node, enclosing, parameters) in templates {
+            let start = node.start();
+            let section = rows
+                .sections
+                .iter()
+                .rposition(|section| section.range.contains(&start))
+                .unwrap_or(0);
+            // Skips enclosing templates that weren't kept, like parser
+            // functions.
+            let parent = enclosing
+                .iter()
+                .rev()
+                .find_map(|start| indices.get(start).copied());
+            rows.templates.push(TemplateRow {
+                section,
+                parent,
+                name,
+                range: start..node.end(),
+                parameters,
+            });
+        }
+        rows
+    }
+
+    // Adds a section and its descendants in the order in which they appear
+    // in the page, so that the last section that contains a position is the
+    // innermost.
+    fn add_section(&mut self, section: &Section, parent: Option<usize>) {
+        let index = self.sections.len();
+        self.sections.push(SectionRow {
+            parent,
+            heading: section.heading.map(String::from),
+            level: section.level,
+            range: section.range.clone(),
+        });
+        for child in &section.children {
+            self.add_section(child, Some(index));
+        }
+    }
+}
+
+// Copies the ids of the pages in `namespaces` from `page.sql` to the
+// `page_ids` table.
+fn load_page_ids(
+    transaction: &Transaction,
+    page_sql: &Path,
+    namespaces: &[Namespace],
+) -> Result<()> {
+    let mut insert = transaction.prepare(
+        "INSERT OR REPLACE INTO page_ids (namespace, title, id)
+            VALUES (?1, ?2, ?3)",
+    )?;
+    let mut result = Ok(());
+    for_each_row(page_sql, |page: PageRow| {
+        if result.is_ok() && namespaces.contains(&Namespace(page.namespace)) {
+            result = insert
+                .execute(params![page.namespace, page.title, page.id])
+                .map(drop);
+        }
+    })?;
+    Ok(result?)
+}
+
+// Prepared statements for inserting rows.
+struct Inserter<'a> {
+    page_ids: Statement<'a>,
+    pages: Statement<'a>,
+    headers: Statement<'a>,
+    sections: Statement<'a>,
+    templates: Statement<'a>,
+    parameters: Statement<'a>,
+    header_ids: HashMap<String, i64>,
+}
+
+impl<'a> Inserter<'a> {
+    fn new(transaction: &'a Transaction) -> rusqlite::Result<Self> {
+        Ok(Self {
+            page_ids: transaction.prepare(
+                "SELECT id FROM page_ids WHERE namespace = ?1 AND title = ?2",
+            )?,
+            pages: transaction.prepare(
+                "INSERT INTO pages (id, namespace, title) VALUES (?1, ?2, ?3)",
+            )?,
+            headers: transaction
+                .prepare("INSERT INTO headers (text) VALUES (?1)")?,
+            sections: transaction.prepare(
+                "INSERT INTO sections
+                    (page_id, parent_id, header_id, level, start, end)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?,
+            templates: transaction.prepare(
+                "INSERT INTO templates
+                    (page_id, section_id, parent_id, name, start, end)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?,
+            // A key given more than once keeps its last value, as in
+            // MediaWiki.
+            parameters: transaction.prepare(
+                "INSERT OR REPLACE INTO parameters (template_id, key, value)
+                    VALUES (?1, ?2, ?3)",
+            )?,
+            header_ids: HashMap::new(),
+        })
+    }
+
+    fn header_id(&mut self, header: String) -> rusqlite::Result<i64> {
+        if let Some(&id) = self.header_ids.get(&header) {
+            return Ok(id);
+        }
+        let id = self.headers.insert(params![header])?;
+        self.header_ids.insert(header, id);
+        Ok(id)
+    }
+
+    // Looks up the id of a page by its title without namespace prefix and
+    // with underscores, as in `page.sql`.
+    fn page_id(
+        &mut self,
+        namespace: Namespace,
+        title: &str,
+    ) -> rusqlite::Result<Option<i64>> {
+        self.page_ids
+            .query_row(params![i32::from(namespace), title], |row| row.get(0))
+            .optional()
+    }
+
+    fn add_page(
+        &mut self,
+        page_id: i64,
+        namespace: Namespace,
+        title: &str,
+        rows: PageRows,
+    ) -> rusqlite::Result<()> {
+        self.pages
+            .execute(params![page_id, i32::from(namespace), title])?;
+        let mut section_ids: Vec<i64> =
+            Vec::with_capacity(rows.sections.len());
+        for section in rows.sections {
+            let header_id =
+                section.heading.map(|h| self.header_id(h)).transpose()?;
+            let id = self.sections.insert(params![
+                page_id,
+                section.parent.map(|i| section_ids[i]),
+                header_id,
+                section.level,
+                section.range.start as i64,
+                section.range.end as i64,
+            ])?;
+            section_ids.push(id);
+        }
+        let mut template_ids: Vec<i64> =
+            Vec::with_capacity(rows.templates.len());
+        for template in rows.templates {
+            let id = self.templates.insert(params![
+                page_id,
+                section_ids[template.section],
+                template.parent.map(|i| template_ids[i]),
+                template.name,
+                template.range.start as i64,
+                template.range.end as i64,
+            ])?;
+            for (key, value) in template.parameters {
+                self.parameters.execute(params![id, key, value])?;
+            }
+            template_ids.push(id);
+        }
+        Ok(())
+    }
+}
+
+/// Writes the pages in the dump to a new SQLite database.
+pub fn export_sqlite(
+    options: ExportSqlite,
+    main_start: Instant,
+    verbose: bool,
+) -> Result<()> {
+    let ExportSqlite {
+        output,
+        templates,
+        page_sql,
+        namespace_table,
+        dump_options:
+            DumpOptions {
+                pages,
+                namespaces,
+                configuration,
+                dump_file,
+                jobs,
+            },
+    } = options;
+    let mut connection = Connection::open(&output)?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    load_page_ids(&transaction, &page_sql, &namespaces)?;
+    let mut inserter = Inserter::new(&transaction)?;
+    let parser = parse_dump(dump_file)
+        .take(pages)
+        .filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+    let start_time = main_start.elapsed();
+    let parse_start = Instant::now();
+    parse_pages(
+        parser,
+        &configuration,
+        jobs,
+        |page, output| {
+            if verbose {
+                print_parser_warnings(page, &output.warnings);
+            }
+            PageRows::new(
+                page,
+                &output.nodes,
+                &namespace_table,
+                templates.as_ref(),
+            )
+        },
+        |page, rows| -> Result<()> {
+            let title =
+                Title::new(&page.title, Namespace::Main, &namespace_table)
+                    .map(Title::into_name);
+            let id = match title {
+                Ok(title) => inserter.page_id(page.namespace, &title)?,
+                Err(_) => None,
+            };
+            match id {
+                Some(id) => {
+                    inserter.add_page(id, page.namespace, &page.title, rows)?
+                }
+                None => eprintln!(
+                    "skipping {}, which is not in {}",
+                    page.title,
+                    page_sql.display()
+                ),
+            }
+            Ok(())
+        },
+    )?;
+    drop(inserter);
+    transaction.execute_batch(INDEXES)?;
+    transaction.commit()?;
+    let parse_time = parse_start.elapsed();
+    eprintln!(
+        "startup took {}, parsing and exporting {}",
+        print_time(&start_time).unwrap(),
+        print_time(&parse_time).unwrap()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        load_page_ids, Inserter, PageRows, SectionRow, TemplateRow, INDEXES,
+        SCHEMA,
+    };
+    use dump_parser::Namespace;
+    use rusqlite::Connection;
+    use std::fs;
+
+    #[test]
+    fn page_ids() {
+        let path = std::env::temp_dir()
+            .join(format!("wiktionary-data-page-{}.sql", std::process::id()));
+        let row = |id, namespace, title| {
+            format!(
+                "({},{},'{}','',0,0,0.5,'20200101000000',NULL,1,1,\
+                'wikitext',NULL)",
+                id, namespace, title
+            )
+        };
+        let sql = format!(
+            "INSERT INTO `page` VALUES {},{},{};\n",
+            row(7, 0, "a_b"),
+            row(8, 10, "l"),
+            row(9, 0, "l"),
+        );
+        fs::write(&path, sql).unwrap();
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        let transaction = connection.transaction().unwrap();
+        let loaded = load_page_ids(&transaction, &path, &[Namespace::Main]);
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        let mut inserter = Inserter::new(&transaction).unwrap();
+        let mut page_id = |namespace, title| {
+            inserter.page_id(namespace, title).unwrap()
+        };
+        assert_eq!(page_id(Namespace::Main, "a_b"), Some(7));
+        assert_eq!(page_id(Namespace::Main, "l"), Some(9));
+        assert_eq!(page_id(Namespace::Template, "l"), None);
+        assert_eq!(page_id(Namespace::Main, "a b"), None);
+    }
+
+    #[test]
+    fn schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        let transaction = connection.transaction().unwrap();
+        let mut inserter = Inserter::new(&transaction).unwrap();
+        let section = |parent, heading: Option<&str>, level| SectionRow {
+            parent,
+            heading: heading.map(String::from),
+            level,
+            range: 0..0,
+        };
+        let template =
+            |section, parent, name: &str, parameters: &[_]| TemplateRow {
+                section,
+                parent,
+                name: name.into(),
+                range: 0..0,
+                parameters: parameters
+                    .iter()
+                    .map(|&(k, v): &(&str, &str)| (k.into(), v.into()))
+                    .collect(),
+            };
+        let rows = PageRows {
+            sections: vec![
+                section(None, None, 0),
+                section(Some(0), Some("English"), 2),
+                section(Some(1), Some("Etymology"), 3),
+                section(Some(1), Some("Noun"), 3),
+            ],
+            templates: vec![
+                template(2, None, "der", &[("1", "en"), ("2", "fro")]),
+                template(2, Some(0), "m", &[("1", "fro"), ("1", "mot")]),
+                template(3, None, "der", &[("1", "en")]),
+            ],
+        };
+        inserter.add_page(5, Namespace::Main, "word", rows).unwrap();
+        drop(inserter);
+        transaction.execute_batch(INDEXES).unwrap();
+        transaction.commit().unwrap();
+
+        let count = |sql: &str| -> i64 {
+            connection.query_row(sql, [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(
+            count(
+                "SELECT count(*) FROM templates
+                    JOIN sections ON sections.id = templates.section_id
+                    JOIN headers ON headers.id = sections.header_id
+                    WHERE templates.name = 'der'
+                        AND headers.text = 'Etymology'"
+            ),
+            1
+        );
+        assert_eq!(count("SELECT count(*) FROM headers"), 3);
+        assert_eq!(
+            count(
+                "SELECT count(*) FROM templates
+                    JOIN pages ON pages.id = templates.page_id
+                    WHERE pages.id = 5 AND pages.title = 'word'"
+            ),
+            3
+        );
+        let value: String = connection
+            .query_row(
+                "SELECT value FROM parameters
+                    JOIN templates ON templates.id = template_id
+                    WHERE name = 'm' AND key = '1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(value, "mot");
+        assert_eq!(
+            count(
+                "SELECT parent.id FROM templates AS child
+                    JOIN templates AS parent ON parent.id = child.parent_id
+                    WHERE child.name = 'm'"
+            ),
+            1
+        );
+    }
+}
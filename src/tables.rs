@@ -0,0 +1,284 @@
+// Parquet tables for the outputs of `dump-parsed-templates`, `all-headers`
+// and `filter-headers`, which DataFrame libraries can load much faster than
+// JSON. Only built with the `parquet` feature.
+
+use arrow::{
+    array::{
+        ArrayRef, MapBuilder, StringBuilder, UInt64Builder, UInt8Builder,
+    },
+    datatypes::{DataType, Field, Fields, Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use filter_headers::HeaderFilterer;
+use header_stats::HeaderStats;
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use std::{io::Write, sync::Arc};
+
+// The number of rows that are buffered before being written.
+const BATCH_SIZE: usize = 8192;
+
+fn writer_properties() -> WriterProperties {
+    WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build()
+}
+
+fn parameters_field() -> Field {
+    let entries = Fields::from(vec![
+        Field::new("keys", DataType::Utf8, false),
+        Field::new("values", DataType::Utf8, false),
+    ]);
+    Field::new(
+        "parameters",
+        DataType::Map(
+            Arc::new(Field::new("entries", DataType::Struct(entries), false)),
+            false,
+        ),
+        false,
+    )
+}
+
+/// A template instance, as a row in a template table.
+#[derive(Debug)]
+pub struct TemplateRow {
+    pub name: String,
+    pub alias: Option<String>,
+    pub parameters: Vec<(String, String)>,
+    pub text: Option<String>,
+}
+
+/// The template instances in a page that go in one table.
+#[derive(Debug)]
+pub struct TemplateRows {
+    pub title: String,
+    pub templates: Vec<TemplateRow>,
+}
+
+/// Writes template instances to a Parquet file with the columns `title`,
+/// `name`, `alias`, `parameters` (a map from key to value) and `text`.
+pub struct TemplateTable<W: Write + Send> {
+    schema: SchemaRef,
+    writer: ArrowWriter<W>,
+    titles: StringBuilder,
+    names: StringBuilder,
+    aliases: StringBuilder,
+    parameters: MapBuilder<StringBuilder, StringBuilder>,
+    texts: StringBuilder,
+    len: usize,
+}
+
+impl<W: Write + Send> TemplateTable<W> {
+    pub fn new(writer: W) -> Result<Self, ParquetError> {
+        let schema = Schema::new(vec![
+            Field::new("title", DataType::Utf8, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("alias", DataType::Utf8, true),
+            parameters_field(),
+            Field::new("text", DataType::Utf8, true),
+        ]);
+        let schema = SchemaRef::new(schema);
+        let writer = ArrowWriter::try_new(
+            writer,
+            schema.clone(),
+            Some(writer_properties()),
+        )?;
+        let parameters =
+            MapBuilder::new(None, StringBuilder::new(), StringBuilder::new())
+                .with_values_field(Field::new(
+                    "values",
+                    DataType::Utf8,
+                    false,
+                ));
+        Ok(Self {
+            schema,
+            writer,
+            titles: StringBuilder::new(),
+            names: StringBuilder::new(),
+            aliases: StringBuilder::new(),
+            parameters,
+            texts: StringBuilder::new(),
+            len: 0,
+        })
+    }
+
+    pub fn push(&mut self, rows: TemplateRows) -> Result<(), ParquetError> {
+        for template in rows.templates {
+            self.titles.append_value(&rows.title);
+            self.names.append_value(template.name);
+            self.aliases.append_option(template.alias);
+            for (key, value) in template.parameters {
+                self.parameters.keys().append_value(key);
+                self.parameters.values().append_value(value);
+            }
+            self.parameters.append(true)?;
+            self.texts.append_option(template.text);
+            self.len += 1;
+        }
+        if self.len >= BATCH_SIZE {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<(), ParquetError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.titles.finish()),
+            Arc::new(self.names.finish()),
+            Arc::new(self.aliases.finish()),
+            Arc::new(self.parameters.finish()),
+            Arc::new(self.texts.finish()),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.len = 0;
+        self.writer.write(&batch)
+    }
+
+    /// Writes the remaining rows and the file footer, without which the
+    /// file can't be read.
+    pub fn close(mut self) -> Result<(), ParquetError> {
+        if self.len > 0 {
+            self.write_batch()?;
+        }
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+// Writes a table with a single record batch.
+fn write_table<W: Write + Send>(
+    writer: W,
+    schema: Schema,
+    columns: Vec<ArrayRef>,
+) -> Result<(), ParquetError> {
+    let schema = SchemaRef::new(schema);
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer =
+        ArrowWriter::try_new(writer, schema, Some(writer_properties()))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Writes the columns `header`, `level` and `count`, with a row for each
+/// level that a header occurs at.
+pub fn write_header_stats<W: Write + Send>(
+    writer: W,
+    stats: &HeaderStats,
+) -> Result<(), ParquetError> {
+    let mut headers = StringBuilder::new();
+    let mut levels = UInt8Builder::new();
+    let mut counts = UInt64Builder::new();
+    for (header, header_counts) in stats.sorted() {
+        for (level, count) in header_counts.iter().filter(|&(_, c)| c > 0) {
+            headers.append_value(header);
+            levels.append_value(level);
+            counts.append_value(count as u64);
+        }
+    }
+    let schema = Schema::new(vec![
+        Field::new("header", DataType::Utf8, false),
+        Field::new("level", DataType::UInt8, false),
+        Field::new("count", DataType::UInt64, false),
+    ]);
+    write_table(
+        writer,
+        schema,
+        vec![
+            Arc::new(headers.finish()),
+            Arc::new(levels.finish()),
+            Arc::new(counts.finish()),
+        ],
+    )
+}
+
+/// Writes the columns `header` and `title`, with a row for each page that
+/// an unlisted header occurs in.
+pub fn write_filtered_headers<W: Write + Send>(
+    writer: W,
+    filterer: &HeaderFilterer,
+) -> Result<(), ParquetError> {
+    let mut headers = StringBuilder::new();
+    let mut titles = StringBuilder::new();
+    for (header, header_titles) in filterer.sorted() {
+        for title in header_titles {
+            headers.append_value(header);
+            titles.append_value(title);
+        }
+    }
+    let schema = Schema::new(vec![
+        Field::new("header", DataType::Utf8, false),
+        Field::new("title", DataType::Utf8, false),
+    ]);
+    write_table(
+        writer,
+        schema,
+        vec![Arc::new(headers.finish()), Arc::new(titles.finish())],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TemplateRow, TemplateRows, TemplateTable};
+    use arrow::array::{Array, MapArray, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn template_table() {
+        let mut file = Vec::new();
+        let mut table = TemplateTable::new(&mut file).unwrap();
+        table
+            .push(TemplateRows {
+                title: "word".into(),
+                templates: vec![
+                    TemplateRow {
+                        name: "l".into(),
+                        alias: None,
+                        parameters: vec![
+                            ("1".into(), "en".into()),
+                            ("2".into(), "word".into()),
+                        ],
+                        text: Some("{{l|en|word}}".into()),
+                    },
+                    TemplateRow {
+                        name: "m".into(),
+                        alias: Some("mention".into()),
+                        parameters: Vec::new(),
+                        text: None,
+                    },
+                ],
+            })
+            .unwrap();
+        table.close().unwrap();
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(file))
+                .unwrap()
+                .build()
+                .unwrap();
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        let column = |name| batch.column_by_name(name).unwrap();
+        let strings = |name| {
+            let column = column(name);
+            let strings =
+                column.as_any().downcast_ref::<StringArray>().unwrap();
+            strings
+                .iter()
+                .map(|s| s.map(String::from))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strings("title"), vec![Some("word".into()); 2]);
+        assert_eq!(strings("name"), vec![Some("l".into()), Some("m".into())]);
+        assert_eq!(strings("alias"), vec![None, Some("mention".into())]);
+        assert_eq!(strings("text"), vec![Some("{{l|en|word}}".into()), None]);
+        let parameters = column("parameters");
+        let parameters =
+            parameters.as_any().downcast_ref::<MapArray>().unwrap();
+        assert_eq!(parameters.value_length(0), 2);
+        assert_eq!(parameters.value_length(1), 0);
+    }
+}
@@ -0,0 +1,218 @@
+// Indexes of the CBOR files written by `dump-parsed-templates`, which are
+// written alongside them and map each page title to the offset of its record,
+// so that the templates in a page can be found without reading the whole file.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+
+/// A record in a template dump: the templates in one page.
+#[derive(Debug, Deserialize)]
+pub struct TemplatesInPage {
+    pub title: String,
+    pub templates: Vec<DumpedTemplate>,
+}
+
+/// A template instance in a template dump. The fields that depend on the
+/// options of `dump-parsed-templates`, such as `text`, `alias`,
+/// `overridden` and the context fields, are kept as they are in `other`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DumpedTemplate {
+    pub name: String,
+    pub parameters: BTreeMap<String, ParameterValue>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// The value of a parameter in a template dump: its wikitext, or an array
+/// of nodes if the dump was made with `--parse-values`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParameterValue {
+    Wikitext(String),
+    Parsed(Value),
+}
+
+impl ParameterValue {
+    /// The wikitext, or the parsed value as JSON, as in Parquet dumps.
+    pub fn as_text(&self) -> Cow<'_, str> {
+        match self {
+            ParameterValue::Wikitext(text) => Cow::Borrowed(text),
+            ParameterValue::Parsed(value) => Cow::Owned(value.to_string()),
+        }
+    }
+}
+
+/// Returns the path of the index of the template dump at `path`, which is
+/// the path with `.index` appended.
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut index_path = OsString::from(path);
+    index_path.push(".index");
+    index_path.into()
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateIndex {
+    /// The length of the dump in bytes, which shows whether the index is
+    /// out of date.
+    pub length: u64,
+    /// The number of records in the dump.
+    pub records: u64,
+    /// Page titles mapped to the offsets of their records.
+    pub offsets: BTreeMap<String, u64>,
+}
+
+impl TemplateIndex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a record of `length` bytes at the end of the dump.
+    pub fn add(&mut self, title: &str, length: usize) {
+        self.offsets.insert(title.to_string(), self.length);
+        self.length += length as u64;
+        self.records += 1;
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|e| Error::IoError {
+            action: "open",
+            path: path.into(),
+            cause: e,
+        })?;
+        Ok(serde_cbor::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).map_err(|e| Error::IoError {
+            action: "create",
+            path: path.into(),
+            cause: e,
+        })?;
+        let mut writer = BufWriter::new(file);
+        serde_cbor::to_writer(&mut writer, self)?;
+        writer.flush().map_err(|e| Error::IoError {
+            action: "write",
+            path: path.into(),
+            cause: e,
+        })
+    }
+}
+
+/// A template dump that can be read a page at a time through its index.
+pub struct IndexedTemplateDump<R: Read + Seek> {
+    reader: R,
+    index: TemplateIndex,
+}
+
+impl IndexedTemplateDump<BufReader<File>> {
+    /// Opens the CBOR template dump at `path` and reads its index, which
+    /// must have been written with the current version of the dump.
+    pub fn open(path: &Path) -> Result<Self> {
+        let index = TemplateIndex::read(&index_path(path))?;
+        let file = File::open(path).map_err(|e| Error::IoError {
+            action: "open",
+            path: path.into(),
+            cause: e,
+        })?;
+        let length = file
+            .metadata()
+            .map_err(|e| Error::IoError {
+                action: "read metadata of",
+                path: path.into(),
+                cause: e,
+            })?
+            .len();
+        if length != index.length {
+            return Err(Error::StaleTemplateIndex(index_path(path)));
+        }
+        Ok(Self::new(BufReader::new(file), index))
+    }
+}
+
+impl<R: Read + Seek> IndexedTemplateDump<R> {
+    pub fn new(reader: R, index: TemplateIndex) -> Self {
+        Self { reader, index }
+    }
+
+    /// Reads the templates in the page titled `title`, if there are any.
+    pub fn get(&mut self, title: &str) -> Result<Option<TemplatesInPage>> {
+        let offset = match self.index.offsets.get(title) {
+            Some(&offset) => offset,
+            None => return Ok(None),
+        };
+        self.reader.seek(SeekFrom::Start(offset)).map_err(|e| {
+            Error::IoError {
+                action: "seek in",
+                path: "template dump".into(),
+                cause: e,
+            }
+        })?;
+        let mut deserializer =
+            serde_cbor::Deserializer::from_reader(&mut self.reader);
+        Ok(Some(TemplatesInPage::deserialize(&mut deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_path, IndexedTemplateDump, TemplateIndex};
+    use serde::Serialize;
+    use std::{collections::BTreeMap, io::Cursor, path::Path};
+
+    #[derive(Serialize)]
+    struct Template<'a> {
+        name: &'a str,
+        parameters: BTreeMap<&'a str, &'a str>,
+    }
+
+    #[derive(Serialize)]
+    struct Page<'a> {
+        title: &'a str,
+        templates: Vec<Template<'a>>,
+    }
+
+    #[test]
+    fn read_through_index() {
+        let mut dump = Vec::new();
+        let mut index = TemplateIndex::new();
+        for (title, name) in &[("a", "l"), ("b", "m"), ("c", "l")] {
+            let page = Page {
+                title,
+                templates: vec![Template {
+                    name,
+                    parameters: vec![("1", "en"), ("2", *title)]
+                        .into_iter()
+                        .collect(),
+                }],
+            };
+            let record = serde_cbor::to_vec(&page).unwrap();
+            index.add(title, record.len());
+            dump.extend(record);
+        }
+        assert_eq!(index.length, dump.len() as u64);
+        assert_eq!(index.records, 3);
+
+        let mut dump = IndexedTemplateDump::new(Cursor::new(dump), index);
+        for title in &["c", "a", "b"] {
+            let page = dump.get(title).unwrap().unwrap();
+            assert_eq!(&page.title, title);
+            assert_eq!(page.templates[0].parameters["2"].as_text(), *title);
+        }
+        assert_eq!(dump.get("b").unwrap().unwrap().templates[0].name, "m");
+        assert!(dump.get("d").unwrap().is_none());
+        assert_eq!(
+            index_path(Path::new("dir/l.cbor")),
+            Path::new("dir/l.cbor.index")
+        );
+    }
+}
@@ -0,0 +1,361 @@
+// Redirects between templates, read from the `page` and `redirect` tables of
+// the SQL dump, from a JSON object mapping each template to an array of its
+// redirects, or from the redirect pages in the XML dump, and their addition
+// to a list of templates to be dumped.
+
+use dump_parser::{
+    input::decompress, open_dump, parse as parse_dump, Configuration,
+    Namespace, NamespaceTable, Node,
+};
+use parse_sql_dump::{iter_rows, Page, Redirect, Row};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use template_iter::Title;
+
+use crate::error::{Error, Result};
+
+/// Template names, without namespace prefix and with underscores, mapped to
+/// the names of the templates that redirect to them, in alphabetical order.
+pub type RedirectsByTarget = BTreeMap<String, Vec<String>>;
+
+pub enum RedirectSource {
+    Sql { page: PathBuf, redirect: PathBuf },
+    Json(PathBuf),
+}
+
+impl RedirectSource {
+    pub fn load(&self) -> Result<RedirectsByTarget> {
+        let mut redirects_by_target = match self {
+            RedirectSource::Sql { page, redirect } => {
+                redirects_from_sql(page, redirect)?
+            }
+            RedirectSource::Json(path) => {
+                let file = File::open(path).map_err(|e| Error::IoError {
+                    action: "open",
+                    path: path.clone(),
+                    cause: e,
+                })?;
+                serde_json::from_reader(BufReader::new(file))?
+            }
+        };
+        for redirects in redirects_by_target.values_mut() {
+            redirects.sort();
+        }
+        Ok(redirects_by_target)
+    }
+}
+
+// Visits the rows of a SQL dump, which may be compressed.
+pub(crate) fn for_each_row<T: Row>(
+    path: &Path,
+    mut f: impl FnMut(T),
+) -> Result<()> {
+    let file = File::open(path).map_err(|e| Error::IoError {
+        action: "open",
+        path: path.into(),
+        cause: e,
+    })?;
+    let reader = decompress(file, 1).map_err(|e| Error::IoError {
+        action: "read",
+        path: path.into(),
+        cause: e,
+    })?;
+    for row in iter_rows(BufReader::new(reader)) {
+        f(row.map_err(|cause| Error::ParseSqlDump {
+            path: path.into(),
+            cause,
+        })?);
+    }
+    Ok(())
+}
+
+fn redirects_from_sql(
+    page_sql: &Path,
+    redirect_sql: &Path,
+) -> Result<RedirectsByTarget> {
+    let template_namespace = i32::from(Namespace::Template);
+    let mut id_to_template = HashMap::new();
+    for_each_row(page_sql, |page: Page| {
+        if page.namespace == template_namespace {
+            id_to_template.insert(page.id, page.title);
+        }
+    })?;
+    let mut redirects_by_target = RedirectsByTarget::new();
+    for_each_row(redirect_sql, |redirect: Redirect| {
+        if redirect.namespace == template_namespace
+            && redirect.interwiki.as_deref().unwrap_or("").is_empty()
+        {
+            if let Some(template) = id_to_template.remove(&redirect.from) {
+                redirects_by_target
+                    .entry(redirect.title)
+                    .or_default()
+                    .push(template);
+            }
+        }
+    })?;
+    Ok(redirects_by_target)
+}
+
+/// Collects the redirects in the Template namespace by reading the dump at
+/// `path` (or the default dump) again, parsing only pages that start with
+/// `#`. This is done before the templates are dumped rather than while they
+/// are, because a redirect can come after the pages that use it, and the
+/// records of those pages would have to be held back until the end of the
+/// dump to keep the templates in each page in one record. A dump read from
+/// standard input can't be read again.
+pub fn redirects_from_dump(
+    path: Option<&Path>,
+    jobs: usize,
+    configuration: &Configuration,
+    namespace_table: &NamespaceTable,
+) -> Result<RedirectsByTarget> {
+    if path == Some(Path::new("-")) {
+        return Err(Error::RedirectsFromStandardInput);
+    }
+    let mut redirects_by_target = RedirectsByTarget::new();
+    for page in parse_dump(open_dump(path, jobs)?) {
+        let page = page?;
+        if page.namespace != Namespace::Template
+            || !page.text.trim_start().starts_with('#')
+        {
+            continue;
+        }
+        if let Some(Node::Redirect { target, .. }) =
+            configuration.parse(&page.text).nodes.first()
+        {
+            let title = |text| {
+                Title::new(text, Namespace::Main, namespace_table)
+                    .ok()
+                    .filter(|title| title.namespace() == Namespace::Template)
+            };
+            if let (Some(target), Some(redirect)) =
+                (title(target), title(&page.title))
+            {
+                redirects_by_target
+                    .entry(target.into_name())
+                    .or_default()
+                    .push(redirect.into_name());
+            }
+        }
+    }
+    for redirects in redirects_by_target.values_mut() {
+        redirects.sort();
+    }
+    Ok(redirects_by_target)
+}
+
+/// Maps the name of each redirect to the template that it redirects to.
+pub fn redirect_to_target(
+    redirects_by_target: RedirectsByTarget,
+) -> HashMap<String, String> {
+    redirects_by_target
+        .into_iter()
+        .flat_map(|(target, redirects)| {
+            redirects
+                .into_iter()
+                .map(move |redirect| (redirect, target.clone()))
+        })
+        .collect()
+}
+
+/// Adds the redirects to the templates in `templates`, a list of template
+/// names and optional output paths such as
+/// `collect_template_names_and_files` returns. The names are resolved as in
+/// `{{}}` with `namespace_table`, so `Template:en-noun` is `en-noun`.
+/// Templates without a path get `path_format` with `%s` replaced by the
+/// template name. If a redirect target has a path, all its redirects use it.
+/// Otherwise the target and its redirects use the path of the first redirect
+/// alphabetically that has one, unless they have their own. The result is
+/// sorted by template name, ignoring case.
+pub fn add_redirects(
+    templates: Vec<(String, Option<String>)>,
+    redirects_by_target: &RedirectsByTarget,
+    path_format: &str,
+    namespace_table: &NamespaceTable,
+) -> Result<Vec<(String, String)>> {
+    let mut template_to_path = HashMap::new();
+    for (template, path) in templates {
+        let template =
+            Title::new(&template, Namespace::Template, namespace_table)
+                .map(Title::into_name)
+                .map_err(|e| Error::TemplateNameNormalization {
+                    title: template,
+                    cause: e,
+                })?;
+        let path = path.unwrap_or_else(|| {
+            path_format.replace("%s", &template.replace('/', "_"))
+        });
+        template_to_path.insert(template, path);
+    }
+
+    let mut with_redirects = HashMap::new();
+    for (target, redirects) in redirects_by_target {
+        let all = std::iter::once(target).chain(redirects);
+        if let Some(path) = template_to_path.get(target) {
+            for template in all {
+                with_redirects.insert(template.clone(), path.clone());
+            }
+        } else if let Some(default) =
+            redirects.iter().find_map(|r| template_to_path.get(r))
+        {
+            for template in all {
+                let path = template_to_path.get(template).unwrap_or(default);
+                with_redirects.insert(template.clone(), path.clone());
+            }
+        }
+    }
+    for (template, path) in template_to_path {
+        with_redirects.entry(template).or_insert(path);
+    }
+
+    let mut with_redirects: Vec<_> = with_redirects.into_iter().collect();
+    with_redirects.sort_by(|(a, _), (b, _)| {
+        a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b))
+    });
+    Ok(with_redirects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_redirects, redirect_to_target, redirects_from_dump,
+        RedirectsByTarget,
+    };
+    use crate::error::Error;
+    use dump_parser::{Configuration, NamespaceTable};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn find_redirects_in_dump() {
+        let pages = [
+            ("Template:en-n", 10, "#REDIRECT [[Template:en-noun]]"),
+            ("Template:en noun", 10, "#redirect [[Template:en-noun]]"),
+            ("Template:l-self", 10, "#REDIRECT [[template:l]]"),
+            ("Template:word", 10, "#REDIRECT [[word]]"),
+            ("Template:m", 10, "{{mention}}"),
+            ("noun", 0, "#REDIRECT [[Template:en-noun]]"),
+        ];
+        let dump = pages
+            .iter()
+            .map(|(title, namespace, text)| {
+                format!(
+                    "<page><title>{}</title><ns>{}</ns><revision>\
+                    <text>{}</text></revision></page>",
+                    title, namespace, text
+                )
+            })
+            .collect::<String>();
+        let path = std::env::temp_dir().join(format!(
+            "wiktionary-data-redirects-{}.xml",
+            std::process::id()
+        ));
+        fs::write(&path, format!("<mediawiki>{}</mediawiki>", dump)).unwrap();
+        let configuration = Configuration::default();
+        let namespace_table = NamespaceTable::english_wiktionary();
+        let redirects = redirects_from_dump(
+            Some(&path),
+            1,
+            &configuration,
+            &namespace_table,
+        );
+        fs::remove_file(&path).unwrap();
+        let redirects = redirects.unwrap();
+        assert_eq!(
+            redirects.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "en-noun".to_string(),
+                    vec!["en-n".to_string(), "en_noun".to_string()]
+                ),
+                ("l".to_string(), vec!["l-self".to_string()]),
+            ]
+        );
+        assert!(matches!(
+            redirects_from_dump(
+                Some(Path::new("-")),
+                1,
+                &configuration,
+                &namespace_table,
+            ),
+            Err(Error::RedirectsFromStandardInput)
+        ));
+    }
+
+    #[test]
+    fn map_redirects_to_targets() {
+        let redirects: RedirectsByTarget = vec![
+            ("en-noun".to_string(), vec!["en-n".to_string()]),
+            (
+                "l".to_string(),
+                vec!["l-self".to_string(), "ll".to_string()],
+            ),
+            ("m".to_string(), Vec::new()),
+        ]
+        .into_iter()
+        .collect();
+        let mut pairs: Vec<_> =
+            redirect_to_target(redirects).into_iter().collect();
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            vec![
+                ("en-n".to_string(), "en-noun".to_string()),
+                ("l-self".to_string(), "l".to_string()),
+                ("ll".to_string(), "l".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn add_template_redirects() {
+        let redirects: RedirectsByTarget = vec![
+            ("en-noun", vec!["en-n", "en_noun"]),
+            ("l", vec!["l-self", "link"]),
+            ("m", vec!["mention"]),
+        ]
+        .into_iter()
+        .map(|(target, redirects)| {
+            let redirects = redirects.into_iter().map(String::from);
+            (target.to_string(), redirects.collect())
+        })
+        .collect();
+        let templates = vec![
+            ("en-noun".to_string(), None),
+            ("en-n".to_string(), Some("other.cbor".to_string())),
+            ("link".to_string(), Some("link.cbor".to_string())),
+            ("l-self".to_string(), None),
+            ("de/noun".to_string(), None),
+            ("Template:m".to_string(), None),
+        ];
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            add_redirects(
+                templates,
+                &redirects,
+                "%s.cbor",
+                &NamespaceTable::english_wiktionary(),
+            )
+            .unwrap(),
+            pairs(&[
+                ("de/noun", "de_noun.cbor"),
+                ("en-n", "en-noun.cbor"),
+                ("en-noun", "en-noun.cbor"),
+                ("en_noun", "en-noun.cbor"),
+                ("l", "l-self.cbor"),
+                ("l-self", "l-self.cbor"),
+                ("link", "link.cbor"),
+                ("m", "m.cbor"),
+                ("mention", "m.cbor"),
+            ])
+        );
+    }
+}
@@ -0,0 +1,441 @@
+// Checks template instances against the TemplateData of their templates,
+// the JSON in `<templatedata>` tags on template pages or their
+// documentation subpages, which lists the parameters that a template
+// accepts and which of them are required.
+
+use dump_parser::{
+    parse_pages, print_parser_warnings, Configuration, DumpParser, Error,
+    Namespace, NamespaceTable, Node, Page, Positioned,
+};
+use parse_wiki_text_ext::{
+    template_parameters::{self, ParameterKey},
+    NodesExt,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, Read, Write},
+};
+use template_iter::{TemplateVisitor, Title};
+
+/// The parts of the TemplateData of a template that instances are checked
+/// against.
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateData {
+    #[serde(default)]
+    pub params: BTreeMap<String, ParamData>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ParamData {
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl TemplateData {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Whether `key` is the name or an alias of a parameter.
+    pub fn is_known(&self, key: &str) -> bool {
+        self.params.contains_key(key)
+            || self
+                .params
+                .values()
+                .any(|param| param.aliases.iter().any(|alias| alias == key))
+    }
+
+    /// Checks the parameter names of an instance, in the order in which
+    /// they appear, with the positional parameters numbered.
+    pub fn check(&self, keys: &[String]) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let mut seen = HashSet::new();
+        for key in keys {
+            if !seen.insert(key) {
+                if !violations.iter().any(
+                    |v| matches!(v, Violation::DuplicateKey(k) if k == key),
+                ) {
+                    violations.push(Violation::DuplicateKey(key.clone()));
+                }
+            } else if !self.is_known(key) {
+                violations.push(Violation::UnknownParameter(key.clone()));
+            }
+        }
+        for (name, param) in &self.params {
+            if param.required
+                && !seen.contains(name)
+                && !param.aliases.iter().any(|alias| seen.contains(alias))
+            {
+                violations.push(Violation::MissingRequired(name.clone()));
+            }
+        }
+        violations
+    }
+}
+
+/// The TemplateData of templates, by template name.
+#[derive(Debug, Default)]
+pub struct TemplateDataMap(HashMap<String, TemplateData>);
+
+impl TemplateDataMap {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Collects the TemplateData in the Template namespace of a dump. Pages
+    /// with invalid TemplateData are skipped, and reported if `verbose` is
+    /// true.
+    pub fn from_dump<R: Read>(
+        parser: DumpParser<R>,
+        configuration: &Configuration,
+        namespace_table: &NamespaceTable,
+        verbose: bool,
+    ) -> Result<Self, Error> {
+        let mut map = Self::new();
+        for page in parser {
+            let page = page?;
+            if page.namespace != Namespace::Template
+                || !page.text.contains("<templatedata")
+            {
+                continue;
+            }
+            let output = configuration.parse(&page.text);
+            if let Err(e) = map.add_page(&page, &output.nodes, namespace_table)
+            {
+                if verbose {
+                    eprintln!("invalid TemplateData in {}: {}", page.title, e);
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Adds the TemplateData in a template page or its documentation
+    /// subpage. TemplateData on the template page takes precedence.
+    pub fn add_page(
+        &mut self,
+        page: &Page,
+        nodes: &[Node],
+        namespace_table: &NamespaceTable,
+    ) -> serde_json::Result<()> {
+        let title =
+            match Title::new(&page.title, Namespace::Main, namespace_table) {
+                Ok(title) if title.namespace() == Namespace::Template => title,
+                _ => return Ok(()),
+            };
+        let tag = nodes.descendants().find(|(node, _)| match node {
+            Node::Tag { name, .. } => {
+                name.eq_ignore_ascii_case("templatedata")
+            }
+            _ => false,
+        });
+        if let Some((tag, _)) = tag {
+            let data = TemplateData::from_json(tag_content(&page.text, tag))?;
+            match title.name().strip_suffix("/documentation") {
+                Some(template) => {
+                    self.0.entry(template.to_string()).or_insert(data);
+                }
+                None => {
+                    self.0.insert(title.into_name(), data);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, template: &str) -> Option<&TemplateData> {
+        self.0.get(template)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// The text between the start and end tags of an extension tag.
+fn tag_content<'a>(wikitext: &'a str, tag: &Node) -> &'a str {
+    let text = tag.get_text_from(wikitext);
+    let start = text.find('>').map_or(text.len(), |i| i + 1);
+    let end = text
+        .rfind("</")
+        .filter(|&i| i >= start)
+        .unwrap_or(text.len());
+    &text[start..end]
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "parameter", rename_all = "kebab-case")]
+pub enum Violation {
+    UnknownParameter(String),
+    MissingRequired(String),
+    DuplicateKey(String),
+}
+
+impl Violation {
+    fn description(&self) -> (&'static str, &str) {
+        match self {
+            Violation::UnknownParameter(key) => ("unknown parameter", key),
+            Violation::MissingRequired(key) => {
+                ("missing required parameter", key)
+            }
+            Violation::DuplicateKey(key) => ("duplicate parameter", key),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageViolation {
+    pub title: String,
+    #[serde(flatten)]
+    pub violation: Violation,
+}
+
+/// The instances of a template that has TemplateData and the problems found
+/// in them.
+#[derive(Debug, Default, Serialize)]
+pub struct TemplateReport {
+    pub instances: usize,
+    /// The number of instances with at least one problem.
+    pub problem_instances: usize,
+    pub violations: Vec<PageViolation>,
+}
+
+/// The normalized name of a template and the problems in one instance of it.
+pub type InstanceViolations = (String, Vec<Violation>);
+
+/// Reports by template name.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct ValidationReport(pub BTreeMap<String, TemplateReport>);
+
+impl ValidationReport {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Checks the instances of the templates in `template_data` in the
+    /// pages in `namespaces`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn parse<R: Read + Send>(
+        &mut self,
+        parser: DumpParser<R>,
+        configuration: &Configuration,
+        template_data: &TemplateDataMap,
+        namespace_table: &NamespaceTable,
+        page_limit: usize,
+        namespaces: Vec<Namespace>,
+        jobs: usize,
+        verbose: bool,
+    ) -> Result<(), Error> {
+        let namespaces: HashSet<Namespace> = namespaces.into_iter().collect();
+        let parser = parser.take(page_limit).filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+        parse_pages(
+            parser,
+            configuration,
+            jobs,
+            |page, parser_output| {
+                if verbose {
+                    print_parser_warnings(page, &parser_output.warnings);
+                }
+                Self::check_page(
+                    page,
+                    &parser_output.nodes,
+                    template_data,
+                    namespace_table,
+                )
+            },
+            |page, instances| {
+                self.add_page(&page.title, instances);
+                Ok(())
+            },
+        )
+    }
+
+    /// Checks each instance of a template with TemplateData in `nodes`.
+    pub fn check_page(
+        page: &Page,
+        nodes: &[Node],
+        template_data: &TemplateDataMap,
+        namespace_table: &NamespaceTable,
+    ) -> Vec<InstanceViolations> {
+        let wikitext = &page.text;
+        let mut instances = Vec::new();
+        TemplateVisitor::new(wikitext).visit(nodes, &mut |template, node| {
+            let name = Title::new(
+                &template.name,
+                Namespace::Template,
+                namespace_table,
+            )
+            .ok()
+            .filter(|title| title.namespace() == Namespace::Template)
+            .map(Title::into_name);
+            let data = name.and_then(|name| {
+                template_data.get(&name).map(|data| (name, data))
+            });
+            if let (Some((name, data)), Node::Template { parameters, .. }) =
+                (data, node)
+            {
+                let keys: Vec<_> = template_parameters::enumerate(parameters)
+                    .map(|(key, _)| match key {
+                        ParameterKey::NodeList(nodes) => {
+                            nodes.get_text_from(wikitext).trim().to_string()
+                        }
+                        ParameterKey::Number(number) => number.to_string(),
+                    })
+                    .collect();
+                instances.push((name, data.check(&keys)));
+            }
+        });
+        instances
+    }
+
+    pub fn add_page(
+        &mut self,
+        title: &str,
+        instances: Vec<InstanceViolations>,
+    ) {
+        for (name, violations) in instances {
+            let report = self.0.entry(name).or_default();
+            report.instances += 1;
+            if !violations.is_empty() {
+                report.problem_instances += 1;
+            }
+            report
+                .violations
+                .extend(violations.into_iter().map(|violation| {
+                    PageViolation {
+                        title: title.to_string(),
+                        violation,
+                    }
+                }));
+        }
+    }
+
+    /// Writes a section for each template with problems, listing them by
+    /// page, for publishing on a maintenance page. `template_prefix` is the
+    /// local name of the Template namespace.
+    pub fn write_wikitext<W: Write>(
+        &self,
+        mut writer: W,
+        template_prefix: &str,
+    ) -> io::Result<()> {
+        for (template, report) in &self.0 {
+            if report.violations.is_empty() {
+                continue;
+            }
+            writeln!(
+                writer,
+                "== [[{}:{}]] ==\n{} of {} instances have problems.\n",
+                template_prefix,
+                template,
+                report.problem_instances,
+                report.instances,
+            )?;
+            for PageViolation { title, violation } in &report.violations {
+                let (description, key) = violation.description();
+                writeln!(
+                    writer,
+                    "* [[:{}]]: {} <code><nowiki>{}</nowiki></code>",
+                    title, description, key
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TemplateData, ValidationReport, Violation};
+
+    #[test]
+    fn check() {
+        let data = TemplateData::from_json(
+            r#"{
+                "description": "A link.",
+                "params": {
+                    "1": { "required": true },
+                    "2": { "label": "term" },
+                    "lang": { "aliases": ["lng"], "required": true }
+                },
+                "format": "inline"
+            }"#,
+        )
+        .unwrap();
+        let check = |keys: &[&str]| {
+            let keys: Vec<_> = keys.iter().map(|k| k.to_string()).collect();
+            data.check(&keys)
+                .into_iter()
+                .map(|violation| match violation {
+                    Violation::UnknownParameter(key) => ("unknown", key),
+                    Violation::MissingRequired(key) => ("missing", key),
+                    Violation::DuplicateKey(key) => ("duplicate", key),
+                })
+                .collect::<Vec<_>>()
+        };
+        let pairs = |pairs: &[(&'static str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(kind, key)| (kind, key.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(check(&["1", "2", "lang"]), pairs(&[]));
+        assert_eq!(check(&["1", "lng"]), pairs(&[]));
+        assert_eq!(
+            check(&["2", "3", "lnag"]),
+            pairs(&[
+                ("unknown", "3"),
+                ("unknown", "lnag"),
+                ("missing", "1"),
+                ("missing", "lang"),
+            ])
+        );
+        assert_eq!(
+            check(&["1", "lang", "1", "1"]),
+            pairs(&[("duplicate", "1")])
+        );
+    }
+
+    #[test]
+    fn count_problem_instances() {
+        let mut report = ValidationReport::new();
+        let problems = || {
+            vec![
+                Violation::UnknownParameter("3".into()),
+                Violation::MissingRequired("1".into()),
+            ]
+        };
+        report.add_page("a", vec![("l".into(), problems())]);
+        report.add_page(
+            "b",
+            vec![("l".into(), Vec::new()), ("l".into(), problems())],
+        );
+        report.add_page("c", vec![("m".into(), Vec::new())]);
+        let mut wikitext = Vec::new();
+        report.write_wikitext(&mut wikitext, "Template").unwrap();
+        let wikitext = String::from_utf8(wikitext).unwrap();
+        assert_eq!(
+            wikitext,
+            "== [[Template:l]] ==\n2 of 3 instances have problems.\n\n\
+            * [[:a]]: unknown parameter <code><nowiki>3</nowiki></code>\n\
+            * [[:a]]: missing required parameter \
+            <code><nowiki>1</nowiki></code>\n\
+            * [[:b]]: unknown parameter <code><nowiki>3</nowiki></code>\n\
+            * [[:b]]: missing required parameter \
+            <code><nowiki>1</nowiki></code>\n\n"
+        );
+    }
+}
@@ -4,15 +4,32 @@ use dump_parser::{
     Positioned,
 };
 pub use parse_wiki_text_ext;
-use parse_wiki_text_ext::template_parameters::{self, ParameterKey};
+use parse_wiki_text_ext::{
+    template_parameters::{self, ParameterKey},
+    walk, Visitor,
+};
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, collections::BTreeMap, error::Error, fmt::Display};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+};
+
+mod title;
+pub use title::{normalize_title, Title, TitleNormalizationError, TITLE_MAX};
+
+mod value;
+pub use value::{parse_parameters, parse_value, ValueNode};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TemplateBorrowed<'a> {
     #[serde(borrow)]
     pub name: Cow<'a, str>,
     pub parameters: BTreeMap<Cow<'a, str>, &'a str>,
+    /// The keys that were given more than once, each with a value that was
+    /// overridden by a later one, in the order in which they appear.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overridden: Vec<(Cow<'a, str>, &'a str)>,
 }
 
 // Avoids memory allocations for most numbered parameters.
@@ -38,32 +55,70 @@ static NUMBERS: &[&str] = &[
     "199", "200",
 ];
 
+// The key of a parameter as it appears in the parameters of a template.
+fn parameter_key<'a>(
+    wikitext: &'a str,
+    key: ParameterKey<'a>,
+) -> Cow<'a, str> {
+    use Cow::*;
+    match key {
+        ParameterKey::NodeList(nodes) => {
+            Borrowed(nodes.get_text_from(wikitext))
+        }
+        ParameterKey::Number(num) => {
+            if let Some(s) = NUMBERS.get(num as usize) {
+                Borrowed(*s)
+            } else {
+                Owned(num.to_string())
+            }
+        }
+    }
+}
+
+// Trims whitespace as PHP's `trim` does.
+fn trim_key<'a>(key: &Cow<'a, str>) -> Cow<'a, str> {
+    let is_whitespace =
+        |c| matches!(c, ' ' | '\t' | '\n' | '\r' | '\0' | '\x0B');
+    match key {
+        Cow::Borrowed(key) => Cow::Borrowed(key.trim_matches(is_whitespace)),
+        Cow::Owned(key) => Cow::Owned(key.trim_matches(is_whitespace).into()),
+    }
+}
+
 impl<'a> TemplateBorrowed<'a> {
     pub fn new(
         wikitext: &'a str,
         name: &'a [Node<'a>],
         parameters: &'a [dump_parser::Parameter<'a>],
     ) -> Self {
-        use Cow::*;
-        let name = Borrowed(name.get_text_from(wikitext));
-        let parameters = template_parameters::enumerate(parameters)
-            .map(|(key, value)| {
-                let key = match key {
-                    ParameterKey::NodeList(nodes) => {
-                        Borrowed(nodes.get_text_from(wikitext))
-                    }
-                    ParameterKey::Number(num) => {
-                        if let Some(s) = NUMBERS.get(num as usize) {
-                            Borrowed(*s)
-                        } else {
-                            Owned(num.to_string())
-                        }
-                    }
-                };
-                (key, value.get_text_from(wikitext))
-            })
-            .collect();
-        Self { name, parameters }
+        let name = Cow::Borrowed(name.get_text_from(wikitext));
+        let mut map = BTreeMap::new();
+        let mut overridden = Vec::new();
+        // MediaWiki trims the keys of named parameters, so `lang` and
+        // ` lang ` are the same key. The raw keys are kept in `map`.
+        let mut raw_keys = HashMap::new();
+        for (key, value) in template_parameters::enumerate(parameters) {
+            let key = parameter_key(wikitext, key);
+            let value = value.get_text_from(wikitext);
+            if let Some(old_key) = raw_keys.insert(trim_key(&key), key.clone())
+            {
+                if let Some(old) = map.remove(&old_key) {
+                    overridden.push((old_key, old));
+                }
+            }
+            map.insert(key, value);
+        }
+        Self {
+            name,
+            parameters: map,
+            overridden,
+        }
+    }
+
+    /// Whether a key was given more than once, as in `{{t|1=a|b}}`, which
+    /// puts the page in MediaWiki's duplicate arguments tracking category.
+    pub fn has_duplicates(&self) -> bool {
+        !self.overridden.is_empty()
     }
 
     #[allow(dead_code)]
@@ -86,6 +141,8 @@ impl<'a> TemplateBorrowed<'a> {
 pub struct TemplateOwned {
     pub name: String,
     pub parameters: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overridden: Vec<(String, String)>,
 }
 
 impl<'a> From<TemplateBorrowed<'a>> for TemplateOwned {
@@ -98,7 +155,16 @@ impl<'a> From<TemplateBorrowed<'a>> for TemplateOwned {
                 (key.to_owned().into(), value.to_owned().into())
             })
             .collect();
-        Self { name, parameters }
+        let overridden = template
+            .overridden
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        Self {
+            name,
+            parameters,
+            overridden,
+        }
     }
 }
 
@@ -111,200 +177,90 @@ impl<'a> TemplateVisitor<'a> {
         TemplateVisitor { wikitext }
     }
 
+    /// Calls `func` on each template in `nodes`, after any templates
+    /// that are nested inside it.
     pub fn visit<F>(&self, nodes: &'a [Node], func: &mut F)
     where
         F: FnMut(TemplateBorrowed<'a>, &'a Node),
     {
-        for node in nodes {
-            match node {
-                DefinitionList { items, .. } => {
-                    for item in items {
-                        self.visit(&item.nodes, func);
-                    }
-                }
-                Heading { nodes, .. }
-                | Preformatted { nodes, .. }
-                | Tag { nodes, .. } => {
-                    self.visit(&nodes, func);
-                }
-                Image { text, .. } | Link { text, .. } => {
-                    self.visit(&text, func);
-                }
-                OrderedList { items, .. } | UnorderedList { items, .. } => {
-                    for item in items {
-                        self.visit(&item.nodes, func);
-                    }
-                }
-                Parameter { name, default, .. } => {
-                    if let Some(nodes) = default {
-                        self.visit(&nodes, func);
-                    }
-                    self.visit(&name, func);
-                }
-                Table {
-                    attributes,
-                    captions,
-                    rows,
-                    ..
-                } => {
-                    self.visit(&attributes, func);
-                    for caption in captions {
-                        if let Some(attributes) = &caption.attributes {
-                            self.visit(attributes, func)
-                        }
-                        self.visit(&caption.content, func);
-                    }
-                    for row in rows {
-                        self.visit(&row.attributes, func);
-                        for cell in &row.cells {
-                            if let Some(attributes) = &cell.attributes {
-                                self.visit(attributes, func);
-                            }
-                            self.visit(&cell.content, func);
-                        }
-                    }
-                }
-                Template {
-                    name, parameters, ..
-                } => {
-                    self.visit(&name, func);
-                    for parameter in parameters {
-                        if let Some(name) = &parameter.name {
-                            self.visit(name, func);
-                        }
-                        self.visit(&parameter.value, func);
-                    }
-                    let template = TemplateBorrowed::new(
-                        self.wikitext,
-                        &name,
-                        &parameters,
-                    );
-                    func(template, &node);
-                }
-                Bold { .. }
-                | BoldItalic { .. }
-                | Category { .. }
-                | CharacterEntity { .. }
-                | Comment { .. }
-                | EndTag { .. }
-                | ExternalLink { .. }
-                | HorizontalDivider { .. }
-                | Italic { .. }
-                | MagicWord { .. }
-                | ParagraphBreak { .. }
-                | Redirect { .. }
-                | StartTag { .. }
-                | Text { .. } => {}
-            }
-        }
+        self.visit_with_ancestors(nodes, &mut |template, node, _| {
+            func(template, node)
+        });
     }
-}
-
-#[derive(Debug, PartialEq, Eq)]
-#[non_exhaustive]
-pub enum TitleNormalizationError {
-    TooLong,
-    IllegalChar,
-}
 
-impl Error for TitleNormalizationError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        None
+    /// Like `visit`, but also passes the nodes that contain each template,
+    /// outermost first.
+    pub fn visit_with_ancestors<F>(&self, nodes: &'a [Node], func: &mut F)
+    where
+        F: FnMut(TemplateBorrowed<'a>, &'a Node, &[&'a Node<'a>]),
+    {
+        let _ = walk(
+            nodes,
+            &mut Templates {
+                wikitext: self.wikitext,
+                func,
+            },
+        );
     }
 }
 
-impl Display for TitleNormalizationError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            TitleNormalizationError::TooLong => write!(f, "title too long"),
-            TitleNormalizationError::IllegalChar => {
-                write!(f, "title contains illegal character")
-            }
-        }
-    }
+struct Templates<'a, 'b, F> {
+    wikitext: &'a str,
+    func: &'b mut F,
 }
 
-pub const TITLE_MAX: usize = 255;
-
-// This trims whitespace on either end and converts
-// sequences of whitespace characters to a single underscore.
-// In titles, underscores count as whitespace.
-// This is only a subset of the stuff done when resolving a template name.
-// TODO: figure out all the characters that count as whitespace
-// at the beginning and end and when collapsing whitespace sequences,
-// and all the illegal characters. Perhaps this information is in one of the
-// routines called by Title.php?
-// Perhaps also decode HTML character entities?
-pub fn normalize_title(name: &str) -> Result<String, TitleNormalizationError> {
-    fn is_title_whitespace(c: char) -> bool {
-        c.is_ascii_whitespace() || c == '_'
-    }
+impl<'a, 'b, F> Visitor<'a> for Templates<'a, 'b, F>
+where
+    F: FnMut(TemplateBorrowed<'a>, &'a Node, &[&'a Node<'a>]),
+{
+    type Break = Infallible;
 
-    let name = name.trim_matches(is_title_whitespace);
-    let mut normalized_title = String::new();
-    let mut name_iter = name.chars().peekable();
-    while let Some(c) = name_iter.next() {
-        if normalized_title.len() >= TITLE_MAX {
-            return Err(TitleNormalizationError::TooLong);
-        }
-        if ('\u{00}'..'\u{1F}').contains(&c) {
-            return Err(TitleNormalizationError::IllegalChar);
-        }
-        if is_title_whitespace(c) {
-            normalized_title.push('_');
-            while name_iter.peek().map(|c| is_title_whitespace(*c))
-                == Some(true)
-            {
-                let _ = name_iter.next();
-            }
-        } else {
-            normalized_title.push(c);
-        }
-    }
-    if normalized_title.len() > TITLE_MAX {
-        Err(TitleNormalizationError::TooLong)
-    } else {
-        Ok(normalized_title)
+    fn leave_template(
+        &mut self,
+        node: &'a Node<'a>,
+        name: &'a [Node<'a>],
+        parameters: &'a [dump_parser::Parameter<'a>],
+        ancestors: &[&'a Node<'a>],
+    ) -> Result<(), Self::Break> {
+        let template = TemplateBorrowed::new(self.wikitext, name, parameters);
+        (self.func)(template, node, ancestors);
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    #[test]
-    fn test_normalize_title() {
-        use super::{normalize_title, TitleNormalizationError::*, TITLE_MAX};
-        use std::iter;
+    use super::TemplateVisitor;
+    use dump_parser::Configuration;
 
-        fn rep<T: Clone>(c: T, n: usize) -> iter::Take<iter::Repeat<T>> {
-            iter::repeat(c).take(n)
-        }
-
-        for (name, normalized) in &[
-            (
-                rep('_', TITLE_MAX)
-                    .chain(iter::once('l').chain(rep(' ', TITLE_MAX)))
-                    .collect(),
-                Ok("l".to_string()),
-            ),
-            (
-                rep("_", TITLE_MAX)
-                    .chain(iter::once("auto").chain(rep(" ", TITLE_MAX)))
-                    .chain(iter::once("cat").chain(rep(" ", TITLE_MAX)))
-                    .collect(),
-                Ok("auto_cat".to_string()),
-            ),
-            (
-                rep('a', TITLE_MAX).collect(),
-                Ok(rep('a', TITLE_MAX).collect()),
-            ),
-            (
-                rep('a', TITLE_MAX).chain(iter::once(' ')).collect(),
-                Ok(rep('a', TITLE_MAX).collect()),
-            ),
-            (rep('a', TITLE_MAX + 1).collect(), Err(TooLong)),
-            ("\u{0}".to_string(), Err(IllegalChar)),
-        ] {
-            assert_eq!(&normalize_title(name), normalized);
-        }
+    #[test]
+    fn overridden() {
+        let configuration = Configuration::default();
+        let wikitext = "{{t|1=a|b}}{{t|lang=a| lang =b}}{{t|1=a| 1 =b}}\
+            {{t|a|2=b|lang=c}}";
+        let output = configuration.parse(wikitext);
+        let mut templates = Vec::new();
+        TemplateVisitor::new(wikitext).visit(
+            &output.nodes,
+            &mut |template, _| {
+                templates.push((
+                    template.has_duplicates(),
+                    template
+                        .overridden
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), *value))
+                        .collect::<Vec<_>>(),
+                ))
+            },
+        );
+        assert_eq!(
+            templates,
+            vec![
+                (true, vec![("1".to_string(), "a")]),
+                (true, vec![("lang".to_string(), "a")]),
+                (true, vec![("1".to_string(), "a")]),
+                (false, vec![]),
+            ]
+        );
     }
 }
@@ -0,0 +1,427 @@
+// Title normalization as MediaWiki does it in `MediaWikiTitleCodec` and
+// `Sanitizer::decodeCharReferences`, except for Unicode normalization and
+// interwiki prefixes.
+
+use dump_parser::{namespaces::Case, Namespace, NamespaceTable};
+use std::{error::Error, fmt::Display};
+
+pub const TITLE_MAX: usize = 255;
+
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TitleNormalizationError {
+    TooLong,
+    IllegalChar,
+    Empty,
+    /// `.` or `..` as a path segment, which MediaWiki treats as relative.
+    RelativePath,
+    /// Three or more tildes, which are expanded into signatures.
+    MagicTilde,
+}
+
+impl Error for TitleNormalizationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+impl Display for TitleNormalizationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TitleNormalizationError::TooLong => write!(f, "title too long"),
+            TitleNormalizationError::IllegalChar => {
+                write!(f, "title contains illegal character")
+            }
+            TitleNormalizationError::Empty => write!(f, "title is empty"),
+            TitleNormalizationError::RelativePath => {
+                write!(f, "title contains relative path")
+            }
+            TitleNormalizationError::MagicTilde => {
+                write!(f, "title contains three or more tildes")
+            }
+        }
+    }
+}
+
+/// A page title split into namespace and name, where the name has
+/// underscores for spaces and its first letter capitalized if the namespace
+/// requires it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Title {
+    namespace: Namespace,
+    name: String,
+}
+
+impl Title {
+    /// Normalizes `text`, which may have a namespace prefix or, for the main
+    /// namespace, a leading colon, and otherwise is in `default_namespace`,
+    /// as in `{{en-noun}}` for `Template:en-noun`. A fragment after `#` is
+    /// dropped.
+    pub fn new(
+        text: &str,
+        default_namespace: Namespace,
+        namespaces: &NamespaceTable,
+    ) -> Result<Self, TitleNormalizationError> {
+        // The parser trims the names of templates and the targets of links.
+        let text = normalize_whitespace(&decode_entities(text.trim()));
+        let (namespace, name) = split_namespace(&text, namespaces)
+            .unwrap_or((default_namespace, &text));
+        if name.starts_with(':') {
+            return Err(TitleNormalizationError::IllegalChar);
+        }
+        let name = match name.find('#') {
+            Some(pos) => name[..pos].trim_end_matches('_'),
+            None => name,
+        };
+        check_name(name)?;
+        let name = match namespaces.case(namespace) {
+            Case::FirstLetter => uppercase_first(name),
+            Case::CaseSensitive => name.to_string(),
+        };
+        Ok(Title { namespace, name })
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+
+    /// The name without namespace prefix, with underscores for spaces.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn into_name(self) -> String {
+        self.name
+    }
+
+    /// The name without namespace prefix, with spaces.
+    pub fn text(&self) -> String {
+        self.name.replace('_', " ")
+    }
+
+    /// The name with the local name of the namespace as prefix, with
+    /// underscores for spaces.
+    pub fn full_name(&self, namespaces: &NamespaceTable) -> String {
+        match namespaces.name(self.namespace) {
+            Some("") => self.name.clone(),
+            Some(prefix) => {
+                format!("{}:{}", prefix, self.name).replace(' ', "_")
+            }
+            None => format!("{}:{}", i32::from(self.namespace), self.name),
+        }
+    }
+}
+
+// Splits off a leading colon or a namespace prefix. Numbers are not
+// namespace prefixes in titles.
+fn split_namespace<'a>(
+    text: &'a str,
+    namespaces: &NamespaceTable,
+) -> Option<(Namespace, &'a str)> {
+    if let Some(name) = text.strip_prefix(':') {
+        return Some((Namespace::Main, name.trim_start_matches('_')));
+    }
+    let (prefix, name) = text.split_once(':')?;
+    if prefix.trim_matches('_').parse::<i32>().is_ok() {
+        return None;
+    }
+    let namespace = namespaces.lookup(prefix)?;
+    Some((namespace, name.trim_start_matches('_')))
+}
+
+/// Normalizes a name without namespace prefix the way `Title` does, but
+/// without splitting off a namespace or fragment or changing case.
+pub fn normalize_title(name: &str) -> Result<String, TitleNormalizationError> {
+    let name = normalize_whitespace(&decode_entities(name));
+    check_name(&name)?;
+    Ok(name)
+}
+
+// The characters that MediaWiki converts to underscores in titles.
+fn is_title_whitespace(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '_'
+            | '\u{A0}'
+            | '\u{1680}'
+            | '\u{180E}'
+            | '\u{2000}'..='\u{200A}'
+            | '\u{2028}'
+            | '\u{2029}'
+            | '\u{202F}'
+            | '\u{205F}'
+            | '\u{3000}'
+    )
+}
+
+// Directional marks, which MediaWiki removes from titles.
+fn is_directional_mark(c: char) -> bool {
+    matches!(c, '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}')
+}
+
+// Removes directional marks, converts runs of whitespace to a single
+// underscore and trims whitespace from both ends.
+fn normalize_whitespace(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for c in text.chars().filter(|&c| !is_directional_mark(c)) {
+        if is_title_whitespace(c) {
+            if !normalized.is_empty() && !normalized.ends_with('_') {
+                normalized.push('_');
+            }
+        } else {
+            normalized.push(c);
+        }
+    }
+    if normalized.ends_with('_') {
+        normalized.pop();
+    }
+    normalized
+}
+
+fn is_illegal_char(c: char) -> bool {
+    c < ' '
+        || matches!(
+            c,
+            '#' | '<' | '>' | '[' | ']' | '{' | '|' | '}'
+                | '\u{7F}'
+                | '\u{FFFD}'
+        )
+}
+
+fn check_name(name: &str) -> Result<(), TitleNormalizationError> {
+    if name.is_empty() {
+        return Err(TitleNormalizationError::Empty);
+    }
+    let bytes = name.as_bytes();
+    let is_percent_encoded = |i: usize| {
+        bytes[i] == b'%'
+            && bytes.len() > i + 2
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+    };
+    if name.contains(is_illegal_char)
+        || (0..bytes.len()).any(is_percent_encoded)
+        || contains_entity(name)
+    {
+        return Err(TitleNormalizationError::IllegalChar);
+    }
+    if name == "."
+        || name == ".."
+        || name.starts_with("./")
+        || name.starts_with("../")
+        || name.contains("/./")
+        || name.contains("/../")
+        || name.ends_with("/.")
+        || name.ends_with("/..")
+    {
+        return Err(TitleNormalizationError::RelativePath);
+    }
+    if name.contains("~~~") {
+        return Err(TitleNormalizationError::MagicTilde);
+    }
+    if name.len() > TITLE_MAX {
+        return Err(TitleNormalizationError::TooLong);
+    }
+    Ok(())
+}
+
+// Whether the name contains something that looks like a character entity,
+// which is illegal because it would be decoded in links.
+fn contains_entity(name: &str) -> bool {
+    name.match_indices('&').any(|(i, _)| {
+        let rest = &name[i + 1..];
+        let len = rest
+            .find(|c: char| c.is_ascii() && !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        len > 0 && rest[len..].starts_with(';')
+    })
+}
+
+fn uppercase_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// The named character references that are common in titles. MediaWiki
+// knows all of HTML's.
+const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("apos", '\''),
+    ("emsp", '\u{2003}'),
+    ("ensp", '\u{2002}'),
+    ("gt", '>'),
+    ("hellip", '…'),
+    ("laquo", '«'),
+    ("ldquo", '“'),
+    ("lrm", '\u{200E}'),
+    ("lsquo", '‘'),
+    ("lt", '<'),
+    ("mdash", '—'),
+    ("middot", '·'),
+    ("nbsp", '\u{A0}'),
+    ("ndash", '–'),
+    ("quot", '"'),
+    ("raquo", '»'),
+    ("rdquo", '”'),
+    ("rlm", '\u{200F}'),
+    ("rsquo", '’'),
+    ("shy", '\u{AD}'),
+    ("thinsp", '\u{2009}'),
+    ("times", '×'),
+    ("zwj", '\u{200D}'),
+    ("zwnj", '\u{200C}'),
+];
+
+// Decodes `&name;`, `&#number;` and `&#xhex;`. Unknown names are left alone
+// and invalid code points become U+FFFD, which is illegal in titles.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match decode_entity(&rest[1..]) {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[1 + len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Decodes the entity at the start of `text`, which follows `&`, and returns
+// it along with the length of the text up to and including `;`.
+fn decode_entity(text: &str) -> Option<(char, usize)> {
+    let end = text.find(';')?;
+    let entity = &text[..end];
+    let c = if let Some(number) = entity.strip_prefix('#') {
+        let code_point = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+            Some(hex) if !hex.is_empty() && !hex.starts_with('+') => {
+                u32::from_str_radix(hex, 16).ok()?
+            }
+            Some(_) => return None,
+            None if !number.is_empty() && !number.starts_with('+') => {
+                number.parse().ok()?
+            }
+            None => return None,
+        };
+        std::char::from_u32(code_point).unwrap_or('\u{FFFD}')
+    } else {
+        NAMED_ENTITIES
+            .iter()
+            .find(|(name, _)| *name == entity)
+            .map(|&(_, c)| c)?
+    };
+    Some((c, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        normalize_title, Title, TitleNormalizationError::*, TITLE_MAX,
+    };
+    use dump_parser::{namespaces::Case, Namespace, NamespaceTable};
+
+    #[test]
+    fn test_normalize_title() {
+        use std::iter;
+
+        fn rep<T: Clone>(c: T, n: usize) -> iter::Take<iter::Repeat<T>> {
+            iter::repeat(c).take(n)
+        }
+
+        for (name, normalized) in &[
+            (
+                rep('_', TITLE_MAX)
+                    .chain(iter::once('l').chain(rep(' ', TITLE_MAX)))
+                    .collect(),
+                Ok("l".to_string()),
+            ),
+            (
+                rep("_", TITLE_MAX)
+                    .chain(iter::once("auto").chain(rep(" ", TITLE_MAX)))
+                    .chain(iter::once("cat").chain(rep(" ", TITLE_MAX)))
+                    .collect(),
+                Ok("auto_cat".to_string()),
+            ),
+            (
+                rep('a', TITLE_MAX).collect(),
+                Ok(rep('a', TITLE_MAX).collect()),
+            ),
+            (
+                rep('a', TITLE_MAX).chain(iter::once(' ')).collect(),
+                Ok(rep('a', TITLE_MAX).collect()),
+            ),
+            (rep('a', TITLE_MAX + 1).collect(), Err(TooLong)),
+            ("\u{0}".to_string(), Err(IllegalChar)),
+            ("a&nbsp;\u{3000}b\u{200E}".to_string(), Ok("a_b".to_string())),
+            ("".to_string(), Err(Empty)),
+        ] {
+            assert_eq!(&normalize_title(name), normalized);
+        }
+    }
+
+    #[test]
+    fn titles() {
+        let table = NamespaceTable::english_wiktionary();
+        let title = |text| {
+            Title::new(text, Namespace::Template, &table)
+                .map(|t| (t.namespace(), t.into_name()))
+        };
+        let ok = |namespace, name: &str| Ok((namespace, name.to_string()));
+        for (text, expected) in vec![
+            ("en-noun", ok(Namespace::Template, "en-noun")),
+            ("Template:en-noun", ok(Namespace::Template, "en-noun")),
+            (" template : en noun\n", ok(Namespace::Template, "en_noun")),
+            ("T:l", ok(Namespace::Template, "l")),
+            (":Main Page", ok(Namespace::Main, "Main_Page")),
+            ("Wiktionary:Beer parlour", ok(Namespace(4), "Beer_parlour")),
+            ("User:someone", ok(Namespace::User, "Someone")),
+            ("1:a", ok(Namespace::Template, "1:a")),
+            ("Main:a", ok(Namespace::Template, "Main:a")),
+            ("m&amp;a", ok(Namespace::Template, "m&a")),
+            ("a&#32;b&#x5F;c", ok(Namespace::Template, "a_b_c")),
+            ("a&unknown;", Err(IllegalChar)),
+            ("a&#xD800;", Err(IllegalChar)),
+            ("l#section", ok(Namespace::Template, "l")),
+            ("#if:x", Err(Empty)),
+            ("Template:", Err(Empty)),
+            ("a[b", Err(IllegalChar)),
+            ("a%20b", Err(IllegalChar)),
+            ("a\tb", Err(IllegalChar)),
+            ("::a", Err(IllegalChar)),
+            ("../a", Err(RelativePath)),
+            ("a/./b", Err(RelativePath)),
+            ("a/..", Err(RelativePath)),
+            ("...", ok(Namespace::Template, "...")),
+            ("a~~~", Err(MagicTilde)),
+        ] {
+            assert_eq!(title(text), expected, "{:?}", text);
+        }
+
+        let mut table = NamespaceTable::new();
+        table.insert(Namespace::Main, String::new(), None, Case::FirstLetter);
+        table.insert(
+            Namespace::Template,
+            "Template".to_string(),
+            None,
+            Case::FirstLetter,
+        );
+        let title = Title::new("éclair", Namespace::Main, &table).unwrap();
+        assert_eq!(title.name(), "Éclair");
+        let title = Title::new("tl:foo bar", Namespace::Main, &table).unwrap();
+        assert_eq!(title.namespace(), Namespace::Main);
+        assert_eq!(title.full_name(&table), "Tl:foo_bar");
+        let title = Title::new("template:foo bar", Namespace::Main, &table);
+        assert_eq!(title.unwrap().full_name(&table), "Template:Foo_bar");
+    }
+}
@@ -0,0 +1,139 @@
+// Parameter values as lists of text, links and templates, for consumers that
+// shouldn't have to parse wikitext themselves.
+
+use dump_parser::{Node, Parameter, Positioned};
+use parse_wiki_text_ext::template_parameters;
+use serde::Serialize;
+use std::{borrow::Cow, collections::BTreeMap};
+
+use crate::parameter_key;
+
+/// A node in a parsed parameter value. Comments are removed, and nodes other
+/// than links and templates are kept as their wikitext.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ValueNode<'a> {
+    Text {
+        text: Cow<'a, str>,
+    },
+    Link {
+        target: &'a str,
+        text: Vec<ValueNode<'a>>,
+    },
+    Template {
+        name: &'a str,
+        parameters: BTreeMap<Cow<'a, str>, Vec<ValueNode<'a>>>,
+    },
+}
+
+/// Parses the value of a parameter, merging text that is separated by
+/// comments.
+pub fn parse_value<'a>(
+    wikitext: &'a str,
+    nodes: &'a [Node<'a>],
+) -> Vec<ValueNode<'a>> {
+    let mut value = Vec::new();
+    let mut after_comment = false;
+    for node in nodes {
+        match node {
+            Node::Comment { .. } => {
+                after_comment = true;
+                continue;
+            }
+            Node::Link { target, text, .. } => value.push(ValueNode::Link {
+                target,
+                text: parse_value(wikitext, text),
+            }),
+            Node::Template {
+                name, parameters, ..
+            } => value.push(ValueNode::Template {
+                name: name.get_text_from(wikitext).trim(),
+                parameters: parse_parameters(wikitext, parameters),
+            }),
+            _ => {
+                let text = node.get_text_from(wikitext);
+                match value.last_mut() {
+                    // Nodes follow each other without gaps, so text that
+                    // isn't separated by a comment can be borrowed whole.
+                    Some(ValueNode::Text {
+                        text: Cow::Borrowed(previous),
+                    }) if !after_comment => {
+                        let start = node.start() - previous.len();
+                        *previous = &wikitext[start..node.end()];
+                    }
+                    Some(ValueNode::Text { text: previous }) => {
+                        previous.to_mut().push_str(text);
+                    }
+                    _ => value.push(ValueNode::Text {
+                        text: Cow::Borrowed(text),
+                    }),
+                }
+            }
+        }
+        after_comment = false;
+    }
+    value
+}
+
+/// Parses the values of the parameters of a template, keyed as in
+/// `TemplateBorrowed`.
+pub fn parse_parameters<'a>(
+    wikitext: &'a str,
+    parameters: &'a [Parameter<'a>],
+) -> BTreeMap<Cow<'a, str>, Vec<ValueNode<'a>>> {
+    template_parameters::enumerate(parameters)
+        .map(|(key, value)| {
+            (parameter_key(wikitext, key), parse_value(wikitext, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_parameters, ValueNode};
+    use dump_parser::{Configuration, Node};
+    use std::borrow::Cow;
+
+    #[test]
+    fn values() {
+        let configuration = Configuration::default();
+        let wikitext = "{{t|a<!-- b -->c|[[d|e]] f|g={{h|i}}}}";
+        let output = configuration.parse(wikitext);
+        let parameters = match output.nodes.first() {
+            Some(Node::Template { parameters, .. }) => parameters,
+            _ => panic!("expected template"),
+        };
+        let text = |text| ValueNode::Text {
+            text: Cow::Borrowed(text),
+        };
+        let parsed = parse_parameters(wikitext, parameters);
+        assert_eq!(
+            parsed
+                .iter()
+                .map(|(key, value)| (key.as_ref(), value.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("1", vec![text("ac")]),
+                (
+                    "2",
+                    vec![
+                        ValueNode::Link {
+                            target: "d",
+                            text: vec![text("e")],
+                        },
+                        text(" f"),
+                    ]
+                ),
+                (
+                    "g",
+                    vec![ValueNode::Template {
+                        name: "h",
+                        parameters: vec![(Cow::Borrowed("1"), vec![text("i")])]
+                            .into_iter()
+                            .collect(),
+                    }]
+                ),
+            ]
+        );
+    }
+}
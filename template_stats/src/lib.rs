@@ -0,0 +1,291 @@
+// Counts the uses of templates in a dump per namespace: how many instances
+// there are, on how many pages, how often each parameter name is used, and
+// the titles of a few of the pages, which helps with finding unused
+// parameters and misspelled ones like `|lng=` for `|lang=`.
+
+use dump_parser::{
+    parse_pages, print_parser_warnings, Configuration, DumpParser, Error,
+    Namespace, NamespaceTable, Node, Page,
+};
+use serde::{ser::Serializer, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, Read, Write},
+};
+use template_iter::{TemplateVisitor, Title};
+
+/// The number of page titles that are kept for each template by default.
+pub const DEFAULT_SAMPLE_SIZE: usize = 5;
+
+/// The uses of a template in one namespace.
+#[derive(Debug, Default, Serialize)]
+pub struct TemplateStat {
+    pub instances: usize,
+    pub pages: usize,
+    /// Parameter names mapped to the number of instances that use them.
+    pub parameters: BTreeMap<String, usize>,
+    /// The titles of the first pages that use the template.
+    pub samples: Vec<String>,
+}
+
+/// The normalized name of a template and the names of the parameters in
+/// one instance of it.
+pub type TemplateUse = (String, Vec<String>);
+
+#[derive(Debug)]
+pub struct TemplateStats {
+    pub stats: HashMap<(String, Namespace), TemplateStat>,
+    pub sample_size: usize,
+}
+
+impl Default for TemplateStats {
+    fn default() -> Self {
+        TemplateStats::new(DEFAULT_SAMPLE_SIZE)
+    }
+}
+
+impl Serialize for TemplateStats {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            template: &'a str,
+            namespace: i32,
+            #[serde(flatten)]
+            stat: &'a TemplateStat,
+        }
+
+        self.sorted()
+            .into_iter()
+            .map(|((template, namespace), stat)| Entry {
+                template,
+                namespace: i32::from(*namespace),
+                stat,
+            })
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+}
+
+impl TemplateStats {
+    pub fn new(sample_size: usize) -> Self {
+        TemplateStats {
+            stats: HashMap::new(),
+            sample_size,
+        }
+    }
+
+    /// Counts the templates in the pages in `namespaces`, or only those in
+    /// `templates` if it is given.
+    #[allow(clippy::too_many_arguments)]
+    pub fn parse<R: Read + Send>(
+        &mut self,
+        parser: DumpParser<R>,
+        configuration: &Configuration,
+        namespace_table: &NamespaceTable,
+        templates: Option<&HashSet<String>>,
+        page_limit: usize,
+        namespaces: Vec<Namespace>,
+        jobs: usize,
+        verbose: bool,
+    ) -> Result<(), Error> {
+        let namespaces: HashSet<Namespace> = namespaces.into_iter().collect();
+        let parser = parser.take(page_limit).filter(|result| match result {
+            Ok(page) => namespaces.contains(&page.namespace),
+            Err(_) => true,
+        });
+        parse_pages(
+            parser,
+            configuration,
+            jobs,
+            |page, parser_output| {
+                if verbose {
+                    print_parser_warnings(page, &parser_output.warnings);
+                }
+                Self::templates(
+                    page,
+                    &parser_output.nodes,
+                    namespace_table,
+                    templates,
+                )
+            },
+            |page, uses| {
+                self.add_page(&page.title, page.namespace, uses);
+                Ok(())
+            },
+        )
+    }
+
+    /// Returns the templates in `nodes` that are in `filter`, or all of them
+    /// if there is no filter. Transclusions of pages outside the Template
+    /// namespace and invalid names are skipped.
+    pub fn templates(
+        page: &Page,
+        nodes: &[Node],
+        namespace_table: &NamespaceTable,
+        filter: Option<&HashSet<String>>,
+    ) -> Vec<TemplateUse> {
+        let mut uses = Vec::new();
+        TemplateVisitor::new(&page.text).visit(nodes, &mut |template, _| {
+            let name = Title::new(
+                &template.name,
+                Namespace::Template,
+                namespace_table,
+            )
+            .ok()
+            .filter(|title| {
+                title.namespace() == Namespace::Template
+                    && match filter {
+                        Some(filter) => filter.contains(title.name()),
+                        None => true,
+                    }
+            })
+            .map(Title::into_name);
+            if let Some(name) = name {
+                let parameters = template
+                    .parameters
+                    .keys()
+                    .map(|key| key.trim().to_string())
+                    .collect();
+                uses.push((name, parameters));
+            }
+        });
+        uses
+    }
+
+    pub fn add_page(
+        &mut self,
+        title: &str,
+        namespace: Namespace,
+        uses: Vec<TemplateUse>,
+    ) {
+        let mut seen = HashSet::new();
+        for (name, parameters) in uses {
+            let stat =
+                self.stats.entry((name.clone(), namespace)).or_default();
+            stat.instances += 1;
+            for parameter in parameters {
+                *stat.parameters.entry(parameter).or_insert(0) += 1;
+            }
+            if seen.insert(name) {
+                stat.pages += 1;
+                if stat.samples.len() < self.sample_size {
+                    stat.samples.push(title.to_string());
+                }
+            }
+        }
+    }
+
+    pub fn get(
+        &self,
+        template: &str,
+        namespace: Namespace,
+    ) -> Option<&TemplateStat> {
+        self.stats.get(&(template.to_string(), namespace))
+    }
+
+    // The statistics sorted by template name and namespace.
+    fn sorted(&self) -> Vec<(&(String, Namespace), &TemplateStat)> {
+        let mut stats: Vec<_> = self.stats.iter().collect();
+        stats.sort_by_key(|(key, _)| *key);
+        stats
+    }
+
+    /// Writes a row for each template and namespace, with the parameters
+    /// as `name=count` and the samples separated by `|`, which can't occur
+    /// in parameter names or titles.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "template,namespace,instances,pages,parameters,samples"
+        )?;
+        for ((template, namespace), stat) in self.sorted() {
+            let parameters = stat
+                .parameters
+                .iter()
+                .map(|(name, count)| format!("{}={}", name, count))
+                .collect::<Vec<_>>()
+                .join("|");
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                csv_field(template),
+                i32::from(*namespace),
+                stat.instances,
+                stat.pages,
+                csv_field(&parameters),
+                csv_field(&stat.samples.join("|")),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Quotes a CSV field if it contains a comma, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TemplateStats;
+    use dump_parser::Namespace;
+
+    #[test]
+    fn counts() {
+        let mut stats = TemplateStats::new(1);
+        let uses = |names: &[(&str, &[&str])]| {
+            names
+                .iter()
+                .map(|(name, parameters)| {
+                    (
+                        name.to_string(),
+                        parameters.iter().map(|p| p.to_string()).collect(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        stats.add_page(
+            "a",
+            Namespace::Main,
+            uses(&[("l", &["1", "2"]), ("l", &["1", "lang"])]),
+        );
+        stats.add_page("b", Namespace::Main, uses(&[("l", &["1", "lng"])]));
+        stats.add_page(
+            "Reconstruction:b",
+            Namespace::Reconstruction,
+            uses(&[("l", &["1"]), ("m, n", &[])]),
+        );
+
+        let l = stats.get("l", Namespace::Main).unwrap();
+        assert_eq!((l.instances, l.pages), (3, 2));
+        assert_eq!(
+            l.parameters
+                .iter()
+                .map(|(k, v)| (k.as_str(), *v))
+                .collect::<Vec<_>>(),
+            vec![("1", 3), ("2", 1), ("lang", 1), ("lng", 1)]
+        );
+        assert_eq!(l.samples, vec!["a"]);
+        let l = stats.get("l", Namespace::Reconstruction).unwrap();
+        assert_eq!((l.instances, l.pages), (1, 1));
+
+        let mut csv = Vec::new();
+        stats.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            concat!(
+                "template,namespace,instances,pages,parameters,samples\n",
+                "l,0,3,2,1=3|2=1|lang=1|lng=1,a\n",
+                "l,118,1,1,1=1,Reconstruction:b\n",
+                "\"m, n\",118,1,1,,Reconstruction:b\n",
+            )
+        );
+    }
+}
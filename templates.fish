@@ -23,34 +23,47 @@ if not begin; set -q CBOR_DIR; and set -q DUMP_DIR; end
 end
 set -l dump_prefix $DUMP_DIR/$dump_date-
 
-set -l template_redirects_bin template_redirects
+set -l template_names template_names.txt
+set -l template_names_cbor (readlink -f template_names_cbor.txt)
 set -l template_redirects_json template_redirects.json
-if which $template_redirects_bin > /dev/null
-	echo "Generating $template_redirects_json"
-	$template_redirects_bin {$dump_prefix}page.sql {$dump_prefix}redirect.sql > $template_redirects_json
-else if not test -f $template_redirects_json
-	echo "$template_redirects_bin binary (from parse-wiki-text) not found on \$PATH" \
-		"and $template_redirects_json not found"
+set -l redirect_source
+if test -f {$dump_prefix}page.sql -a -f {$dump_prefix}redirect.sql
+	set redirect_source \
+		--page-sql {$dump_prefix}page.sql \
+		--redirect-sql {$dump_prefix}redirect.sql
+else if test -f $template_redirects_json
+	set redirect_source --redirects-json $template_redirects_json
+else
+	echo "{$dump_prefix}page.sql and {$dump_prefix}redirect.sql" \
+		"or $template_redirects_json required"
 	exit 1
 end
 
-set -l template_names template_names.txt
-set -l template_names_cbor (readlink -f template_names_cbor.txt)
-cat $template_names | lua lua/add_template_redirects.lua "%s.cbor" $template_redirects_json \
+echo "Adding template redirects to $template_names_cbor"
+wiktionary-data add-template-redirects \
+	--templates $template_names \
+	--path-format "%s.cbor" \
+	$redirect_source \
 	> $template_names_cbor
+	or exit 1
 
-set -l orig_dir $PWD
 set -l dated_cbor_dir $CBOR_DIR/$dump_date
-if not begin; mkdir -p $dated_cbor_dir; and cd $dated_cbor_dir; end;
+if not mkdir -p $dated_cbor_dir
 	echo "Failed to create output directory"; exit 1
 end
 
-echo "Dumping parsed templates in $dated_cbor_dir"
-wiktionary-data dump-parsed-templates \
-	--input $DUMP_DIR/$dump_date-pages-articles.xml \
-	--templates $template_names_cbor \
-	--namespaces main,reconstruction,appendix \
-	--format cbor \
-	--include-text
+set -l job_file template_jobs.toml
+printf '%s\n' \
+	'[[jobs]]' \
+	'kind = "dump-parsed-templates"' \
+	"output_dir = \"$dated_cbor_dir\"" \
+	"templates = [\"$template_names_cbor\"]" \
+	'namespaces = ["main", "reconstruction", "appendix"]' \
+	'format = "cbor"' \
+	'include_text = true' \
+	> "$job_file"
 
-cd $orig_dir
+echo "Dumping parsed templates in $dated_cbor_dir"
+wiktionary-data run "$job_file" \
+	--input $DUMP_DIR/$dump_date-pages-articles.xml
+	or exit 1